@@ -0,0 +1,421 @@
+//! 消息缓存
+//!
+//! `Reply` 消息段与消息撤回通知仅携带 `message_id`，[`MessageCache`] 作为 EventHandler
+//! 缓存收到的 `message` 事件与经过 [`MessageCache::send_message`] 发送成功的消息，
+//! 用于查询被回复或被撤回的消息内容。
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+use crate::action::{Action, SendMessage};
+use crate::config::MessageCacheConfig;
+use crate::event::{Event, MessageEvent};
+use crate::resp::Resp;
+use crate::segment::{MsgSegmentRef, Segments};
+use crate::structs::{Selft, SendMessageResp};
+use crate::util::{ProtocolItem, Value, ValueMapExt};
+use crate::{ActionHandler, EventHandler, OneBot, WalleError, WalleResult};
+use walle_macro::{_PushToValueMap as PushToValueMap, _TryFromValue as TryFromValue};
+
+/// 缓存的消息
+#[derive(Debug, Clone, PartialEq, PushToValueMap, TryFromValue)]
+pub struct CachedMessage {
+    pub selft: Selft,
+    pub message_id: String,
+    pub time: f64,
+    pub detail_type: String,
+    /// 消息发送者，bot 发送的消息为 bot 自身
+    pub user_id: String,
+    pub group_id: Option<String>,
+    pub guild_id: Option<String>,
+    pub channel_id: Option<String>,
+    pub message: Segments,
+    pub alt_message: String,
+}
+
+#[derive(Default)]
+struct Lru {
+    capacity: usize,
+    tick: u64,
+    entries: HashMap<(Selft, String), (CachedMessage, u64)>,
+    order: BTreeMap<u64, (Selft, String)>,
+}
+
+impl Lru {
+    fn insert(&mut self, message: CachedMessage) {
+        let key = (message.selft.clone(), message.message_id.clone());
+        self.tick += 1;
+        if let Some((_, tick)) = self.entries.insert(key.clone(), (message, self.tick)) {
+            self.order.remove(&tick);
+        }
+        self.order.insert(self.tick, key);
+        while self.entries.len() > self.capacity {
+            let Some((_, key)) = self.order.pop_first() else {
+                break;
+            };
+            self.entries.remove(&key);
+        }
+    }
+    fn get(&mut self, key: (Selft, String)) -> Option<CachedMessage> {
+        let (message, tick) = self.entries.get_mut(&key)?;
+        self.order.remove(tick);
+        self.tick += 1;
+        *tick = self.tick;
+        self.order.insert(self.tick, key);
+        Some(message.clone())
+    }
+    fn resize(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.entries.len() > self.capacity {
+            let Some((_, key)) = self.order.pop_first() else {
+                break;
+            };
+            self.entries.remove(&key);
+        }
+    }
+}
+
+/// LRU 消息缓存，按 `(Selft, message_id)` 查询
+///
+/// 可以通过 `join` 与其他 EventHandler 组合使用，设置 `path` 后启动时将从文件加载，停止时写入文件
+///
+/// bot 发送的消息需要通过 [`send_message`](Self::send_message) 发送，或调用
+/// [`insert_sent`](Self::insert_sent) 手动缓存
+#[derive(Clone)]
+pub struct MessageCache {
+    lru: Arc<Mutex<Lru>>,
+    path: Arc<Mutex<Option<PathBuf>>>,
+}
+
+impl Default for MessageCache {
+    fn default() -> Self {
+        Self::new(MessageCacheConfig::default().capacity)
+    }
+}
+
+impl MessageCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            lru: Arc::new(Mutex::new(Lru {
+                capacity,
+                ..Default::default()
+            })),
+            path: Arc::default(),
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.lru.lock().unwrap().entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    pub fn insert(&self, message: CachedMessage) {
+        self.lru.lock().unwrap().insert(message)
+    }
+    /// 缓存一个 `message` 事件，非 `message` 事件将被忽略
+    pub fn insert_event(&self, event: &Event) {
+        if event.ty != "message" {
+            return;
+        }
+        let get = |key: &str| event.extra.try_get_downcast::<String>(key).ok().flatten();
+        let (group_id, guild_id, channel_id) =
+            (get("group_id"), get("guild_id"), get("channel_id"));
+        match TryInto::<MessageEvent>::try_into(event.clone()) {
+            Ok(e) => self.insert(CachedMessage {
+                selft: e.ty.selft,
+                message_id: e.ty.message_id,
+                time: e.time,
+                detail_type: event.detail_type.clone(),
+                user_id: e.ty.user_id,
+                group_id,
+                guild_id,
+                channel_id,
+                message: e.ty.message,
+                alt_message: e.ty.alt_message,
+            }),
+            Err(e) => warn!(target: crate::WALLE_CORE, "cache message event failed: {}", e),
+        }
+    }
+    /// 缓存 bot 发送的消息
+    pub fn insert_sent(&self, selft: &Selft, action: &SendMessage, resp: &SendMessageResp) {
+        self.insert(CachedMessage {
+            selft: selft.clone(),
+            message_id: resp.message_id.clone(),
+            time: resp.time,
+            detail_type: action.detail_type.clone(),
+            user_id: selft.user_id.clone(),
+            group_id: action.group_id.clone(),
+            guild_id: action.guild_id.clone(),
+            channel_id: action.channel_id.clone(),
+            message: action.message.clone(),
+            alt_message: crate::segment::alt(&action.message),
+        })
+    }
+    pub fn get(&self, selft: &Selft, message_id: &str) -> Option<CachedMessage> {
+        self.lru
+            .lock()
+            .unwrap()
+            .get((selft.clone(), message_id.to_owned()))
+    }
+    /// 查询 `Reply` 消息段所回复的消息，其他消息段返回 None
+    pub fn hydrate_reply(&self, selft: &Selft, segment: &MsgSegmentRef) -> Option<CachedMessage> {
+        match segment {
+            MsgSegmentRef::Reply { message_id, .. } => self.get(selft, message_id),
+            _ => None,
+        }
+    }
+    /// 查询消息中第一个 `Reply` 消息段所回复的消息
+    ///
+    /// `Reply` 消息段的 `user_id` 是可选的，因此这里不经过 `MsgSegmentRef` 转换
+    pub fn replied(&self, selft: &Selft, message: &Segments) -> Option<CachedMessage> {
+        message
+            .iter()
+            .filter(|seg| seg.ty == "reply")
+            .filter_map(|seg| seg.data.get_downcast::<String>("message_id").ok())
+            .find_map(|message_id| self.get(selft, &message_id))
+    }
+    /// 查询 `group_message_delete` 或 `private_message_delete` 通知所撤回的消息
+    pub fn deleted(&self, event: &Event) -> Option<CachedMessage> {
+        match (event.ty.as_str(), event.detail_type.as_str()) {
+            ("notice", "group_message_delete" | "private_message_delete") => {
+                let message_id: String = event.extra.get_downcast("message_id").ok()?;
+                self.get(&event.selft()?, &message_id)
+            }
+            _ => None,
+        }
+    }
+    /// 以 `selft` 发送消息，成功后缓存该消息
+    pub async fn send_message<E, AH, EH>(
+        &self,
+        ob: &Arc<OneBot<AH, EH>>,
+        selft: &Selft,
+        send_message: SendMessage,
+    ) -> WalleResult<SendMessageResp>
+    where
+        AH: ActionHandler<E, Action, Resp> + Send + Sync + 'static,
+        EH: EventHandler<E, Action, Resp> + Send + Sync + 'static,
+    {
+        let mut action: Action = send_message.clone().into();
+        action.selft = Some(selft.clone());
+        let resp: SendMessageResp = ob.handle_action(action).await?.as_result_downcast()?;
+        self.insert_sent(selft, &send_message, &resp);
+        Ok(resp)
+    }
+    /// 从文件加载缓存
+    pub async fn load(&self, path: impl AsRef<Path>) -> WalleResult<()> {
+        let data = tokio::fs::read(path).await?;
+        let value: Value =
+            serde_json::from_slice(&data).map_err(|e| WalleError::Other(e.to_string()))?;
+        let messages: Vec<CachedMessage> = value.downcast()?;
+        let mut lru = self.lru.lock().unwrap();
+        for message in messages {
+            lru.insert(message);
+        }
+        Ok(())
+    }
+    /// 将缓存写入文件，按最近使用顺序保存
+    pub async fn save(&self, path: impl AsRef<Path>) -> WalleResult<()> {
+        let messages = {
+            let lru = self.lru.lock().unwrap();
+            lru.order
+                .values()
+                .filter_map(|key| lru.entries.get(key))
+                .map(|(message, _)| message.clone())
+                .collect::<Vec<_>>()
+        };
+        let data = serde_json::to_vec(&Value::from(messages))
+            .map_err(|e| WalleError::Other(e.to_string()))?;
+        tokio::fs::write(path, data).await?;
+        Ok(())
+    }
+}
+
+impl<E, A, R> EventHandler<E, A, R> for MessageCache
+where
+    E: ProtocolItem + Clone + Into<Event>,
+{
+    type Config = MessageCacheConfig;
+    async fn start<AH, EH>(
+        &self,
+        _ob: &Arc<OneBot<AH, EH>>,
+        config: MessageCacheConfig,
+    ) -> WalleResult<Vec<JoinHandle<()>>>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        self.lru.lock().unwrap().resize(config.capacity);
+        if let Some(path) = config.path.map(PathBuf::from) {
+            if tokio::fs::try_exists(&path).await? {
+                self.load(&path).await?;
+            }
+            *self.path.lock().unwrap() = Some(path);
+        }
+        Ok(vec![])
+    }
+    async fn call<AH, EH>(&self, event: E, _ob: &Arc<OneBot<AH, EH>>) -> WalleResult<()>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        self.insert_event(&event.into());
+        Ok(())
+    }
+    async fn shutdown(&self) {
+        let path = self.path.lock().unwrap().clone();
+        if let Some(path) = path {
+            if let Err(e) = self.save(&path).await {
+                warn!(target: crate::WALLE_CORE, "save message cache failed: {}", e);
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn message_cache_test() {
+    use crate::segment::{IntoMessage, Reply};
+    use crate::value_map;
+
+    let selft = Selft {
+        platform: "test".to_string(),
+        user_id: "bot".to_string(),
+    };
+    let message_event = |id: &str, message: Segments| Event {
+        id: id.to_string(),
+        time: 1.0,
+        ty: "message".to_string(),
+        detail_type: "group".to_string(),
+        sub_type: "".to_string(),
+        extra: value_map! {
+            "self": selft.clone(),
+            "message_id": id,
+            "message": message,
+            "alt_message": "",
+            "user_id": "user",
+            "group_id": "group"
+        },
+    };
+    let cache = MessageCache::new(2);
+    cache.insert_event(&message_event("1", "hello".into_message()));
+    cache.insert_sent(
+        &selft,
+        &SendMessage {
+            detail_type: "group".to_string(),
+            user_id: None,
+            group_id: Some("group".to_string()),
+            guild_id: None,
+            channel_id: None,
+            message: "world".into_message(),
+        },
+        &SendMessageResp {
+            message_id: "2".to_string(),
+            time: 2.0,
+        },
+    );
+    let hello = cache.get(&selft, "1").unwrap();
+    assert_eq!(hello.group_id.as_deref(), Some("group"));
+    assert_eq!(cache.get(&selft, "2").unwrap().user_id, "bot");
+
+    // "1" is most recently used so "2" will be evicted by "3"
+    cache.get(&selft, "1");
+    let reply = vec![
+        Reply {
+            message_id: "1".to_string(),
+            user_id: None,
+        }
+        .into(),
+        crate::segment::MsgSegment {
+            ty: "text".to_string(),
+            data: value_map! { "text": "reply" },
+        },
+    ];
+    cache.insert_event(&message_event("3", reply.clone()));
+    assert_eq!(cache.len(), 2);
+    assert!(cache.get(&selft, "2").is_none());
+    assert_eq!(cache.replied(&selft, &reply), Some(hello.clone()));
+
+    let delete = Event {
+        id: "4".to_string(),
+        time: 4.0,
+        ty: "notice".to_string(),
+        detail_type: "group_message_delete".to_string(),
+        sub_type: "".to_string(),
+        extra: value_map! {
+            "self": selft.clone(),
+            "group_id": "group",
+            "message_id": "1",
+            "user_id": "user",
+            "operator_id": "user"
+        },
+    };
+    assert_eq!(cache.deleted(&delete), Some(hello));
+
+    let path = std::env::temp_dir().join(format!("walle-cache-{}.json", std::process::id()));
+    cache.save(&path).await.unwrap();
+    let loaded = MessageCache::new(2);
+    loaded.load(&path).await.unwrap();
+    assert_eq!(loaded.get(&selft, "3"), cache.get(&selft, "3"));
+    std::fs::remove_file(&path).ok();
+}
+
+#[tokio::test]
+async fn message_cache_send_test() {
+    use crate::segment::IntoMessage;
+    use crate::testing::MockActionHandler;
+
+    let selft = Selft {
+        platform: "test".to_string(),
+        user_id: "bot".to_string(),
+    };
+    let send = |text: &str| SendMessage {
+        detail_type: "private".to_string(),
+        user_id: Some("user".to_string()),
+        group_id: None,
+        guild_id: None,
+        channel_id: None,
+        message: text.into_message(),
+    };
+    let cache = MessageCache::new(8);
+    let ah = MockActionHandler::new().with_handler("send_message", |action| {
+        let send: SendMessage =
+            crate::action::TryFromAction::try_from_action(action.clone()).unwrap();
+        SendMessageResp {
+            message_id: crate::segment::alt(&send.message),
+            time: 1.0,
+        }
+        .into()
+    });
+    let ob = Arc::new(OneBot::new(
+        ah,
+        cache.clone(),
+        crate::structs::Version {
+            implt: "test".to_owned(),
+            version: crate::VERSION.to_owned(),
+            onebot_version: 12.to_string(),
+        },
+    ));
+
+    // concurrent sends in one task are each cached under their own message_id
+    let (hello, world) = tokio::join!(
+        cache.send_message::<Event, _, _>(&ob, &selft, send("hello")),
+        cache.send_message::<Event, _, _>(&ob, &selft, send("world")),
+    );
+    assert_eq!(hello.unwrap().message_id, "hello");
+    assert_eq!(world.unwrap().message_id, "world");
+    assert_eq!(cache.get(&selft, "hello").unwrap().alt_message, "hello");
+    let world = cache.get(&selft, "world").unwrap();
+    assert_eq!(world.alt_message, "world");
+    assert_eq!(world.user_id, "bot");
+
+    // actions handled directly are not cached
+    let mut action: Action = send("direct").into();
+    action.selft = Some(selft.clone());
+    ob.handle_action::<Event, Action, Resp>(action)
+        .await
+        .unwrap();
+    assert!(cache.get(&selft, "direct").is_none());
+}
@@ -0,0 +1,444 @@
+//! 命令解析
+//!
+//! 从消息中解析形如 `/command arg1 "arg two"` 的命令，支持命令前缀、别名、引号参数、
+//! 消息开头提及 bot 的检测，非文本消息段将作为参数保留。
+//!
+//! [`CommandHandler`] 作为 EventHandler 将 `message` 事件分发到注册的命令，
+//! 命令处理函数返回的消息将回复到事件来源。
+//!
+//! ```rust
+//! use walle_core::command::{tokenize, Command};
+//! use walle_core::prelude::*;
+//!
+//! let echo = Command::new("echo").with_alias("say");
+//! let message: Segments = vec![r#"/say hello "walle core""#.into()];
+//! let matched = echo.match_tokens(&tokenize(&message, None)).unwrap();
+//! assert_eq!(matched.name, "echo");
+//! assert_eq!(matched.text_args(), vec!["hello", "walle core"]);
+//! ```
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use tokio::task::JoinHandle;
+
+use crate::action::Action;
+use crate::event::{Event, MessageDetailEvent};
+use crate::segment::{MsgSegment, Segments};
+use crate::structs::Selft;
+use crate::util::{ProtocolItem, ValueMapExt};
+use crate::{ActionHandler, EventHandler, OneBot, WalleResult};
+
+/// 命令参数
+#[derive(Debug, Clone, PartialEq)]
+pub enum Arg {
+    Text(String),
+    /// 非文本消息段
+    Segment(MsgSegment),
+}
+
+impl Arg {
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Arg::Text(text) => Some(text),
+            Arg::Segment(_) => None,
+        }
+    }
+    pub fn as_segment(&self) -> Option<&MsgSegment> {
+        match self {
+            Arg::Text(_) => None,
+            Arg::Segment(segment) => Some(segment),
+        }
+    }
+    /// 将消息段参数转换为具体消息段类型，如 `Image`、`Mention`
+    pub fn downcast<T: TryFrom<MsgSegment>>(&self) -> Option<T> {
+        self.as_segment()
+            .and_then(|segment| T::try_from(segment.clone()).ok())
+    }
+}
+
+/// 消息切分结果
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Tokens {
+    /// 消息是否以提及 bot 开头
+    pub mentioned: bool,
+    pub args: Vec<Arg>,
+}
+
+fn split_text(text: &str, args: &mut Vec<Arg>) {
+    let mut chars = text.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let Some(&first) = chars.peek() else {
+            break;
+        };
+        let mut token = String::new();
+        if first == '"' || first == '\'' {
+            chars.next();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => token.extend(chars.next()),
+                    c if c == first => break,
+                    c => token.push(c),
+                }
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                token.push(c);
+            }
+        }
+        args.push(Arg::Text(token));
+    }
+}
+
+/// 切分消息为参数
+///
+/// 开头的 `reply` 消息段将被忽略，随后提及 `self_id` 的 `mention` 消息段将被移除并标记 `mentioned`
+pub fn tokenize(message: &Segments, self_id: Option<&str>) -> Tokens {
+    let mut segments = message
+        .iter()
+        .skip_while(|seg| seg.ty == "reply")
+        .skip_while(|seg| {
+            seg.ty == "text"
+                && seg
+                    .data
+                    .get_downcast::<String>("text")
+                    .unwrap_or_default()
+                    .trim()
+                    .is_empty()
+        })
+        .peekable();
+    let mentioned = self_id.is_some()
+        && segments
+            .next_if(|seg| {
+                seg.ty == "mention"
+                    && seg.data.get_downcast::<String>("user_id").ok().as_deref() == self_id
+            })
+            .is_some();
+    let mut args = vec![];
+    for segment in segments {
+        if segment.ty == "text" {
+            split_text(
+                &segment
+                    .data
+                    .get_downcast::<String>("text")
+                    .unwrap_or_default(),
+                &mut args,
+            );
+        } else {
+            args.push(Arg::Segment(segment.clone()));
+        }
+    }
+    Tokens { mentioned, args }
+}
+
+/// 命令匹配结果
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matched {
+    /// 命令名称
+    pub name: String,
+    /// 实际匹配的命令名称或别名
+    pub alias: String,
+    pub prefix: String,
+    pub mentioned: bool,
+    /// 命令之后的参数
+    pub args: Vec<Arg>,
+}
+
+impl Matched {
+    /// 所有文本参数
+    pub fn text_args(&self) -> Vec<&str> {
+        self.args.iter().filter_map(Arg::as_text).collect()
+    }
+}
+
+/// 命令定义
+///
+/// 未设置前缀时默认使用 `/`，前缀可以为空字符串
+#[derive(Debug, Clone)]
+pub struct Command {
+    name: String,
+    aliases: Vec<String>,
+    prefixes: Vec<String>,
+    mention_required: bool,
+}
+
+impl Command {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            aliases: vec![],
+            prefixes: vec![],
+            mention_required: false,
+        }
+    }
+    pub fn with_alias(mut self, alias: impl Into<String>) -> Self {
+        self.aliases.push(alias.into());
+        self
+    }
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefixes.push(prefix.into());
+        self
+    }
+    /// 仅在消息以提及 bot 开头时匹配，私聊消息视为已提及
+    pub fn with_mention_required(mut self, required: bool) -> Self {
+        self.mention_required = required;
+        self
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// 匹配已切分的消息
+    pub fn match_tokens(&self, tokens: &Tokens) -> Option<Matched> {
+        if self.mention_required && !tokens.mentioned {
+            return None;
+        }
+        let (first, args) = tokens.args.split_first()?;
+        let first = first.as_text()?;
+        let default = ["/".to_owned()];
+        let prefixes = if self.prefixes.is_empty() {
+            &default[..]
+        } else {
+            &self.prefixes[..]
+        };
+        let names = std::iter::once(&self.name).chain(&self.aliases);
+        prefixes.iter().find_map(|prefix| {
+            let rest = first.strip_prefix(prefix.as_str())?;
+            let alias = names.clone().find(|name| name.as_str() == rest)?;
+            Some(Matched {
+                name: self.name.clone(),
+                alias: alias.clone(),
+                prefix: prefix.clone(),
+                mentioned: tokens.mentioned,
+                args: args.to_vec(),
+            })
+        })
+    }
+    /// 匹配消息，`self_id` 用于检测消息开头是否提及 bot
+    pub fn matches(&self, message: &Segments, self_id: Option<&str>) -> Option<Matched> {
+        self.match_tokens(&tokenize(message, self_id))
+    }
+}
+
+/// 命令处理函数的参数
+#[derive(Debug, Clone)]
+pub struct CommandContext {
+    pub event: Event,
+    pub selft: Selft,
+    pub command: Matched,
+}
+
+type CommandFuture = Pin<Box<dyn Future<Output = WalleResult<Option<Segments>>> + Send>>;
+type CommandFn = Arc<dyn Fn(CommandContext) -> CommandFuture + Send + Sync>;
+
+/// 命令分发 EventHandler
+///
+/// 按注册顺序匹配 `message` 事件，仅执行第一个匹配的命令，
+/// 处理函数返回 `Some` 时将以 `send_message` 回复到事件来源
+#[derive(Clone, Default)]
+pub struct CommandHandler {
+    commands: Vec<(Command, CommandFn)>,
+}
+
+impl CommandHandler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn on<F, Fut>(mut self, command: Command, handler: F) -> Self
+    where
+        F: Fn(CommandContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = WalleResult<Option<Segments>>> + Send + 'static,
+    {
+        self.commands
+            .push((command, Arc::new(move |cx| Box::pin(handler(cx)))));
+        self
+    }
+    /// 匹配 `message` 事件，返回第一个匹配的命令与其处理函数的参数
+    fn dispatch(&self, event: &Event) -> Option<(CommandContext, &CommandFn)> {
+        if event.ty != "message" {
+            return None;
+        }
+        let selft = event.selft()?;
+        let message: Segments = event.extra.get_downcast("message").ok()?;
+        let mut tokens = tokenize(&message, Some(&selft.user_id));
+        tokens.mentioned |= event.detail_type == "private";
+        self.commands.iter().find_map(|(command, handler)| {
+            let command = command.match_tokens(&tokens)?;
+            Some((
+                CommandContext {
+                    event: event.clone(),
+                    selft: selft.clone(),
+                    command,
+                },
+                handler,
+            ))
+        })
+    }
+}
+
+impl<E, A, R> EventHandler<E, A, R> for CommandHandler
+where
+    E: ProtocolItem + Clone + Into<Event>,
+    A: ProtocolItem + From<Action>,
+    R: ProtocolItem,
+{
+    type Config = ();
+    async fn start<AH, EH>(
+        &self,
+        _ob: &Arc<OneBot<AH, EH>>,
+        _config: (),
+    ) -> WalleResult<Vec<JoinHandle<()>>>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        Ok(vec![])
+    }
+    async fn call<AH, EH>(&self, event: E, ob: &Arc<OneBot<AH, EH>>) -> WalleResult<()>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        let event: Event = event.into();
+        let Some((cx, handler)) = self.dispatch(&event) else {
+            return Ok(());
+        };
+        let selft = cx.selft.clone();
+        if let Some(message) = handler(cx).await? {
+            let event: MessageDetailEvent = event.try_into()?;
+            let mut action: Action = event.reply_action(message).into();
+            action.selft = Some(selft);
+            ob.handle_action(action.into()).await?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn command_test() {
+    use crate::segment::{Image, Mention, Reply};
+    use crate::value_map;
+
+    let mention = |user_id: &str| -> MsgSegment {
+        Mention {
+            user_id: user_id.to_owned(),
+        }
+        .into()
+    };
+    let image: MsgSegment = Image {
+        file_id: "f".to_owned(),
+    }
+    .into();
+    let message: Segments = vec![
+        Reply {
+            message_id: "1".to_owned(),
+            user_id: None,
+        }
+        .into(),
+        mention("bot"),
+        r#" !Ban  'a b' "c \"d\"" "#.into(),
+        image.clone(),
+        "e".into(),
+    ];
+    let tokens = tokenize(&message, Some("bot"));
+    assert!(tokens.mentioned);
+    assert_eq!(
+        tokens.args,
+        vec![
+            Arg::Text("!Ban".to_owned()),
+            Arg::Text("a b".to_owned()),
+            Arg::Text("c \"d\"".to_owned()),
+            Arg::Segment(image.clone()),
+            Arg::Text("e".to_owned()),
+        ]
+    );
+    assert!(!tokenize(&message, Some("other")).mentioned);
+
+    let ban = Command::new("ban")
+        .with_alias("Ban")
+        .with_prefix("!")
+        .with_mention_required(true);
+    let matched = ban.matches(&message, Some("bot")).unwrap();
+    assert_eq!(
+        (matched.name.as_str(), matched.alias.as_str()),
+        ("ban", "Ban")
+    );
+    assert_eq!(matched.text_args(), vec!["a b", "c \"d\"", "e"]);
+    assert_eq!(matched.args[2].downcast::<Image>().unwrap().file_id, "f");
+    assert!(ban.matches(&message, None).is_none());
+    assert!(Command::new("ban").matches(&message, Some("bot")).is_none());
+
+    let handler = CommandHandler::new()
+        .on(Command::new("ping"), |_| async {
+            Ok(Some(vec!["pong".into()]))
+        })
+        .on(ban, |_| async { Ok(None) });
+    let event = Event {
+        id: "1".to_owned(),
+        time: 0.0,
+        ty: "message".to_owned(),
+        detail_type: "private".to_owned(),
+        sub_type: "".to_owned(),
+        extra: value_map! {
+            "self": Selft { platform: "test".to_owned(), user_id: "bot".to_owned() },
+            "message": vec![MsgSegment::from("/ping")],
+            "user_id": "user"
+        },
+    };
+    let (cx, _) = handler.dispatch(&event).unwrap();
+    assert_eq!(cx.command.name, "ping");
+    assert!(cx.command.mentioned);
+}
+
+#[tokio::test]
+async fn command_handler_test() {
+    use crate::segment::IntoMessage;
+    use crate::structs::SendMessageResp;
+    use crate::testing::{MockActionHandler, MockEvents};
+
+    let selft = Selft {
+        platform: "test".to_string(),
+        user_id: "bot".to_string(),
+    };
+    let ah = MockActionHandler::new().with_resp(
+        "send_message",
+        SendMessageResp {
+            message_id: "reply".to_string(),
+            time: 1.0,
+        },
+    );
+    let eh = CommandHandler::new().on(Command::new("echo"), |cx| async move {
+        Ok(Some(cx.command.text_args().join(" ").into_message()))
+    });
+    let ob = Arc::new(OneBot::new(
+        ah.clone(),
+        eh,
+        crate::structs::Version {
+            implt: "test".to_owned(),
+            version: crate::VERSION.to_owned(),
+            onebot_version: 12.to_string(),
+        },
+    ));
+    let events = MockEvents::new(selft.clone());
+    events
+        .inject(&ob, |e| {
+            e.group_message("group", "alice", "/echo hello walle")
+        })
+        .await
+        .unwrap();
+    events
+        .inject(&ob, |e| e.group_message("group", "alice", "not a command"))
+        .await
+        .unwrap();
+
+    let sent = ah.called("send_message");
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].selft.as_ref(), Some(&selft));
+    let send: crate::action::SendMessage =
+        crate::action::TryFromAction::try_from_action(sent[0].clone()).unwrap();
+    assert_eq!(send.detail_type, "group");
+    assert_eq!(send.group_id.as_deref(), Some("group"));
+    assert_eq!(send.user_id, None);
+    assert_eq!(crate::segment::alt(&send.message), "hello walle");
+}
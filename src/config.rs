@@ -4,6 +4,9 @@ use std::{collections::HashMap, fmt::Debug};
 
 use serde::{Deserialize, Serialize};
 
+mod loader;
+pub use loader::{ConfigFormat, LoadConfig};
+
 /// OneBot 实现端设置项
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct ImplConfig {
@@ -11,24 +14,35 @@ pub struct ImplConfig {
     pub http_webhook: Vec<HttpClient>,
     pub websocket: Vec<WebSocketServer>,
     pub websocket_rev: Vec<WebSocketClient>,
+    /// 进程内内存传输监听
+    #[serde(default)]
+    pub memory: Vec<MemoryServer>,
     pub heartbeat: Heartbeat,
+    /// 每个 WebSocket 连接的 resp 队列设置，为 None 则使用默认设置
+    pub resp_queue: Option<QueueConfig>,
+    /// 事件广播容量，连接接收落后超过该容量时将跳过最旧的事件，为 None 则使用 ImplOBC 创建时的容量
+    #[serde(default)]
+    pub event_capacity: Option<usize>,
 }
 
 impl Default for ImplConfig {
     fn default() -> Self {
         Self {
             heartbeat: Heartbeat::default(),
+            resp_queue: None,
+            event_capacity: None,
             http: vec![],
             http_webhook: vec![],
             websocket: vec![],
             websocket_rev: vec![WebSocketClient::default()],
+            memory: vec![],
         }
     }
 }
 
 /// OneBot 心跳设置
 ///
-/// 间隔为 0 则默认为 4
+/// 间隔单位为秒，为 0 则默认为 4，心跳事件中的 `interval` 以毫秒发送
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Heartbeat {
     pub enabled: bool,
@@ -48,20 +62,37 @@ impl Default for Heartbeat {
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct AppConfig {
     pub block_meta_event: Option<bool>,
+    /// 心跳超时倍数，连接超过 `interval` 的该倍数未收到心跳即视为断开
+    ///
+    /// 为 None 或 0 则不检测，未设置时为 3
+    #[serde(default = "default_heartbeat_timeout_multiple")]
+    pub heartbeat_timeout_multiple: Option<u32>,
+    /// 每个连接的 action 队列设置，为 None 则使用默认设置
+    pub action_queue: Option<QueueConfig>,
     pub http_webhook: Vec<HttpServer>,
     pub websocket: Vec<WebSocketClient>,
     pub websocket_rev: Vec<WebSocketServer>,
     pub http: HashMap<String, HttpClient>,
+    /// 进程内内存传输连接
+    #[serde(default)]
+    pub memory: Vec<MemoryClient>,
+}
+
+fn default_heartbeat_timeout_multiple() -> Option<u32> {
+    Some(3)
 }
 
 impl Default for AppConfig {
     fn default() -> Self {
         Self {
             block_meta_event: Some(true),
+            heartbeat_timeout_multiple: default_heartbeat_timeout_multiple(),
+            action_queue: None,
             http: HashMap::default(),
             http_webhook: vec![],
             websocket: vec![],
             websocket_rev: vec![WebSocketServer::default()],
+            memory: vec![],
         }
     }
 }
@@ -70,10 +101,46 @@ impl AppConfig {
     pub fn empty() -> Self {
         Self {
             block_meta_event: Some(true),
+            heartbeat_timeout_multiple: default_heartbeat_timeout_multiple(),
+            action_queue: None,
             http: HashMap::default(),
             http_webhook: vec![],
             websocket: vec![],
             websocket_rev: vec![],
+            memory: vec![],
+        }
+    }
+}
+
+/// 队列溢出策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// 丢弃队列中最旧的元素
+    DropOldest,
+    /// 等待队列出现空位
+    #[default]
+    Block,
+    /// 断开对应连接
+    Disconnect,
+}
+
+/// OBC 队列设置
+///
+/// 未填写的字段使用默认值
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct QueueConfig {
+    /// 队列容量，至少为 1
+    pub capacity: usize,
+    pub overflow: OverflowPolicy,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 1024,
+            overflow: OverflowPolicy::Block,
         }
     }
 }
@@ -83,8 +150,16 @@ impl AppConfig {
 pub struct HttpServer {
     pub host: std::net::IpAddr,
     pub port: u16,
+    /// 请求路径，为 None 时为 `/`；同一进程内的多个服务端可以监听相同地址，按路径分发请求
     pub path: Option<String>,
     pub access_token: Option<String>,
+    /// 文件服务设置，仅实现端 HTTP 服务设置 FileStore 后生效
+    pub files: Option<FileServer>,
+    /// SSE 事件流设置，仅实现端 HTTP 服务生效
+    pub sse: Option<SseServer>,
+    /// 设置后监听 Unix socket 而不是 `host:port`
+    #[serde(default)]
+    pub unix: Option<UnixSocket>,
     // #[cfg(feature = "impl-obc")]
     // pub event_enable: bool,
     // #[cfg(feature = "impl-obc")]
@@ -98,6 +173,9 @@ impl Default for HttpServer {
             port: 6700,
             path: None,
             access_token: None,
+            files: None,
+            sse: None,
+            unix: None,
             // #[cfg(feature = "impl-obc")]
             // event_enable: true,
             // #[cfg(feature = "impl-obc")]
@@ -112,6 +190,7 @@ pub struct HttpClient {
     #[serde(rename = "impl")]
     pub implt: Option<String>,
     pub platform: Option<String>,
+    /// 推送地址，`unix:{socket 路径}:{请求路径}` 地址通过 Unix socket 推送
     pub url: String,
     pub access_token: Option<String>,
     pub timeout: u64,
@@ -134,8 +213,16 @@ impl Default for HttpClient {
 pub struct WebSocketServer {
     pub host: std::net::IpAddr,
     pub port: u16,
+    /// 请求路径，为 None 时为 `/`；同一进程内的多个服务端可以监听相同地址，按路径分发请求
     pub path: Option<String>,
     pub access_token: Option<String>,
+    pub keepalive: Option<KeepAlive>,
+    /// 连接使用的 OneBot 协议版本，仅应用端支持 OneBot 11
+    #[serde(default)]
+    pub onebot_version: OneBotVersion,
+    /// 设置后监听 Unix socket 而不是 `host:port`
+    #[serde(default)]
+    pub unix: Option<UnixSocket>,
 }
 
 impl Default for WebSocketServer {
@@ -145,6 +232,64 @@ impl Default for WebSocketServer {
             port: 8844,
             path: None,
             access_token: None,
+            keepalive: None,
+            onebot_version: OneBotVersion::default(),
+            unix: None,
+        }
+    }
+}
+
+/// Unix domain socket 监听设置
+///
+/// 监听前移除无进程监听的残留 socket 文件，`mode` 不为 None 时设置 socket 文件权限，
+/// 可配合文件属主与属组限制可以连接的用户，仅 unix 平台可用
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct UnixSocket {
+    pub path: String,
+    /// socket 文件权限，如 `0o660`
+    pub mode: Option<u32>,
+}
+
+/// 实现端 HTTP 文件服务设置
+///
+/// 通过 GET `{path}/{file_id}` 提供文件下载，`get_file` 返回的 url 在 `expire` 秒后过期，
+/// `public_url` 为 None 时使用 `http://{host}:{port}{path}`，监听 Unix socket 或
+/// `0.0.0.0` 等未指定地址时必须设置
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FileServer {
+    pub path: String,
+    pub expire: u32,
+    pub public_url: Option<String>,
+}
+
+impl Default for FileServer {
+    fn default() -> Self {
+        Self {
+            path: "/files".to_owned(),
+            expire: 3600,
+            public_url: None,
+        }
+    }
+}
+
+/// 实现端 HTTP SSE 事件流设置
+///
+/// 通过 GET `path` 以 `text/event-stream` 推送事件，`Event.id` 作为 SSE id，
+/// 客户端携带 `Last-Event-ID` 重连时续传最近 `buffer` 个事件中该 id 之后的事件
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SseServer {
+    pub path: String,
+    pub buffer: usize,
+    /// 保活注释发送间隔（秒），为 0 时不发送
+    pub keepalive: u32,
+}
+
+impl Default for SseServer {
+    fn default() -> Self {
+        Self {
+            path: "/events".to_owned(),
+            buffer: 64,
+            keepalive: 15,
         }
     }
 }
@@ -152,9 +297,16 @@ impl Default for WebSocketServer {
 /// OneBot Impl 反向 WebSocket 通讯设置
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct WebSocketClient {
+    /// 连接地址，`unix:{socket 路径}:{请求路径}` 地址通过 Unix socket 连接
     pub url: String,
     pub access_token: Option<String>,
     pub reconnect_interval: u32,
+    pub keepalive: Option<KeepAlive>,
+    /// 断线期间的事件重放缓冲，仅实现端反向 WebSocket 生效
+    pub replay: Option<ReplayConfig>,
+    /// 连接使用的 OneBot 协议版本，仅应用端支持 OneBot 11
+    #[serde(default)]
+    pub onebot_version: OneBotVersion,
 }
 
 impl Default for WebSocketClient {
@@ -163,6 +315,90 @@ impl Default for WebSocketClient {
             url: "ws://127.0.0.1:8844".to_owned(),
             access_token: None,
             reconnect_interval: 4,
+            keepalive: None,
+            replay: None,
+            onebot_version: OneBotVersion::default(),
+        }
+    }
+}
+
+/// 实现端进程内内存传输监听设置
+///
+/// 同一进程内同一 `name` 同时只能存在一个监听
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MemoryServer {
+    pub name: String,
+}
+
+impl Default for MemoryServer {
+    fn default() -> Self {
+        Self {
+            name: "walle".to_owned(),
+        }
+    }
+}
+
+/// 应用端进程内内存传输连接设置
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MemoryClient {
+    pub name: String,
+    pub reconnect_interval: u32,
+}
+
+impl Default for MemoryClient {
+    fn default() -> Self {
+        Self {
+            name: "walle".to_owned(),
+            reconnect_interval: 1,
+        }
+    }
+}
+
+/// OneBot 协议版本
+///
+/// OneBot 11 需要启用 `v11` feature，连接 go-cqhttp 等 OneBot 11 实现端时使用，
+/// 仅应用端 WebSocket 与反向 WebSocket 支持 OneBot 11
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum OneBotVersion {
+    #[serde(rename = "11")]
+    V11,
+    #[default]
+    #[serde(rename = "12")]
+    V12,
+}
+
+/// 事件重放缓冲设置
+///
+/// 最多缓存 `capacity` 个事件，超过 `max_age` 秒的事件将被丢弃
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ReplayConfig {
+    pub capacity: usize,
+    pub max_age: u32,
+}
+
+impl Default for ReplayConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 1024,
+            max_age: 60,
+        }
+    }
+}
+
+/// WebSocket ping/pong 保活设置
+///
+/// 每隔 `interval` 秒发送一次 ping，`timeout` 秒内未收到 pong 则关闭连接
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct KeepAlive {
+    pub interval: u32,
+    pub timeout: u32,
+}
+
+impl Default for KeepAlive {
+    fn default() -> Self {
+        Self {
+            interval: 30,
+            timeout: 10,
         }
     }
 }
@@ -172,3 +408,55 @@ fn toml_test() {
     let config = AppConfig::default();
     println!("{:?}", toml::to_string(&config));
 }
+
+#[test]
+fn app_config_default_test() {
+    let config: AppConfig = serde_json::from_value(serde_json::json!({
+        "block_meta_event": true,
+        "http_webhook": [],
+        "websocket": [],
+        "websocket_rev": [],
+        "http": {}
+    }))
+    .unwrap();
+    assert_eq!(
+        config.heartbeat_timeout_multiple,
+        AppConfig::default().heartbeat_timeout_multiple
+    );
+}
+
+/// 事件存储设置
+///
+/// `retention` 为事件保留时长，单位为秒，为 None 则永久保留
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct EventStoreConfig {
+    pub path: String,
+    pub retention: Option<u32>,
+}
+
+impl Default for EventStoreConfig {
+    fn default() -> Self {
+        Self {
+            path: "events.log".to_owned(),
+            retention: None,
+        }
+    }
+}
+
+/// 消息缓存设置
+///
+/// 设置 `path` 后启动时将从文件加载缓存，停止时写入文件
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MessageCacheConfig {
+    pub capacity: usize,
+    pub path: Option<String>,
+}
+
+impl Default for MessageCacheConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 1024,
+            path: None,
+        }
+    }
+}
@@ -0,0 +1,759 @@
+//! 配置文件加载与校验
+//!
+//! 以默认配置为基础合并配置文件，再应用环境变量覆盖，反序列化后统一校验。
+//!
+//! 环境变量以 `{ENV_PREFIX}__` 开头，其后的字段以 `__` 分隔，数组使用下标，
+//! 如 `WALLE_IMPL__WEBSOCKET__0__PORT=8845`；字段名不区分大小写，
+//! 值按原字段类型解析，数组与表可以直接填写 JSON；默认为空的字段先按 JSON 解析，
+//! 失败时作为字符串，需要字符串形式的数字时可填写 `"123"`。
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use super::{
+    AppConfig, HttpClient, HttpServer, ImplConfig, OneBotVersion, WebSocketClient, WebSocketServer,
+};
+use crate::{WalleError, WalleResult};
+
+/// 配置文件格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    /// 需要启用 `toml` feature
+    Toml,
+    Json,
+    /// 需要启用 `yaml` feature
+    Yaml,
+}
+
+impl ConfigFormat {
+    /// 根据文件扩展名判断格式
+    pub fn from_path(path: &Path) -> WalleResult<Self> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Ok(Self::Toml),
+            Some("json") => Ok(Self::Json),
+            Some("yaml" | "yml") => Ok(Self::Yaml),
+            _ => Err(config_error(format!(
+                "{}: unknown config format, expect .toml, .json or .yaml",
+                path.display()
+            ))),
+        }
+    }
+
+    fn parse(self, content: &str) -> WalleResult<Value> {
+        match self {
+            #[cfg(feature = "toml")]
+            Self::Toml => toml::from_str(content).map_err(|e| config_error(e.to_string())),
+            #[cfg(not(feature = "toml"))]
+            Self::Toml => Err(config_error(
+                "toml config requires `toml` feature".to_owned(),
+            )),
+            Self::Json => serde_json::from_str(content).map_err(|e| config_error(e.to_string())),
+            #[cfg(feature = "yaml")]
+            Self::Yaml => serde_yaml::from_str(content).map_err(|e| config_error(e.to_string())),
+            #[cfg(not(feature = "yaml"))]
+            Self::Yaml => Err(config_error(
+                "yaml config requires `yaml` feature".to_owned(),
+            )),
+        }
+    }
+}
+
+/// 可从文件与环境变量加载的配置
+pub trait LoadConfig: Default + Serialize + DeserializeOwned {
+    /// 环境变量覆盖前缀
+    const ENV_PREFIX: &'static str;
+
+    /// 校验配置，一次返回所有问题
+    fn validate(&self) -> WalleResult<()>;
+
+    /// 带注释的 TOML 默认配置模板
+    fn template() -> &'static str;
+
+    /// 从配置文件加载并应用环境变量覆盖，`path` 为 None 时仅使用默认配置
+    fn load(path: Option<&Path>) -> WalleResult<Self> {
+        let file = match path {
+            Some(path) => Some((
+                std::fs::read_to_string(path)
+                    .map_err(|e| config_error(format!("{}: {}", path.display(), e)))?,
+                ConfigFormat::from_path(path)?,
+            )),
+            None => None,
+        };
+        Self::load_with(
+            file.as_ref().map(|(s, f)| (s.as_str(), *f)),
+            std::env::vars(),
+        )
+    }
+
+    /// 从配置内容与给定的环境变量加载
+    fn load_with(
+        file: Option<(&str, ConfigFormat)>,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> WalleResult<Self> {
+        let mut value =
+            serde_json::to_value(Self::default()).map_err(|e| config_error(e.to_string()))?;
+        if let Some((content, format)) = file {
+            merge(&mut value, format.parse(content)?);
+        }
+        let prefix = format!("{}__", Self::ENV_PREFIX);
+        for (key, raw) in vars {
+            if let Some(path) = key.strip_prefix(&prefix) {
+                override_env(&mut value, &key, path, raw)?;
+            }
+        }
+        let config: Self =
+            serde_json::from_value(value).map_err(|e| config_error(e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// 每隔 `interval` 检查配置文件，内容变化且加载成功时发送新配置，可用于 `OneBot::reconfigure`
+    ///
+    /// 加载失败时仅记录警告，Receiver 被丢弃后停止检查
+    fn watch(path: PathBuf, interval: Duration) -> mpsc::Receiver<Self>
+    where
+        Self: Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel(1);
+        tokio::spawn(async move {
+            let mut last = std::fs::read(&path).ok();
+            loop {
+                tokio::select! {
+                    _ = tx.closed() => break,
+                    _ = tokio::time::sleep(interval) => {}
+                }
+                let content = std::fs::read(&path).ok();
+                if content.is_none() || content == last {
+                    continue;
+                }
+                last = content;
+                match Self::load(Some(&path)) {
+                    Ok(config) => {
+                        info!(target: crate::WALLE_CORE, "config {} reloaded", path.display());
+                        if tx.send(config).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => warn!(target: crate::WALLE_CORE, "reload config failed: {}", e),
+                }
+            }
+        });
+        rx
+    }
+}
+
+impl LoadConfig for ImplConfig {
+    const ENV_PREFIX: &'static str = "WALLE_IMPL";
+
+    fn validate(&self) -> WalleResult<()> {
+        let mut v = Validator::default();
+        for (i, s) in self.http.iter().enumerate() {
+            let field = format!("http[{}]", i);
+            v.http_server(&field, s);
+            if let Some(files) = &s.files {
+                v.path(&format!("{}.files.path", field), &files.path);
+                v.positive(&format!("{}.files.expire", field), files.expire as u64);
+                // 默认的 `http://{host}:{port}` 对于 Unix socket 与未指定地址不可访问
+                if files.public_url.is_none() && (s.unix.is_some() || s.host.is_unspecified()) {
+                    v.error(
+                        &format!("{}.files.public_url", field),
+                        "must be set when listening on a unix socket or unspecified address",
+                    );
+                }
+            }
+            if let Some(sse) = &s.sse {
+                v.path(&format!("{}.sse.path", field), &sse.path);
+                v.listener(
+                    &format!("{}.sse.path", field),
+                    s.unix.as_ref().map(|u| u.path.as_str()),
+                    &s.host,
+                    s.port,
+                    &sse.path,
+                );
+            }
+        }
+        for (i, c) in self.http_webhook.iter().enumerate() {
+            v.http_client(&format!("http_webhook[{}]", i), c);
+        }
+        for (i, s) in self.websocket.iter().enumerate() {
+            let field = format!("websocket[{}]", i);
+            v.ws_server(&field, s);
+            v.onebot_version(&field, s.onebot_version, false);
+        }
+        for (i, c) in self.websocket_rev.iter().enumerate() {
+            let field = format!("websocket_rev[{}]", i);
+            v.ws_client(&field, c);
+            v.onebot_version(&field, c.onebot_version, false);
+            if let Some(replay) = &c.replay {
+                v.positive(
+                    &format!("{}.replay.capacity", field),
+                    replay.capacity as u64,
+                );
+                v.positive(&format!("{}.replay.max_age", field), replay.max_age as u64);
+            }
+        }
+        let mut names = HashSet::new();
+        for (i, m) in self.memory.iter().enumerate() {
+            v.name(&format!("memory[{}].name", i), &m.name, &mut names);
+        }
+        if self.heartbeat.enabled {
+            v.positive("heartbeat.interval", self.heartbeat.interval as u64);
+        }
+        if let Some(queue) = &self.resp_queue {
+            v.positive("resp_queue.capacity", queue.capacity as u64);
+        }
+        if let Some(capacity) = self.event_capacity {
+            v.positive("event_capacity", capacity as u64);
+        }
+        v.finish()
+    }
+
+    fn template() -> &'static str {
+        IMPL_TEMPLATE
+    }
+}
+
+impl LoadConfig for AppConfig {
+    const ENV_PREFIX: &'static str = "WALLE_APP";
+
+    fn validate(&self) -> WalleResult<()> {
+        let mut v = Validator::default();
+        for (i, s) in self.http_webhook.iter().enumerate() {
+            v.http_server(&format!("http_webhook[{}]", i), s);
+        }
+        for (i, c) in self.websocket.iter().enumerate() {
+            let field = format!("websocket[{}]", i);
+            v.ws_client(&field, c);
+            v.onebot_version(&field, c.onebot_version, true);
+        }
+        for (i, s) in self.websocket_rev.iter().enumerate() {
+            let field = format!("websocket_rev[{}]", i);
+            v.ws_server(&field, s);
+            v.onebot_version(&field, s.onebot_version, true);
+        }
+        let mut bots: Vec<_> = self.http.iter().collect();
+        bots.sort_by(|a, b| a.0.cmp(b.0));
+        for (bot_id, c) in bots {
+            if bot_id.is_empty() {
+                v.error("http", "bot id must not be empty");
+            }
+            v.http_client(&format!("http.{}", bot_id), c);
+        }
+        let mut names = HashSet::new();
+        for (i, m) in self.memory.iter().enumerate() {
+            let field = format!("memory[{}]", i);
+            v.name(&format!("{}.name", field), &m.name, &mut names);
+            v.positive(
+                &format!("{}.reconnect_interval", field),
+                m.reconnect_interval as u64,
+            );
+        }
+        if let Some(queue) = &self.action_queue {
+            v.positive("action_queue.capacity", queue.capacity as u64);
+        }
+        v.finish()
+    }
+
+    fn template() -> &'static str {
+        APP_TEMPLATE
+    }
+}
+
+fn config_error(msg: String) -> WalleError {
+    WalleError::Config(msg)
+}
+
+/// 表递归合并，数组与值直接替换
+fn merge(base: &mut Value, over: Value) {
+    match (base, over) {
+        (Value::Object(base), Value::Object(over)) => {
+            for (k, v) in over {
+                merge(base.entry(k).or_insert(Value::Null), v);
+            }
+        }
+        (base, over) => *base = over,
+    }
+}
+
+fn override_env(root: &mut Value, key: &str, path: &str, raw: String) -> WalleResult<()> {
+    let mut cur = root;
+    for seg in path.split("__") {
+        if seg.is_empty() {
+            return Err(config_error(format!("{}: empty field name", key)));
+        }
+        if cur.is_null() {
+            *cur = Value::Object(Map::new());
+        }
+        cur = match cur {
+            Value::Array(array) => {
+                let len = array.len();
+                let index: usize = seg.parse().map_err(|_| {
+                    config_error(format!("{}: expect array index, got `{}`", key, seg))
+                })?;
+                array.get_mut(index).ok_or_else(|| {
+                    config_error(format!(
+                        "{}: index {} out of range, array length is {}",
+                        key, index, len
+                    ))
+                })?
+            }
+            Value::Object(map) => {
+                let field = map
+                    .keys()
+                    .find(|k| k.eq_ignore_ascii_case(seg))
+                    .cloned()
+                    .unwrap_or_else(|| seg.to_ascii_lowercase());
+                map.entry(field).or_insert(Value::Null)
+            }
+            _ => return Err(config_error(format!("{}: `{}` is not a table", key, seg))),
+        };
+    }
+    *cur = match cur {
+        Value::Number(_) => serde_json::from_str::<serde_json::Number>(&raw)
+            .map(Value::Number)
+            .map_err(|_| config_error(format!("{}: expect number, got `{}`", key, raw)))?,
+        Value::Bool(_) => raw
+            .parse()
+            .map(Value::Bool)
+            .map_err(|_| config_error(format!("{}: expect bool, got `{}`", key, raw)))?,
+        Value::Array(_) | Value::Object(_) => serde_json::from_str(&raw)
+            .map_err(|e| config_error(format!("{}: invalid json: {}", key, e)))?,
+        Value::Null => serde_json::from_str(&raw).unwrap_or(Value::String(raw)),
+        Value::String(_) => Value::String(raw),
+    };
+    Ok(())
+}
+
+/// 收集校验错误，错误以 `字段: 原因` 格式给出
+#[derive(Default)]
+struct Validator {
+    errors: Vec<String>,
+    listeners: HashSet<String>,
+    urls: HashSet<String>,
+}
+
+impl Validator {
+    fn error(&mut self, field: &str, reason: impl std::fmt::Display) {
+        self.errors.push(format!("{}: {}", field, reason));
+    }
+
+    fn finish(self) -> WalleResult<()> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(config_error(self.errors.join("; ")))
+        }
+    }
+
+    fn positive(&mut self, field: &str, value: u64) {
+        if value == 0 {
+            self.error(field, "must be greater than 0");
+        }
+    }
+
+    fn path(&mut self, field: &str, path: &str) {
+        if !path.starts_with('/') {
+            self.error(field, format!("`{}` must start with `/`", path));
+        }
+    }
+
+    fn name(&mut self, field: &str, name: &str, names: &mut HashSet<String>) {
+        if name.is_empty() {
+            self.error(field, "must not be empty");
+        } else if !names.insert(name.to_owned()) {
+            self.error(field, format!("duplicate name `{}`", name));
+        }
+    }
+
+    fn listener(
+        &mut self,
+        field: &str,
+        unix: Option<&str>,
+        host: &std::net::IpAddr,
+        port: u16,
+        path: &str,
+    ) {
+        let addr = match unix {
+            Some(unix) => format!("unix:{}", unix),
+            None if port == 0 => return,
+            None => format!("{}:{}", host, port),
+        };
+        if !self.listeners.insert(format!("{}{}", addr, path)) {
+            self.error(field, format!("duplicate listener `{}{}`", addr, path));
+        }
+    }
+
+    fn server(
+        &mut self,
+        field: &str,
+        host: &std::net::IpAddr,
+        port: u16,
+        path: &Option<String>,
+        unix: Option<&str>,
+    ) {
+        match unix {
+            Some("") => self.error(&format!("{}.unix.path", field), "must not be empty"),
+            Some(_) => {}
+            None if port == 0 => self.error(&format!("{}.port", field), "must not be 0"),
+            None => {}
+        }
+        if let Some(path) = path {
+            self.path(&format!("{}.path", field), path);
+        }
+        let path = path.as_deref().unwrap_or("/");
+        self.listener(field, unix, host, port, path);
+    }
+
+    fn http_server(&mut self, field: &str, s: &HttpServer) {
+        let unix = s.unix.as_ref().map(|u| u.path.as_str());
+        self.server(field, &s.host, s.port, &s.path, unix);
+    }
+
+    fn ws_server(&mut self, field: &str, s: &WebSocketServer) {
+        let unix = s.unix.as_ref().map(|u| u.path.as_str());
+        self.server(field, &s.host, s.port, &s.path, unix);
+        if let Some(keepalive) = &s.keepalive {
+            self.positive(
+                &format!("{}.keepalive.interval", field),
+                keepalive.interval as u64,
+            );
+            self.positive(
+                &format!("{}.keepalive.timeout", field),
+                keepalive.timeout as u64,
+            );
+        }
+    }
+
+    /// 实现端仅支持 OneBot 12，应用端使用 OneBot 11 需要启用 `v11` feature
+    fn onebot_version(&mut self, field: &str, version: OneBotVersion, app: bool) {
+        if version != OneBotVersion::V11 {
+            return;
+        }
+        let field = format!("{}.onebot_version", field);
+        if !app {
+            self.error(&field, "OneBot 11 is only supported on app side");
+        } else if cfg!(not(feature = "v11")) {
+            self.error(&field, "OneBot 11 requires `v11` feature");
+        }
+    }
+
+    fn url(&mut self, field: &str, url: &str, scheme: &str, require_port: bool) {
+        let field = format!("{}.url", field);
+        if let Some(rest) = url.strip_prefix("unix:") {
+            if rest.is_empty() || rest.starts_with(':') {
+                self.error(&field, format!("`{}` missing socket path", url));
+            }
+        } else if let Some(rest) = url.strip_prefix(scheme) {
+            let authority = rest.split('/').next().unwrap_or_default();
+            let (host, port) = match authority.rsplit_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            };
+            if host.is_empty() {
+                self.error(&field, format!("`{}` missing host", url));
+            } else if let Some(port) = port {
+                if !matches!(port.parse::<u16>(), Ok(p) if p != 0) {
+                    self.error(&field, format!("`{}` has invalid port `{}`", url, port));
+                }
+            } else if require_port {
+                self.error(&field, format!("`{}` missing port", url));
+            }
+        } else {
+            self.error(
+                &field,
+                format!("`{}` must start with `{}` or `unix:`", url, scheme),
+            );
+        }
+        if !self.urls.insert(url.to_owned()) {
+            self.error(&field, format!("duplicate url `{}`", url));
+        }
+    }
+
+    fn http_client(&mut self, field: &str, c: &HttpClient) {
+        self.url(field, &c.url, "http://", false);
+        self.positive(&format!("{}.timeout", field), c.timeout);
+    }
+
+    fn ws_client(&mut self, field: &str, c: &WebSocketClient) {
+        self.url(field, &c.url, "ws://", true);
+        self.positive(
+            &format!("{}.reconnect_interval", field),
+            c.reconnect_interval as u64,
+        );
+        if let Some(keepalive) = &c.keepalive {
+            self.positive(
+                &format!("{}.keepalive.interval", field),
+                keepalive.interval as u64,
+            );
+            self.positive(
+                &format!("{}.keepalive.timeout", field),
+                keepalive.timeout as u64,
+            );
+        }
+    }
+}
+
+const IMPL_TEMPLATE: &str = r#"# OneBot 实现端配置
+#
+# 省略的字段使用默认值，数组中的每一项需要填写完整的必填字段
+# 可以使用 `WALLE_IMPL__` 开头的环境变量覆盖，如 `WALLE_IMPL__WEBSOCKET_REV__0__URL`
+
+# HTTP 服务端
+http = []
+# [[http]]
+# host = "127.0.0.1"
+# port = 6700
+# path = "/"                                                # 可选，请求路径
+# access_token = "token"                                    # 可选
+# files = { path = "/files", expire = 3600 }                # 可选，文件服务
+# sse = { path = "/events", buffer = 64, keepalive = 15 }   # 可选，SSE 事件流
+# unix = { path = "/run/walle.sock", mode = 0o660 }         # 可选，改为监听 Unix socket
+
+# HTTP Webhook
+http_webhook = []
+# [[http_webhook]]
+# url = "http://127.0.0.1:8080"     # 或 `unix:{socket 路径}:{请求路径}`
+# access_token = "token"            # 可选
+# timeout = 4                       # 超时（秒）
+
+# 正向 WebSocket 服务端
+websocket = []
+# [[websocket]]
+# host = "127.0.0.1"
+# port = 8844
+# path = "/"                                   # 可选
+# access_token = "token"                       # 可选
+# keepalive = { interval = 30, timeout = 10 }  # 可选，ping/pong 保活
+# unix = { path = "/run/walle.sock" }          # 可选
+
+# 进程内内存传输
+memory = []
+# [[memory]]
+# name = "walle"
+
+# 事件广播容量，连接接收落后超过该容量时跳过最旧的事件，可选
+# event_capacity = 1024
+
+# 每个连接的 resp 队列，可选
+# [resp_queue]
+# capacity = 1024
+# overflow = "block"    # drop_oldest / block / disconnect
+
+[heartbeat]
+enabled = true
+interval = 4
+
+# 反向 WebSocket 客户端
+[[websocket_rev]]
+url = "ws://127.0.0.1:8844"
+reconnect_interval = 4
+# access_token = "token"
+# keepalive = { interval = 30, timeout = 10 }
+# replay = { capacity = 1024, max_age = 60 }   # 断线期间的事件重放
+"#;
+
+const APP_TEMPLATE: &str = r#"# OneBot 应用端配置
+#
+# 省略的字段使用默认值，数组中的每一项需要填写完整的必填字段
+# 可以使用 `WALLE_APP__` 开头的环境变量覆盖，如 `WALLE_APP__WEBSOCKET_REV__0__PORT`
+
+block_meta_event = true
+# 心跳超时倍数，为 0 则不检测
+heartbeat_timeout_multiple = 3
+
+# HTTP Webhook 服务端
+http_webhook = []
+# [[http_webhook]]
+# host = "127.0.0.1"
+# port = 6700
+# access_token = "token"
+
+# 正向 WebSocket 客户端
+websocket = []
+# [[websocket]]
+# url = "ws://127.0.0.1:8844"       # 或 `unix:{socket 路径}:{请求路径}`
+# reconnect_interval = 4
+# access_token = "token"
+# onebot_version = "12"             # 11 需要启用 `v11` feature
+
+# 进程内内存传输
+memory = []
+# [[memory]]
+# name = "walle"
+# reconnect_interval = 1
+
+# 每个连接的 action 队列，可选
+# [action_queue]
+# capacity = 1024
+# overflow = "block"
+
+# HTTP 客户端，以 bot id 为键
+[http]
+# [http.bot_id]
+# impl = "walle"
+# platform = "qq"
+# url = "http://127.0.0.1:6700"
+# timeout = 4
+
+# 反向 WebSocket 服务端
+[[websocket_rev]]
+host = "127.0.0.1"
+port = 8844
+# path = "/"
+# access_token = "token"
+# keepalive = { interval = 30, timeout = 10 }
+# onebot_version = "12"
+"#;
+
+#[test]
+fn loader_test() {
+    let vars = |v: &[(&str, &str)]| {
+        v.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<Vec<_>>()
+    };
+    let json =
+        r#"{"websocket": [{"host": "127.0.0.1", "port": 8845}], "heartbeat": {"interval": 10}}"#;
+    let config = ImplConfig::load_with(
+        Some((json, ConfigFormat::Json)),
+        vars(&[
+            ("WALLE_IMPL__WEBSOCKET__0__PORT", "8846"),
+            ("WALLE_IMPL__websocket__0__ACCESS_TOKEN", "token"),
+            ("WALLE_IMPL__HEARTBEAT__ENABLED", "false"),
+            ("WALLE_APP__HEARTBEAT__ENABLED", "oops"),
+        ]),
+    )
+    .unwrap();
+    assert_eq!(config.websocket[0].port, 8846);
+    assert_eq!(config.websocket[0].access_token.as_deref(), Some("token"));
+    assert_eq!(config.heartbeat.interval, 10);
+    assert!(!config.heartbeat.enabled);
+    assert_eq!(config.websocket_rev.len(), 1);
+
+    let err = |r: WalleResult<ImplConfig>| match r {
+        Err(WalleError::Config(e)) => e,
+        r => panic!("unexpected {:?}", r),
+    };
+    let e = err(ImplConfig::load_with(
+        None,
+        vars(&[("WALLE_IMPL__WEBSOCKET_REV__1__URL", "ws://a:1")]),
+    ));
+    assert!(e.contains("index 1 out of range"), "{}", e);
+    let e = err(ImplConfig::load_with(
+        None,
+        vars(&[("WALLE_IMPL__HEARTBEAT__INTERVAL", "often")]),
+    ));
+    assert!(e.contains("expect number"), "{}", e);
+
+    // 默认为 None 的字段按 JSON 解析，失败时作为字符串
+    let config = ImplConfig::load_with(
+        None,
+        vars(&[
+            ("WALLE_IMPL__EVENT_CAPACITY", "10"),
+            ("WALLE_IMPL__RESP_QUEUE__CAPACITY", "16"),
+            ("WALLE_IMPL__WEBSOCKET_REV__0__ACCESS_TOKEN", "\"123\""),
+        ]),
+    )
+    .unwrap();
+    assert_eq!(config.event_capacity, Some(10));
+    let resp_queue = config.resp_queue.unwrap();
+    assert_eq!(resp_queue.capacity, 16);
+    assert_eq!(resp_queue.overflow, super::OverflowPolicy::Block);
+    assert_eq!(config.websocket_rev[0].access_token.as_deref(), Some("123"));
+
+    let json = r#"{
+        "websocket": [
+            {"host": "127.0.0.1", "port": 0},
+            {"host": "127.0.0.1", "port": 8844, "path": "ws"},
+            {"host": "127.0.0.1", "port": 8844, "path": "ws"}
+        ],
+        "websocket_rev": [
+            {"url": "http://127.0.0.1:8844", "reconnect_interval": 0},
+            {"url": "ws://127.0.0.1", "reconnect_interval": 4, "onebot_version": "11"}
+        ],
+        "http": [{"host": "0.0.0.0", "port": 8080, "files": {"path": "/files", "expire": 60}}],
+        "http_webhook": [{"url": "http://127.0.0.1:8080", "timeout": 0}],
+        "memory": [{"name": "walle"}, {"name": "walle"}]
+    }"#;
+    let e = err(ImplConfig::load_with(
+        Some((json, ConfigFormat::Json)),
+        vec![],
+    ));
+    for expect in [
+        "websocket[0].port: must not be 0",
+        "websocket[1].path: `ws` must start with `/`",
+        "websocket[2]: duplicate listener `127.0.0.1:8844ws`",
+        "websocket_rev[0].url: `http://127.0.0.1:8844` must start with `ws://`",
+        "websocket_rev[0].reconnect_interval: must be greater than 0",
+        "websocket_rev[1].url: `ws://127.0.0.1` missing port",
+        "websocket_rev[1].onebot_version: OneBot 11 is only supported on app side",
+        "http[0].files.public_url: must be set when listening on a unix socket or unspecified address",
+        "http_webhook[0].timeout: must be greater than 0",
+        "memory[1].name: duplicate name `walle`",
+    ] {
+        assert!(e.contains(expect), "missing `{}` in {}", expect, e);
+    }
+    let json = r#"{"websocket": [{"url": "ws://127.0.0.1:8844", "reconnect_interval": 4, "onebot_version": "11"}]}"#;
+    let app = AppConfig::load_with(Some((json, ConfigFormat::Json)), vec![]);
+    #[cfg(feature = "v11")]
+    assert!(app.is_ok());
+    #[cfg(not(feature = "v11"))]
+    assert!(matches!(app, Err(WalleError::Config(e))
+        if e.contains("websocket[0].onebot_version: OneBot 11 requires `v11` feature")));
+    assert_eq!(
+        ConfigFormat::from_path(Path::new("walle.yml")).unwrap(),
+        ConfigFormat::Yaml
+    );
+    assert!(ConfigFormat::from_path(Path::new("walle.ini")).is_err());
+    let yaml = "heartbeat:\n  interval: 7\nmemory:\n  - name: walle\n";
+    #[cfg(feature = "yaml")]
+    {
+        let config = ImplConfig::load_with(Some((yaml, ConfigFormat::Yaml)), vec![]).unwrap();
+        assert_eq!(config.heartbeat.interval, 7);
+        assert_eq!(config.memory[0].name, "walle");
+    }
+    #[cfg(not(feature = "yaml"))]
+    assert!(ImplConfig::load_with(Some((yaml, ConfigFormat::Yaml)), vec![]).is_err());
+
+    #[cfg(feature = "toml")]
+    {
+        let config =
+            ImplConfig::load_with(Some((ImplConfig::template(), ConfigFormat::Toml)), vec![])
+                .unwrap();
+        assert_eq!(
+            serde_json::to_value(config).unwrap(),
+            serde_json::to_value(ImplConfig::default()).unwrap()
+        );
+        let config =
+            AppConfig::load_with(Some((AppConfig::template(), ConfigFormat::Toml)), vec![])
+                .unwrap();
+        assert_eq!(
+            serde_json::to_value(config).unwrap(),
+            serde_json::to_value(AppConfig::default()).unwrap()
+        );
+    }
+}
+
+#[tokio::test]
+async fn watch_test() {
+    let path = std::env::temp_dir().join(format!("walle_watch_{}.json", std::process::id()));
+    std::fs::write(&path, r#"{"heartbeat": {"interval": 5}}"#).unwrap();
+    let mut rx = ImplConfig::watch(path.clone(), Duration::from_millis(10));
+    tokio::time::sleep(Duration::from_millis(30)).await;
+    // 校验失败的配置被忽略
+    std::fs::write(&path, r#"{"heartbeat": {"interval": 0}}"#).unwrap();
+    tokio::time::sleep(Duration::from_millis(30)).await;
+    std::fs::write(&path, r#"{"heartbeat": {"interval": 6}}"#).unwrap();
+    let config = tokio::time::timeout(Duration::from_secs(1), rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(config.heartbeat.interval, 6);
+    std::fs::remove_file(&path).ok();
+}
@@ -4,6 +4,7 @@ use std::sync::Arc;
 use crate::action::Action;
 use crate::error::WalleResult;
 use crate::event::Event;
+use crate::lifecycle::{RestartPolicy, TaskStatus};
 use crate::resp::Resp;
 use crate::ActionHandler;
 use crate::OneBot;
@@ -19,7 +20,7 @@ pub trait EventHandler<E = Event, A = Action, R = Resp>: Sync {
         &self,
         ob: &Arc<OneBot<AH, EH>>,
         config: Self::Config,
-    ) -> impl Future<Output = WalleResult<Vec<tokio::task::JoinHandle<()>>>>
+    ) -> impl Future<Output = WalleResult<Vec<tokio::task::JoinHandle<()>>>> + Send
     where
         AH: ActionHandler<E, A, R> + Send + Sync + 'static,
         EH: EventHandler<E, A, R> + Send + Sync + 'static;
@@ -56,6 +57,40 @@ pub trait EventHandler<E = Event, A = Action, R = Resp>: Sync {
     {
         async { Ok(resp) }
     }
+    /// 校验配置，`OneBot::reconfigure` 在应用任何一方的配置之前调用
+    fn validate_config(&self, _config: &Self::Config) -> WalleResult<()> {
+        Ok(())
+    }
+    /// 运行期间以新配置重新配置，仅启停发生变化的部分，需要先调用 `start`
+    ///
+    /// 默认忽略新配置
+    fn reconfigure<AH, EH>(
+        &self,
+        _ob: &Arc<OneBot<AH, EH>>,
+        _config: Self::Config,
+    ) -> impl Future<Output = WalleResult<()>> + Send
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        async { Ok(()) }
+    }
+    /// 运行组件的状态
+    fn task_status(&self) -> Vec<TaskStatus> {
+        vec![]
+    }
+    /// 按重启策略重启失败的组件，由 OneBot 定期调用
+    fn restart_failed<AH, EH>(
+        &self,
+        _ob: &Arc<OneBot<AH, EH>>,
+        _policy: &RestartPolicy,
+    ) -> impl Future<Output = ()> + Send
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        async {}
+    }
     fn shutdown(&self) -> impl Future<Output = ()> {
         async {}
     }
@@ -113,9 +148,38 @@ where
         EH: EventHandler<E, A, R> + Send + Sync + 'static,
     {
         let mut joins = self.0.start(ob, config.0).await?;
-        joins.extend(self.1.start(ob, config.1).await?.into_iter());
+        joins.extend(self.1.start(ob, config.1).await?);
         Ok(joins)
     }
+    async fn reconfigure<AH, EH>(
+        &self,
+        ob: &Arc<OneBot<AH, EH>>,
+        config: Self::Config,
+    ) -> WalleResult<()>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        self.0.reconfigure(ob, config.0).await?;
+        self.1.reconfigure(ob, config.1).await
+    }
+    fn validate_config(&self, config: &Self::Config) -> WalleResult<()> {
+        self.0.validate_config(&config.0)?;
+        self.1.validate_config(&config.1)
+    }
+    fn task_status(&self) -> Vec<TaskStatus> {
+        let mut status = self.0.task_status();
+        status.extend(self.1.task_status());
+        status
+    }
+    async fn restart_failed<AH, EH>(&self, ob: &Arc<OneBot<AH, EH>>, policy: &RestartPolicy)
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        self.0.restart_failed(ob, policy).await;
+        self.1.restart_failed(ob, policy).await
+    }
     async fn call<AH, EH>(&self, event: E, ob: &Arc<OneBot<AH, EH>>) -> WalleResult<()>
     where
         AH: ActionHandler<E, A, R> + Send + Sync + 'static,
@@ -28,6 +28,12 @@ pub enum WalleError {
     AlreadyStarted,
     #[error("OneBot is not started")]
     NotStarted,
+    /// 任务 panic，附带任务名与 panic 信息
+    #[error("Task {0} panicked: {1}")]
+    TaskPanicked(String, String),
+    /// 停止超时，附带被中止的任务数
+    #[error("Shutdown timeout, {0} tasks aborted")]
+    ShutdownTimeout(usize),
 
     // Extended
     #[error("ExtendedMap missed key: {0}")]
@@ -41,6 +47,19 @@ pub enum WalleError {
     // OBC
     #[error("Bot not exist")]
     BotNotExist,
+    #[error("Queue is full")]
+    QueueFull,
+    #[error("Queue is closed")]
+    QueueClosed,
+
+    // Session
+    #[error("Session already exists")]
+    SessionExists,
+
+    // Config
+    /// 配置文件解析或校验失败
+    #[error("Config error: {0}")]
+    Config(String),
 
     #[error("{0}")]
     Other(String),
@@ -3,13 +3,18 @@
 //!
 //! - `Event`：标准的 Event 模型，确保事件最基本的字段，所有其他字段可以后续再继续处理，可以序列化和反序列化。
 //! - `BaseEvent<T, D, S, P, I>`：根据 Rust 类型系统设计的可扩展模型，使用五个层级的泛型分别持有五个层级的扩展字段，
-//! 可以尝试从 `Event` 转化，或转化到 `Event`，不可直接序列化和反序列化，可以用于更好的在实现端构建事件以及在应用端处理事件。
+//!   可以尝试从 `Event` 转化，或转化到 `Event`，不可直接序列化和反序列化，可以用于更好的在实现端构建事件以及在应用端处理事件。
 
 use crate::{
+    action::{Action, SendMessage},
     prelude::{WalleError, WalleResult},
-    structs::Selft,
+    resp::Resp,
+    segment::{IntoMessage, Mention, Reply, Segments},
+    structs::{Selft, SendMessageResp},
     util::{GetSelf, PushToValueMap, Value, ValueMap, ValueMapExt},
+    ActionHandler, EventHandler, OneBot,
 };
+use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
 
@@ -145,6 +150,62 @@ impl GetSelf for Event {
     }
 }
 
+/// 约束具有 `id`、`type` 与 `detail_type` 字段
+///
+/// OBC 依赖该 trait 识别 meta 事件与事件去重，自定义 Event 类型可以通过 `EventKind` derive 宏实现，
+/// 要求结构体具有 `ty` 与 `detail_type` 字段，`id` 字段可选，没有时使用默认的 `id` 实现：
+///
+/// ```rust
+/// use walle_core::prelude::*;
+///
+/// #[derive(Debug, Clone, EventKind)]
+/// pub struct MyEvent {
+///     pub id: String,
+///     pub ty: String,
+///     pub detail_type: String,
+/// }
+/// ```
+pub trait EventKind {
+    /// 返回 `id` 字段
+    ///
+    /// 默认返回空字符串，此时 ImplOBC 重放不会对该事件去重，SSE 也不会为其设置 `id`
+    fn id(&self) -> &str {
+        ""
+    }
+    /// 返回 `type` 字段
+    fn ty(&self) -> &str;
+    /// 返回 `detail_type` 字段
+    fn detail_type(&self) -> &str;
+}
+
+impl EventKind for Event {
+    fn id(&self) -> &str {
+        &self.id
+    }
+    fn ty(&self) -> &str {
+        &self.ty
+    }
+    fn detail_type(&self) -> &str {
+        &self.detail_type
+    }
+}
+
+impl<T, D, S, P, I> EventKind for BaseEvent<T, D, S, P, I>
+where
+    T: ToEvent<TypeLevel>,
+    D: ToEvent<DetailTypeLevel>,
+{
+    fn id(&self) -> &str {
+        &self.id
+    }
+    fn ty(&self) -> &str {
+        self.ty.ty()
+    }
+    fn detail_type(&self) -> &str {
+        self.detail_type.ty()
+    }
+}
+
 /// 泛型可扩展 Event 模型
 ///
 /// 用于在实现端构建事件以及在应用端处理事件，可以尝试从 `Event` 转化，或转化到 `Event`，不可直接序列化和反序列化
@@ -430,6 +491,143 @@ pub enum MessageDetailTypes {
 pub type MessageDetailEvent<S = (), P = (), I = ()> =
     BaseEvent<Message, MessageDetailTypes, S, P, I>;
 
+/// 可回复的 `message` 事件 `detail_type` 层级
+///
+/// 用于从消息事件构造发送到消息来源的 `SendMessage`
+pub trait MessageTarget {
+    /// 构造发送到该消息来源的 `SendMessage`，`user_id` 为消息发送者
+    fn send_message(&self, user_id: &str, message: Segments) -> SendMessage;
+    /// 是否为私聊
+    fn is_private(&self) -> bool {
+        false
+    }
+}
+
+impl MessageTarget for Private {
+    fn send_message(&self, user_id: &str, message: Segments) -> SendMessage {
+        SendMessage {
+            detail_type: "private".to_owned(),
+            user_id: Some(user_id.to_owned()),
+            group_id: None,
+            guild_id: None,
+            channel_id: None,
+            message,
+        }
+    }
+    fn is_private(&self) -> bool {
+        true
+    }
+}
+
+impl MessageTarget for Group {
+    fn send_message(&self, _: &str, message: Segments) -> SendMessage {
+        SendMessage {
+            detail_type: "group".to_owned(),
+            user_id: None,
+            group_id: Some(self.group_id.clone()),
+            guild_id: None,
+            channel_id: None,
+            message,
+        }
+    }
+}
+
+impl MessageTarget for Channel {
+    fn send_message(&self, _: &str, message: Segments) -> SendMessage {
+        SendMessage {
+            detail_type: "channel".to_owned(),
+            user_id: None,
+            group_id: None,
+            guild_id: Some(self.guild_id.clone()),
+            channel_id: Some(self.channel_id.clone()),
+            message,
+        }
+    }
+}
+
+impl MessageTarget for MessageDetailTypes {
+    fn send_message(&self, user_id: &str, message: Segments) -> SendMessage {
+        match self {
+            Self::Group(group) => group.send_message(user_id, message),
+            Self::Private(private) => private.send_message(user_id, message),
+            Self::Channel(channel) => channel.send_message(user_id, message),
+        }
+    }
+    fn is_private(&self) -> bool {
+        matches!(self, Self::Private(_))
+    }
+}
+
+impl<D, S, P, I> BaseEvent<Message, D, S, P, I>
+where
+    D: MessageTarget,
+{
+    /// 构造回复该消息的 `SendMessage`
+    pub fn reply_action(&self, message: impl IntoMessage) -> SendMessage {
+        self.detail_type
+            .send_message(&self.ty.user_id, message.into_message())
+    }
+    /// 构造引用该消息的 `SendMessage`
+    ///
+    /// 消息前添加 `reply` 消息段，`mention` 为 true 且不为私聊时同时提及消息发送者
+    pub fn reply_quote_action(&self, message: impl IntoMessage, mention: bool) -> SendMessage {
+        let mut segments: Segments = vec![Reply {
+            message_id: self.ty.message_id.clone(),
+            user_id: Some(self.ty.user_id.clone()),
+        }
+        .into()];
+        if mention && !self.detail_type.is_private() {
+            segments.push(
+                Mention {
+                    user_id: self.ty.user_id.clone(),
+                }
+                .into(),
+            );
+        }
+        segments.extend(message.into_message());
+        self.detail_type.send_message(&self.ty.user_id, segments)
+    }
+    /// 以事件的 `self` 回复该消息
+    pub async fn reply<E, AH, EH>(
+        &self,
+        ob: &Arc<OneBot<AH, EH>>,
+        message: impl IntoMessage,
+    ) -> WalleResult<SendMessageResp>
+    where
+        AH: ActionHandler<E, Action, Resp> + Send + Sync + 'static,
+        EH: EventHandler<E, Action, Resp> + Send + Sync + 'static,
+    {
+        self.send(ob, self.reply_action(message)).await
+    }
+    /// 以事件的 `self` 引用回复该消息，参见 [`reply_quote_action`](Self::reply_quote_action)
+    pub async fn reply_quote<E, AH, EH>(
+        &self,
+        ob: &Arc<OneBot<AH, EH>>,
+        message: impl IntoMessage,
+        mention: bool,
+    ) -> WalleResult<SendMessageResp>
+    where
+        AH: ActionHandler<E, Action, Resp> + Send + Sync + 'static,
+        EH: EventHandler<E, Action, Resp> + Send + Sync + 'static,
+    {
+        self.send(ob, self.reply_quote_action(message, mention))
+            .await
+    }
+    async fn send<E, AH, EH>(
+        &self,
+        ob: &Arc<OneBot<AH, EH>>,
+        send_message: SendMessage,
+    ) -> WalleResult<SendMessageResp>
+    where
+        AH: ActionHandler<E, Action, Resp> + Send + Sync + 'static,
+        EH: EventHandler<E, Action, Resp> + Send + Sync + 'static,
+    {
+        let mut action: Action = send_message.into();
+        action.selft = Some(self.ty.selft.clone());
+        ob.handle_action(action).await?.as_result_downcast()
+    }
+}
+
 /// OneBot 12 标准事件 `detail_type` 层级 `connect` 字段结构体
 ///
 /// 用于 `meta.connect`
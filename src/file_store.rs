@@ -0,0 +1,938 @@
+//! 实现端文件存储
+//!
+//! [`FileStore`] 实现 `upload_file`、`get_file`、`upload_file_fragmented` 与
+//! `get_file_fragmented` 动作的文件管理，文件与元信息保存在 `root/files` 下，
+//! 分片上传的临时文件保存在 `root/tmp` 下。
+//!
+//! 文件大小不能超过 [`FileStore::with_max_size`] 设置的上限，
+//! 超过 [`FileStore::with_session_ttl`] 未收到分片的上传会话将被回收。
+//! `type` 为 `url` 的上传需要 `http` feature，仅支持 http url，下载超过上限时中止。
+//!
+//! ```rust,no_run
+//! use walle_core::action::{GetFile, UploadFile};
+//! use walle_core::file_store::FileStore;
+//!
+//! # async fn upload() -> walle_core::WalleResult<()> {
+//! let store = FileStore::open("./data").await?;
+//! let file_id = store
+//!     .upload_file(UploadFile {
+//!         ty: "data".to_string(),
+//!         name: "hello.txt".to_string(),
+//!         url: None,
+//!         headers: None,
+//!         path: None,
+//!         data: Some(b"hello".to_vec().into()),
+//!         sha256: None,
+//!     })
+//!     .await?;
+//! let file = store
+//!     .get_file(GetFile {
+//!         file_id: file_id.file_id,
+//!         ty: "path".to_string(),
+//!     })
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::Mutex;
+
+use crate::action::{GetFile, GetFileFragmented, UploadFile, UploadFileFragmented};
+use crate::resp::resp_error;
+use crate::structs::{File, FileFragmentedData, FileFragmentedHead, FileId};
+use crate::{WalleError, WalleResult};
+
+fn bad_param<T: std::fmt::Display>(msg: T) -> WalleError {
+    WalleError::RespError(resp_error::bad_param(msg))
+}
+
+fn fs_error<T: std::fmt::Display>(msg: T) -> WalleError {
+    WalleError::RespError(resp_error::filesystem_error(msg))
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+fn hmac_sha256(key: &[u8], msg: &[u8]) -> [u8; 32] {
+    const BLOCK: usize = 64;
+    let mut block = [0u8; BLOCK];
+    if key.len() > BLOCK {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let pad = |b: u8| block.iter().map(|k| k ^ b).collect::<Vec<_>>();
+    let inner = Sha256::new()
+        .chain_update(pad(0x36))
+        .chain_update(msg)
+        .finalize();
+    Sha256::new()
+        .chain_update(pad(0x5c))
+        .chain_update(inner)
+        .finalize()
+        .into()
+}
+
+/// `get_file` 返回 url 的设置，每个提供文件下载的 HTTP 服务各有一个
+#[derive(Debug)]
+pub struct FileUrl {
+    base: String,
+    /// 设置后 url 将带有过期时间与签名
+    secret: Option<Vec<u8>>,
+    expire: u32,
+}
+
+impl FileUrl {
+    fn sign(&self, file_id: &str, expires: u64) -> Option<String> {
+        self.secret.as_ref().map(|secret| {
+            hex::encode(hmac_sha256(
+                secret,
+                format!("{}:{}", file_id, expires).as_bytes(),
+            ))
+        })
+    }
+    fn url(&self, file_id: &str) -> String {
+        let expires = crate::util::timestamp_nano() / 1_000_000_000 + self.expire as u128;
+        match self.sign(file_id, expires as u64) {
+            Some(sign) => format!(
+                "{}/{}?expires={}&sign={}",
+                self.base, file_id, expires, sign
+            ),
+            None => format!("{}/{}", self.base, file_id),
+        }
+    }
+    /// 校验签名 url 的签名与过期时间
+    pub fn verify(&self, file_id: &str, expires: u64, sign: &str) -> bool {
+        let Some(expect) = self.sign(file_id, expires) else {
+            return false;
+        };
+        let now = (crate::util::timestamp_nano() / 1_000_000_000) as u64;
+        expires >= now
+            && expect.len() == sign.len()
+            && expect
+                .bytes()
+                .zip(sign.bytes())
+                .fold(0, |acc, (a, b)| acc | (a ^ b))
+                == 0
+    }
+}
+
+/// 已保存文件的元信息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredFile {
+    pub file_id: String,
+    pub name: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// 默认的单个文件大小上限
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 1 << 30;
+/// 分片上传会话默认的过期时长
+pub const DEFAULT_SESSION_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// 分片上传会话
+#[derive(Debug)]
+struct UploadSession {
+    name: String,
+    total_size: u64,
+    /// 已接收的区间，按起点排序且互不重叠
+    received: Vec<(u64, u64)>,
+    /// 最近一次收到分片的时间
+    last_active: Instant,
+}
+
+impl UploadSession {
+    fn receive(&mut self, start: u64, end: u64) {
+        self.received.push((start, end));
+        self.received.sort_unstable();
+        let mut merged: Vec<(u64, u64)> = Vec::with_capacity(self.received.len());
+        for (start, end) in self.received.drain(..) {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        self.received = merged;
+    }
+    fn received_size(&self) -> u64 {
+        self.received.iter().map(|(s, e)| e - s).sum()
+    }
+    fn is_complete(&self) -> bool {
+        self.total_size == 0 || self.received == [(0, self.total_size)]
+    }
+}
+
+/// 实现端文件存储
+pub struct FileStore {
+    root: PathBuf,
+    /// `get_file` 以 url 形式返回时使用的地址，使用第一个，为空时不支持 url 形式
+    urls: RwLock<Vec<Arc<FileUrl>>>,
+    files: DashMap<String, StoredFile>,
+    sessions: DashMap<String, Arc<Mutex<UploadSession>>>,
+    max_size: u64,
+    session_ttl: Duration,
+}
+
+impl FileStore {
+    /// 打开文件存储目录，并加载已保存的文件信息
+    ///
+    /// 上传会话不会持久化，上次运行遗留的临时文件将被删除
+    pub async fn open(root: impl Into<PathBuf>) -> WalleResult<Self> {
+        let root = root.into();
+        tokio::fs::create_dir_all(root.join("files")).await?;
+        tokio::fs::create_dir_all(root.join("tmp")).await?;
+        let mut tmp = tokio::fs::read_dir(root.join("tmp")).await?;
+        while let Some(entry) = tmp.next_entry().await? {
+            tokio::fs::remove_file(entry.path()).await.ok();
+        }
+        let files = DashMap::new();
+        let mut dir = tokio::fs::read_dir(root.join("files")).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                let meta = tokio::fs::read(&path).await?;
+                match serde_json::from_slice::<StoredFile>(&meta) {
+                    Ok(file) => {
+                        files.insert(file.file_id.clone(), file);
+                    }
+                    Err(e) => tracing::warn!(
+                        target: crate::WALLE_CORE,
+                        "skip broken file meta {:?}: {}",
+                        path,
+                        e
+                    ),
+                }
+            }
+        }
+        Ok(Self {
+            root,
+            urls: RwLock::default(),
+            files,
+            sessions: DashMap::new(),
+            max_size: DEFAULT_MAX_FILE_SIZE,
+            session_ttl: DEFAULT_SESSION_TTL,
+        })
+    }
+    /// 设置单个文件的大小上限，默认为 [`DEFAULT_MAX_FILE_SIZE`]
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = max_size;
+        self
+    }
+    /// 设置分片上传会话的过期时长，默认为 [`DEFAULT_SESSION_TTL`]
+    pub fn with_session_ttl(mut self, ttl: Duration) -> Self {
+        self.session_ttl = ttl;
+        self
+    }
+    fn check_size(&self, size: u64) -> WalleResult<()> {
+        if size > self.max_size {
+            return Err(bad_param(format!(
+                "file size {} exceeds limit {}",
+                size, self.max_size
+            )));
+        }
+        Ok(())
+    }
+    /// 设置 url 地址前缀，`get_file` 将返回 `{url_base}/{file_id}`
+    pub fn with_url_base(self, url_base: impl Into<String>) -> Self {
+        self.add_url(FileUrl {
+            base: url_base.into().trim_end_matches('/').to_owned(),
+            secret: None,
+            expire: 0,
+        });
+        self
+    }
+    /// 添加签名 url，`get_file` 将返回 `{url_base}/{file_id}?expires={expires}&sign={sign}`，
+    /// url 在 `expire` 秒后过期；相同 `url_base` 的设置将被替换
+    pub fn add_signed_url(
+        &self,
+        url_base: impl Into<String>,
+        secret: &[u8],
+        expire: u32,
+    ) -> Arc<FileUrl> {
+        self.add_url(FileUrl {
+            base: url_base.into().trim_end_matches('/').to_owned(),
+            secret: Some(secret.to_vec()),
+            expire,
+        })
+    }
+    fn add_url(&self, url: FileUrl) -> Arc<FileUrl> {
+        let url = Arc::new(url);
+        let mut urls = self.urls.write().unwrap();
+        match urls.iter_mut().find(|u| u.base == url.base) {
+            Some(u) => *u = url.clone(),
+            None => urls.push(url.clone()),
+        }
+        url
+    }
+    /// 移除 url 设置，提供文件下载的 HTTP 服务停止时调用
+    pub fn remove_url(&self, url: &Arc<FileUrl>) {
+        self.urls.write().unwrap().retain(|u| !Arc::ptr_eq(u, url));
+    }
+    /// 生成文件的 url，未设置 url 时返回 None
+    pub fn file_url(&self, file_id: &str) -> Option<String> {
+        self.urls
+            .read()
+            .unwrap()
+            .first()
+            .map(|url| url.url(file_id))
+    }
+    /// 使用任意一个签名 url 设置校验签名与过期时间
+    pub fn verify_url(&self, file_id: &str, expires: u64, sign: &str) -> bool {
+        self.urls
+            .read()
+            .unwrap()
+            .iter()
+            .any(|url| url.verify(file_id, expires, sign))
+    }
+    /// 文件内容的保存路径
+    pub fn file_path(&self, file_id: &str) -> PathBuf {
+        self.root.join("files").join(file_id)
+    }
+    fn meta_path(&self, file_id: &str) -> PathBuf {
+        self.root.join("files").join(format!("{}.json", file_id))
+    }
+    fn tmp_path(&self, file_id: &str) -> PathBuf {
+        self.root.join("tmp").join(file_id)
+    }
+    /// 生成随机的文件 id，并以 `path` 创建该 id 的新文件
+    ///
+    /// id 与已有文件或上传会话重复时返回错误，不会覆盖已有文件
+    async fn create_new(
+        &self,
+        path: impl FnOnce(&Self, &str) -> PathBuf,
+    ) -> WalleResult<(String, tokio::fs::File)> {
+        let file_id = uuid::Builder::from_random_bytes(rand::random())
+            .into_uuid()
+            .to_string();
+        let exists = || WalleError::Other(format!("file id {} already exists", file_id));
+        if self.files.contains_key(&file_id) || self.sessions.contains_key(&file_id) {
+            return Err(exists());
+        }
+        match tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path(self, &file_id))
+            .await
+        {
+            Ok(file) => Ok((file_id, file)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => Err(exists()),
+            Err(e) => Err(e.into()),
+        }
+    }
+    pub fn get(&self, file_id: &str) -> Option<StoredFile> {
+        self.files.get(file_id).map(|f| f.clone())
+    }
+    fn get_or_not_found(&self, file_id: &str) -> WalleResult<StoredFile> {
+        self.get(file_id)
+            .ok_or_else(|| bad_param(format!("file {} not found", file_id)))
+    }
+    async fn save_meta(&self, file: StoredFile) -> WalleResult<FileId> {
+        let meta = serde_json::to_vec(&file).map_err(|e| WalleError::Other(e.to_string()))?;
+        tokio::fs::write(self.meta_path(&file.file_id), meta).await?;
+        let file_id = file.file_id.clone();
+        self.files.insert(file_id.clone(), file);
+        Ok(FileId { file_id })
+    }
+    /// 保存文件内容，提供 `sha256` 时将校验内容
+    pub async fn save(
+        &self,
+        name: String,
+        data: &[u8],
+        sha256: Option<&str>,
+    ) -> WalleResult<FileId> {
+        self.check_size(data.len() as u64)?;
+        let hash = sha256_hex(data);
+        if let Some(expect) = sha256 {
+            if !expect.eq_ignore_ascii_case(&hash) {
+                return Err(bad_param(format!(
+                    "sha256 mismatch, expect {} got {}",
+                    expect, hash
+                )));
+            }
+        }
+        let (file_id, mut file) = self.create_new(Self::file_path).await?;
+        file.write_all(data).await?;
+        file.flush().await?;
+        self.save_meta(StoredFile {
+            file_id,
+            name,
+            size: data.len() as u64,
+            sha256: hash,
+        })
+        .await
+    }
+    /// 处理 `upload_file` 动作，按 `type` 从 `url`、`path` 或 `data` 读取文件
+    pub async fn upload_file(&self, upload: UploadFile) -> WalleResult<FileId> {
+        let data = match upload.ty.as_str() {
+            "url" => {
+                let url = upload.url.ok_or_else(|| bad_param("missing field url"))?;
+                download(&url, upload.headers.unwrap_or_default(), self.max_size).await?
+            }
+            "path" => {
+                let path = upload.path.ok_or_else(|| bad_param("missing field path"))?;
+                let meta = tokio::fs::metadata(&path).await.map_err(fs_error)?;
+                self.check_size(meta.len())?;
+                tokio::fs::read(&path).await.map_err(fs_error)?
+            }
+            "data" => {
+                upload
+                    .data
+                    .ok_or_else(|| bad_param("missing field data"))?
+                    .0
+            }
+            ty => {
+                return Err(WalleError::RespError(resp_error::unsupported_param(
+                    format!("unsupported upload type {}", ty),
+                )))
+            }
+        };
+        self.save(upload.name, &data, upload.sha256.as_deref())
+            .await
+    }
+    /// 处理 `get_file` 动作，按 `type` 返回 `url`、`path` 或 `data`
+    pub async fn get_file(&self, get: GetFile) -> WalleResult<File> {
+        let stored = self.get_or_not_found(&get.file_id)?;
+        let mut file = File {
+            name: stored.name,
+            url: None,
+            headers: None,
+            path: None,
+            data: None,
+            sha256: Some(stored.sha256),
+        };
+        match get.ty.as_str() {
+            "url" => match self.file_url(&get.file_id) {
+                Some(url) => file.url = Some(url),
+                None => {
+                    return Err(WalleError::RespError(resp_error::unsupported_param(
+                        "url is not supported",
+                    )))
+                }
+            },
+            "path" => {
+                let path = tokio::fs::canonicalize(self.file_path(&get.file_id)).await?;
+                file.path = Some(path.to_string_lossy().into_owned());
+            }
+            "data" => file.data = Some(tokio::fs::read(self.file_path(&get.file_id)).await?),
+            ty => {
+                return Err(WalleError::RespError(resp_error::unsupported_param(
+                    format!("unsupported file type {}", ty),
+                )))
+            }
+        }
+        Ok(file)
+    }
+    /// 处理 `upload_file_fragmented` 动作
+    ///
+    /// prepare 与 finish 阶段返回 `file_id`，transfer 阶段返回 None；
+    /// transfer 可以按任意顺序重复发送，断线后可以通过 [`FileStore::received_size`] 查询进度并续传
+    pub async fn upload_file_fragmented(
+        &self,
+        upload: UploadFileFragmented,
+    ) -> WalleResult<Option<FileId>> {
+        match upload {
+            UploadFileFragmented::Prepare { name, total_size } => {
+                let total_size =
+                    u64::try_from(total_size).map_err(|_| bad_param("invalid total_size"))?;
+                self.check_size(total_size)?;
+                self.reap_sessions().await;
+                let (file_id, file) = self.create_new(Self::tmp_path).await?;
+                file.set_len(total_size).await?;
+                self.sessions.insert(
+                    file_id.clone(),
+                    Arc::new(Mutex::new(UploadSession {
+                        name,
+                        total_size,
+                        received: vec![],
+                        last_active: Instant::now(),
+                    })),
+                );
+                Ok(Some(FileId { file_id }))
+            }
+            UploadFileFragmented::Transfer {
+                file_id,
+                offset,
+                data,
+            } => {
+                let session = self.session(&file_id)?;
+                let mut session = session.lock().await;
+                let start = u64::try_from(offset).map_err(|_| bad_param("invalid offset"))?;
+                let end = start + data.0.len() as u64;
+                if end > session.total_size {
+                    return Err(bad_param("fragment out of range"));
+                }
+                let mut file = tokio::fs::OpenOptions::new()
+                    .write(true)
+                    .open(self.tmp_path(&file_id))
+                    .await?;
+                file.seek(SeekFrom::Start(start)).await?;
+                file.write_all(&data.0).await?;
+                file.flush().await?;
+                session.receive(start, end);
+                session.last_active = Instant::now();
+                Ok(None)
+            }
+            UploadFileFragmented::Finish { file_id, sha256 } => {
+                let session = self.session(&file_id)?;
+                let session = session.lock().await;
+                if !session.is_complete() {
+                    return Err(bad_param("file is not completely transferred"));
+                }
+                // 其他并发的 finish 请求将找不到会话
+                if self.sessions.remove(&file_id).is_none() {
+                    return Err(bad_param(format!("upload session {} not found", file_id)));
+                }
+                let tmp = self.tmp_path(&file_id);
+                let hash = sha256_file(&tmp).await?;
+                if let Some(expect) = sha256 {
+                    if !expect.eq_ignore_ascii_case(&hash) {
+                        tokio::fs::remove_file(&tmp).await.ok();
+                        return Err(bad_param(format!(
+                            "sha256 mismatch, expect {} got {}",
+                            expect, hash
+                        )));
+                    }
+                }
+                tokio::fs::rename(&tmp, self.file_path(&file_id)).await?;
+                self.save_meta(StoredFile {
+                    file_id,
+                    name: session.name.clone(),
+                    size: session.total_size,
+                    sha256: hash,
+                })
+                .await
+                .map(Some)
+            }
+        }
+    }
+    fn session(&self, file_id: &str) -> WalleResult<Arc<Mutex<UploadSession>>> {
+        self.sessions
+            .get(file_id)
+            .map(|s| s.clone())
+            .ok_or_else(|| bad_param(format!("upload session {} not found", file_id)))
+    }
+    /// 回收超过过期时长未收到分片的上传会话并删除其临时文件，返回回收的会话数
+    ///
+    /// 每次 prepare 时自动调用
+    pub async fn reap_sessions(&self) -> usize {
+        let expired = |_: &String, session: &Arc<Mutex<UploadSession>>| {
+            // 正在处理分片的会话不会过期
+            session
+                .try_lock()
+                .is_ok_and(|s| s.last_active.elapsed() >= self.session_ttl)
+        };
+        let keys: Vec<String> = self
+            .sessions
+            .iter()
+            .filter(|e| expired(e.key(), e.value()))
+            .map(|e| e.key().clone())
+            .collect();
+        let mut reaped = 0;
+        for file_id in keys {
+            if self.sessions.remove_if(&file_id, expired).is_some() {
+                tokio::fs::remove_file(self.tmp_path(&file_id)).await.ok();
+                reaped += 1;
+            }
+        }
+        reaped
+    }
+    /// 分片上传已接收的字节数
+    pub async fn received_size(&self, file_id: &str) -> Option<u64> {
+        let session = self.session(file_id).ok()?;
+        let size = session.lock().await.received_size();
+        Some(size)
+    }
+    /// 处理 `get_file_fragmented` prepare 阶段
+    pub async fn get_file_fragmented_prepare(
+        &self,
+        file_id: &str,
+    ) -> WalleResult<FileFragmentedHead> {
+        let stored = self.get_or_not_found(file_id)?;
+        Ok(FileFragmentedHead {
+            name: stored.name,
+            total_size: stored.size as i64,
+            sha256: stored.sha256,
+        })
+    }
+    /// 处理 `get_file_fragmented` transfer 阶段
+    pub async fn get_file_fragmented_transfer(
+        &self,
+        file_id: &str,
+        offset: i64,
+        size: i64,
+    ) -> WalleResult<FileFragmentedData> {
+        let stored = self.get_or_not_found(file_id)?;
+        let offset = u64::try_from(offset).map_err(|_| bad_param("invalid offset"))?;
+        let size = u64::try_from(size).map_err(|_| bad_param("invalid size"))?;
+        let size = size.min(stored.size.saturating_sub(offset));
+        let mut file = tokio::fs::File::open(self.file_path(file_id)).await?;
+        file.seek(SeekFrom::Start(offset)).await?;
+        let mut data = vec![0; size as usize];
+        file.read_exact(&mut data).await?;
+        Ok(FileFragmentedData { data: data.into() })
+    }
+    /// 处理 `get_file_fragmented` 动作，prepare 阶段返回 [`FileFragmentedHead`]，
+    /// transfer 阶段返回 [`FileFragmentedData`]
+    pub async fn get_file_fragmented(
+        &self,
+        get: GetFileFragmented,
+    ) -> WalleResult<crate::util::Value> {
+        match get {
+            GetFileFragmented::Prepare { file_id } => self
+                .get_file_fragmented_prepare(&file_id)
+                .await
+                .map(Into::into),
+            GetFileFragmented::Transfer {
+                file_id,
+                offset,
+                size,
+            } => self
+                .get_file_fragmented_transfer(&file_id, offset, size)
+                .await
+                .map(Into::into),
+        }
+    }
+    /// 删除文件
+    pub async fn remove(&self, file_id: &str) -> WalleResult<()> {
+        if self.files.remove(file_id).is_some() {
+            tokio::fs::remove_file(self.file_path(file_id)).await.ok();
+            tokio::fs::remove_file(self.meta_path(file_id)).await.ok();
+        }
+        Ok(())
+    }
+}
+
+async fn sha256_file(path: &Path) -> WalleResult<String> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// 下载文件，响应体超过 `max_size` 时中止，仅支持 http url
+#[cfg(feature = "http")]
+async fn download(
+    url: &str,
+    headers: HashMap<String, String>,
+    max_size: u64,
+) -> WalleResult<Vec<u8>> {
+    use http_body_util::{BodyExt, Empty, Limited};
+    use hyper::body::Bytes;
+    use hyper_util::{client::legacy::Client, rt::TokioExecutor};
+
+    let network_error = |e: String| WalleError::RespError(resp_error::network_error(e));
+    let uri: hyper::Uri = url.parse().map_err(|e: hyper::http::uri::InvalidUri| {
+        bad_param(format!("invalid url {}: {}", url, e))
+    })?;
+    if uri.scheme_str() != Some("http") {
+        return Err(WalleError::RespError(resp_error::unsupported_param(
+            format!("unsupported url scheme {}, only http is supported", url),
+        )));
+    }
+    let mut req = hyper::Request::get(uri);
+    for (k, v) in headers {
+        req = req.header(k, v);
+    }
+    let req = req
+        .body(Empty::<Bytes>::new())
+        .map_err(|e| bad_param(e.to_string()))?;
+    let resp = Client::builder(TokioExecutor::new())
+        .build_http()
+        .request(req)
+        .await
+        .map_err(|e| network_error(e.to_string()))?;
+    if !resp.status().is_success() {
+        return Err(network_error(format!("download failed: {}", resp.status())));
+    }
+    let too_large = || bad_param(format!("file size exceeds limit {}", max_size));
+    let content_length = resp
+        .headers()
+        .get(hyper::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if content_length.is_some_and(|len| len > max_size) {
+        return Err(too_large());
+    }
+    let limit = usize::try_from(max_size).unwrap_or(usize::MAX);
+    let body = Limited::new(resp.into_body(), limit)
+        .collect()
+        .await
+        .map_err(|e| {
+            if e.is::<http_body_util::LengthLimitError>() {
+                too_large()
+            } else {
+                network_error(e.to_string())
+            }
+        })?;
+    Ok(body.to_bytes().to_vec())
+}
+
+#[cfg(not(feature = "http"))]
+async fn download(
+    _url: &str,
+    _headers: HashMap<String, String>,
+    _max_size: u64,
+) -> WalleResult<Vec<u8>> {
+    Err(WalleError::RespError(resp_error::unsupported_param(
+        "url upload requires http feature",
+    )))
+}
+
+#[tokio::test]
+async fn file_store_test() {
+    let root = std::env::temp_dir().join(format!("walle-files-{}", std::process::id()));
+    let store = FileStore::open(&root).await.unwrap();
+
+    let data = b"hello world".to_vec();
+    let file_id = store
+        .upload_file(UploadFile {
+            ty: "data".to_string(),
+            name: "hello.txt".to_string(),
+            url: None,
+            headers: None,
+            path: None,
+            data: Some(data.clone().into()),
+            sha256: Some(sha256_hex(&data)),
+        })
+        .await
+        .unwrap()
+        .file_id;
+    let file = store
+        .get_file(GetFile {
+            file_id: file_id.clone(),
+            ty: "data".to_string(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(file.data.as_ref(), Some(&data));
+
+    // fragmented upload out of order with a resent fragment
+    let file_id = store
+        .upload_file_fragmented(UploadFileFragmented::Prepare {
+            name: "hello.txt".to_string(),
+            total_size: data.len() as i64,
+        })
+        .await
+        .unwrap()
+        .unwrap()
+        .file_id;
+    for (offset, end) in [(6, 11), (0, 6), (6, 11)] {
+        store
+            .upload_file_fragmented(UploadFileFragmented::Transfer {
+                file_id: file_id.clone(),
+                offset,
+                data: data[offset as usize..end].to_vec().into(),
+            })
+            .await
+            .unwrap();
+    }
+    assert_eq!(store.received_size(&file_id).await, Some(11));
+    assert!(store
+        .upload_file_fragmented(UploadFileFragmented::Finish {
+            file_id: file_id.clone(),
+            sha256: Some("00".to_string()),
+        })
+        .await
+        .is_err());
+
+    let file_id = store
+        .upload_file_fragmented(UploadFileFragmented::Prepare {
+            name: "hello.txt".to_string(),
+            total_size: data.len() as i64,
+        })
+        .await
+        .unwrap()
+        .unwrap()
+        .file_id;
+    store
+        .upload_file_fragmented(UploadFileFragmented::Transfer {
+            file_id: file_id.clone(),
+            offset: 0,
+            data: data.clone().into(),
+        })
+        .await
+        .unwrap();
+    store
+        .upload_file_fragmented(UploadFileFragmented::Finish {
+            file_id: file_id.clone(),
+            sha256: Some(sha256_hex(&data)),
+        })
+        .await
+        .unwrap();
+
+    // reopen and fragmented download
+    let store = FileStore::open(&root).await.unwrap();
+    let other = store.add_signed_url("http://127.0.0.2/files/", b"other", 60);
+    let signer = store.add_signed_url("http://127.0.0.1/files/", b"secret", 60);
+    store.remove_url(&other);
+    let url = store.file_url(&file_id).unwrap();
+    let query = url.split_once('?').unwrap().1;
+    let (expires, sign) = query.split_once('&').unwrap();
+    let expires = expires.trim_start_matches("expires=").parse().unwrap();
+    let sign = sign.trim_start_matches("sign=");
+    assert!(url.starts_with(&format!("http://127.0.0.1/files/{}?", file_id)));
+    assert!(signer.verify(&file_id, expires, sign));
+    assert!(store.verify_url(&file_id, expires, sign));
+    assert!(!store.verify_url(&file_id, expires + 1, sign));
+    // 每个服务使用各自的密钥
+    assert!(!other.verify(&file_id, expires, sign));
+    let expired = signer.sign(&file_id, 1);
+    assert!(!store.verify_url(&file_id, 1, &expired.unwrap()));
+    // RFC 4231 test case 2
+    assert_eq!(
+        hex::encode(hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+        "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+    );
+    let head = store.get_file_fragmented_prepare(&file_id).await.unwrap();
+    assert_eq!(head.total_size, 11);
+    let tail = store
+        .get_file_fragmented_transfer(&file_id, 6, 100)
+        .await
+        .unwrap();
+    assert_eq!(tail.data.0, b"world");
+    tokio::fs::remove_dir_all(&root).await.ok();
+}
+
+#[tokio::test]
+async fn file_store_limit_test() {
+    let root = std::env::temp_dir().join(format!("walle-files-limit-{}", std::process::id()));
+    tokio::fs::create_dir_all(root.join("tmp")).await.unwrap();
+    tokio::fs::write(root.join("tmp").join("stale"), b"stale")
+        .await
+        .unwrap();
+    let store = FileStore::open(&root)
+        .await
+        .unwrap()
+        .with_max_size(8)
+        .with_session_ttl(Duration::from_millis(50));
+    assert!(!root.join("tmp").join("stale").exists());
+
+    // 超过上限的文件在分配临时文件前被拒绝
+    assert!(store
+        .upload_file_fragmented(UploadFileFragmented::Prepare {
+            name: "big.bin".to_string(),
+            total_size: 9,
+        })
+        .await
+        .is_err());
+    assert!(store
+        .save("big.bin".to_string(), &[0; 9], None)
+        .await
+        .is_err());
+    assert_eq!(std::fs::read_dir(root.join("tmp")).unwrap().count(), 0);
+
+    let file_id = store
+        .upload_file_fragmented(UploadFileFragmented::Prepare {
+            name: "small.bin".to_string(),
+            total_size: 8,
+        })
+        .await
+        .unwrap()
+        .unwrap()
+        .file_id;
+    assert_eq!(store.reap_sessions().await, 0);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(store.reap_sessions().await, 1);
+    assert_eq!(store.received_size(&file_id).await, None);
+    assert!(!store.tmp_path(&file_id).exists());
+    assert!(store
+        .upload_file_fragmented(UploadFileFragmented::Transfer {
+            file_id,
+            offset: 0,
+            data: vec![0; 8].into(),
+        })
+        .await
+        .is_err());
+    tokio::fs::remove_dir_all(&root).await.ok();
+}
+
+#[tokio::test]
+async fn file_store_id_test() {
+    let root = std::env::temp_dir().join(format!("walle-files-id-{}", std::process::id()));
+    let store = Arc::new(FileStore::open(&root).await.unwrap());
+
+    // 并发上传得到不同的 id，不会互相覆盖
+    let tasks: Vec<_> = (0..32u8)
+        .map(|i| {
+            let store = store.clone();
+            tokio::spawn(async move { (i, store.save(i.to_string(), &[i], None).await.unwrap()) })
+        })
+        .collect();
+    let mut ids = std::collections::HashSet::new();
+    for task in tasks {
+        let (i, id) = task.await.unwrap();
+        assert_eq!(
+            tokio::fs::read(store.file_path(&id.file_id)).await.unwrap(),
+            [i]
+        );
+        assert!(ids.insert(id.file_id));
+    }
+
+    // 已存在的 id 不会被覆盖
+    let file_id = ids.iter().next().unwrap();
+    let err = store
+        .create_new(|store, _| store.file_path(file_id))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, WalleError::Other(e) if e.contains("already exists")));
+    tokio::fs::remove_dir_all(&root).await.ok();
+}
+
+#[cfg(feature = "http")]
+#[tokio::test]
+async fn file_store_download_limit_test() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // 依次返回声明超长、分块超长与正常的响应
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let responses: [&[u8]; 3] = [
+            b"HTTP/1.1 200 OK\r\ncontent-length: 9\r\nconnection: close\r\n\r\n123456789",
+            b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\nconnection: close\r\n\r\n9\r\n123456789\r\n0\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\ncontent-length: 5\r\nconnection: close\r\n\r\nhello",
+        ];
+        for resp in responses {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf).await;
+            stream.write_all(resp).await.unwrap();
+            stream.shutdown().await.ok();
+        }
+    });
+    let url = format!("http://{}/file", addr);
+    for _ in 0..2 {
+        assert!(matches!(
+            download(&url, HashMap::default(), 8).await,
+            Err(WalleError::RespError(resp)) if resp.retcode == resp_error::bad_param("").retcode
+        ));
+    }
+    assert_eq!(
+        download(&url, HashMap::default(), 8).await.unwrap(),
+        b"hello"
+    );
+    assert!(matches!(
+        download("https://127.0.0.1/file", HashMap::default(), 8).await,
+        Err(WalleError::RespError(resp))
+            if resp.retcode == resp_error::unsupported_param("").retcode
+    ));
+}
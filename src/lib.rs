@@ -9,13 +9,28 @@ pub const WALLE_CORE: &str = "Walle-core";
 pub mod action;
 #[cfg(feature = "alt")]
 pub mod alt;
+pub mod cache;
+pub mod command;
 pub mod config;
 pub mod error;
 pub mod event;
+#[cfg(feature = "file-store")]
+pub mod file_store;
+pub mod lifecycle;
+pub mod render;
 pub mod resp;
 pub mod segment;
+pub mod session;
+#[cfg(feature = "event-store")]
+pub mod store;
 pub mod structs;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+#[cfg(feature = "app-obc")]
+pub mod transfer;
 pub mod util;
+#[cfg(feature = "v11")]
+pub mod v11;
 
 mod ah;
 pub use ah::{AHExt, ActionHandler, GenStatus};
@@ -35,11 +50,11 @@ pub mod prelude {
     pub use crate::error::{WalleError, WalleResult};
     pub use crate::util::{Echo, GetSelf, OneBotBytes, Value, ValueMap, ValueMapExt};
     pub use crate::{value, value_map, value_vec};
-    pub use walle_macro::{PushToValueMap, ToAction, ToEvent, ToMsgSegment};
+    pub use walle_macro::{EventKind, PushToValueMap, ToAction, ToEvent, ToMsgSegment};
     pub use walle_macro::{TryFromAction, TryFromEvent, TryFromMsgSegment, TryFromValue};
 
     pub use crate::action::{Action, BaseAction, ToAction, TryFromAction};
-    pub use crate::event::{BaseEvent, Event, ToEvent, TryFromEvent};
+    pub use crate::event::{BaseEvent, Event, EventKind, ToEvent, TryFromEvent};
     pub use crate::resp::{resp_error, Resp};
     pub use crate::segment::{
         IntoMessage, MessageExt, MsgSegment, Segments, ToMsgSegment, TryFromMsgSegment,
@@ -53,14 +68,21 @@ pub struct OneBot<AH, EH> {
     event_handler: EH,
     // Some for running, None for stopped
     signal: StdMutex<Option<tokio::sync::broadcast::Sender<()>>>,
-    ah_tasks: Mutex<Vec<JoinHandle<()>>>,
-    eh_tasks: Mutex<Vec<JoinHandle<()>>>,
+    // named tasks returned by handlers
+    tasks: StdMutex<Vec<NamedTask>>,
+    // (AH::Config, EH::Config) used to restart failed handler tasks
+    configs: StdMutex<Option<Box<dyn Any + Send + Sync>>>,
+    restart_policy: RestartPolicy,
+    shutdown_timeout: Duration,
+    drain_timeout: Duration,
     // Version
     pub version: Version,
 }
 
+use lifecycle::{named_tasks, NamedTask, RestartPolicy, TaskOwner, TaskState, TaskStatus};
+use std::any::Any;
 use std::sync::{Arc, Mutex as StdMutex};
-use tokio::sync::Mutex;
+use std::time::Duration;
 
 pub use crate::error::{WalleError, WalleResult};
 
@@ -70,11 +92,32 @@ impl<AH, EH> OneBot<AH, EH> {
             action_handler,
             event_handler,
             signal: StdMutex::new(None),
-            ah_tasks: Mutex::default(),
-            eh_tasks: Mutex::default(),
+            tasks: StdMutex::default(),
+            configs: StdMutex::default(),
+            restart_policy: RestartPolicy::default(),
+            shutdown_timeout: lifecycle::DEFAULT_SHUTDOWN_TIMEOUT,
+            drain_timeout: lifecycle::DEFAULT_DRAIN_TIMEOUT,
             version,
         }
     }
+    /// 设置失败组件与 handler 任务的重启策略
+    pub fn with_restart_policy(mut self, policy: RestartPolicy) -> Self {
+        self.restart_policy = policy;
+        self
+    }
+    /// 设置停止时等待任务结束的时长，超时后任务将被中止
+    pub fn with_shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+    /// 设置停止时连接等待进行中的 action 与排队事件发送完成的时长
+    pub fn with_drain_timeout(mut self, timeout: Duration) -> Self {
+        self.drain_timeout = timeout;
+        self
+    }
+    /// 启动 ActionHandler 与 EventHandler，并按重启策略监督其任务
+    ///
+    /// 配置会被保存用于重启意外退出的 handler 任务，因此要求两方 Config 实现 `Clone`
     pub async fn start<E, A, R>(
         self: &Arc<Self>,
         ah_config: AH::Config,
@@ -87,27 +130,218 @@ impl<AH, EH> OneBot<AH, EH> {
         R: Send + Sync + 'static,
         AH: ActionHandler<E, A, R> + Send + Sync + 'static,
         EH: EventHandler<E, A, R> + Send + Sync + 'static,
+        AH::Config: Clone + Send + Sync + 'static,
+        EH::Config: Clone + Send + Sync + 'static,
     {
         if !self.set_signal() {
             return Err(WalleError::AlreadyStarted);
         }
+        *self.configs.lock().unwrap() = Some(Box::new((ah_config.clone(), eh_config.clone())));
+        let mut tasks = vec![];
         if ah_first {
-            *self.ah_tasks.lock().await = self.action_handler.start(self, ah_config).await?;
-            *self.eh_tasks.lock().await = self.event_handler.start(self, eh_config).await?;
+            let ah_tasks = self.action_handler.start(self, ah_config).await?;
+            tasks.extend(named_tasks(TaskOwner::ActionHandler, ah_tasks, 0));
+            let eh_tasks = self.event_handler.start(self, eh_config).await?;
+            tasks.extend(named_tasks(TaskOwner::EventHandler, eh_tasks, 0));
         } else {
-            *self.eh_tasks.lock().await = self.event_handler.start(self, eh_config).await?;
-            *self.ah_tasks.lock().await = self.action_handler.start(self, ah_config).await?;
+            let eh_tasks = self.event_handler.start(self, eh_config).await?;
+            tasks.extend(named_tasks(TaskOwner::EventHandler, eh_tasks, 0));
+            let ah_tasks = self.action_handler.start(self, ah_config).await?;
+            tasks.extend(named_tasks(TaskOwner::ActionHandler, ah_tasks, 0));
         }
+        let supervisor = self.supervise::<E, A, R>()?;
+        tasks.extend(named_tasks(TaskOwner::OneBot, vec![supervisor], 0));
+        *self.tasks.lock().unwrap() = tasks;
         Ok(())
     }
-    pub async fn wait_all(&self) {
-        let mut tasks: Vec<JoinHandle<()>> = std::mem::take(self.ah_tasks.lock().await.as_mut());
-        tasks.extend(
-            std::mem::take::<Vec<JoinHandle<()>>>(self.eh_tasks.lock().await.as_mut()).into_iter(),
+    /// 每隔 `backoff` 按重启策略重启失败的 handler 任务与组件
+    fn supervise<E, A, R>(self: &Arc<Self>) -> WalleResult<JoinHandle<()>>
+    where
+        E: Send + Sync + 'static,
+        A: Send + Sync + 'static,
+        R: Send + Sync + 'static,
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+        AH::Config: Clone + Send + Sync + 'static,
+        EH::Config: Clone + Send + Sync + 'static,
+    {
+        let mut signal_rx = self.get_signal_rx()?;
+        let ob = self.clone();
+        Ok(tokio::spawn(async move {
+            let policy = &ob.restart_policy;
+            loop {
+                tokio::select! {
+                    _ = signal_rx.recv() => break,
+                    _ = tokio::time::sleep(policy.backoff) => {
+                        ob.restart_tasks::<E, A, R>(TaskOwner::ActionHandler).await;
+                        ob.restart_tasks::<E, A, R>(TaskOwner::EventHandler).await;
+                        ob.action_handler.restart_failed(&ob, policy).await;
+                        ob.event_handler.restart_failed(&ob, policy).await;
+                    }
+                }
+            }
+        }))
+    }
+    /// 回收 handler 意外退出的任务，按重启策略中止其余任务后以最近一次的配置重新调用 `start`
+    async fn restart_tasks<E, A, R>(self: &Arc<Self>, owner: TaskOwner)
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+        AH::Config: Clone + Send + Sync + 'static,
+        EH::Config: Clone + Send + Sync + 'static,
+    {
+        let exited: Vec<(String, JoinHandle<()>)> = self
+            .tasks
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .filter(|t| t.owner == owner && t.handle.as_ref().is_some_and(|h| h.is_finished()))
+            .map(|t| (t.status.name.clone(), t.handle.take().unwrap()))
+            .collect();
+        for (name, handle) in exited {
+            let (reason, panicked) = match handle.await {
+                Ok(()) => ("exited unexpectedly".to_owned(), false),
+                Err(e) => {
+                    let panicked = e.is_panic();
+                    (lifecycle::failure_reason(e), panicked)
+                }
+            };
+            tracing::warn!(target: WALLE_CORE, "task {} failed: {}", name, reason);
+            let mut tasks = self.tasks.lock().unwrap();
+            if let Some(task) = tasks
+                .iter_mut()
+                .find(|t| t.owner == owner && t.status.name == name)
+            {
+                task.status.state = TaskState::Failed(reason);
+                task.panicked = panicked;
+            }
+        }
+        let restarts = {
+            let tasks = self.tasks.lock().unwrap();
+            let owned = tasks.iter().filter(|t| t.owner == owner);
+            if !owned
+                .clone()
+                .any(|t| matches!(t.status.state, TaskState::Failed(_)))
+            {
+                return;
+            }
+            owned.map(|t| t.status.restarts).max().unwrap_or_default()
+        };
+        if restarts >= self.restart_policy.max_restarts {
+            return;
+        }
+        let configs = self
+            .configs
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(|c| c.downcast_ref::<(AH::Config, EH::Config)>())
+            .cloned();
+        let Some((ah_config, eh_config)) = configs else {
+            return;
+        };
+        self.tasks.lock().unwrap().retain(|t| {
+            if t.owner == owner {
+                t.handle.iter().for_each(JoinHandle::abort);
+            }
+            t.owner != owner
+        });
+        tracing::info!(
+            target: WALLE_CORE,
+            "restart {} ({}/{})",
+            owner.name(),
+            restarts + 1,
+            self.restart_policy.max_restarts
         );
-        for task in tasks {
-            task.await.ok();
+        let started = match owner {
+            TaskOwner::ActionHandler => self.action_handler.start(self, ah_config).await,
+            _ => self.event_handler.start(self, eh_config).await,
+        };
+        let tasks = match started {
+            Ok(handles) => named_tasks(owner, handles, restarts + 1),
+            Err(e) => {
+                tracing::warn!(target: WALLE_CORE, "restart {} failed: {}", owner.name(), e);
+                vec![NamedTask::failed(owner, e.to_string(), restarts + 1)]
+            }
+        };
+        if !self.is_started() {
+            // 重启期间 OneBot 已停止
+            tasks
+                .iter()
+                .flat_map(|t| &t.handle)
+                .for_each(JoinHandle::abort);
+            return;
         }
+        self.tasks.lock().unwrap().extend(tasks);
+    }
+    /// 等待所有任务结束，返回第一个 panic 的任务
+    pub async fn wait_all(&self) -> WalleResult<()> {
+        self.join_tasks(None).await
+    }
+    async fn join_tasks(&self, timeout: Option<Duration>) -> WalleResult<()> {
+        let tasks = std::mem::take(&mut *self.tasks.lock().unwrap());
+        let deadline = timeout.map(|t| tokio::time::Instant::now() + t);
+        let mut result = Ok(());
+        let mut aborted = 0;
+        for NamedTask {
+            status,
+            handle,
+            panicked,
+            ..
+        } in tasks
+        {
+            let name = status.name;
+            let Some(mut task) = handle else {
+                // 已回收的失败任务
+                if let (true, TaskState::Failed(reason), true) =
+                    (panicked, status.state, result.is_ok())
+                {
+                    result = Err(WalleError::TaskPanicked(name, reason));
+                }
+                continue;
+            };
+            let joined = match deadline {
+                Some(deadline) => tokio::time::timeout_at(deadline, &mut task).await,
+                None => Ok((&mut task).await),
+            };
+            match joined {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) if e.is_panic() => {
+                    let reason = lifecycle::failure_reason(e);
+                    tracing::warn!(target: WALLE_CORE, "task {} panicked: {}", name, reason);
+                    if result.is_ok() {
+                        result = Err(WalleError::TaskPanicked(name, reason));
+                    }
+                }
+                Ok(Err(_)) => {}
+                Err(_) => {
+                    tracing::warn!(target: WALLE_CORE, "task {} shutdown timeout, abort", name);
+                    task.abort();
+                    aborted += 1;
+                }
+            }
+        }
+        if aborted > 0 {
+            return Err(WalleError::ShutdownTimeout(aborted));
+        }
+        result
+    }
+    /// OneBot 任务与 ActionHandler、EventHandler 运行组件的状态
+    pub fn task_status<E, A, R>(&self) -> Vec<TaskStatus>
+    where
+        AH: ActionHandler<E, A, R>,
+        EH: EventHandler<E, A, R>,
+    {
+        let mut status: Vec<TaskStatus> = self
+            .tasks
+            .lock()
+            .unwrap()
+            .iter()
+            .map(NamedTask::status)
+            .collect();
+        status.extend(self.action_handler.task_status());
+        status.extend(self.event_handler.task_status());
+        status
     }
     pub fn set_signal(&self) -> bool {
         let mut signal = self.signal.lock().unwrap();
@@ -146,6 +380,8 @@ impl<AH, EH> OneBot<AH, EH> {
             .take()
             .ok_or(WalleError::NotStarted)?;
         tx.send(()).ok();
+        // 连接在停止信号后 drain 进行中的 action 与排队事件，完成后再调用 handler 的 shutdown
+        let joined = self.join_tasks(Some(self.shutdown_timeout)).await;
         if ah_first {
             self.action_handler.shutdown().await;
             self.event_handler.shutdown().await;
@@ -153,7 +389,73 @@ impl<AH, EH> OneBot<AH, EH> {
             self.event_handler.shutdown().await;
             self.action_handler.shutdown().await;
         }
-        self.wait_all().await;
+        joined
+    }
+    /// 停止后以新配置重新启动，停止过程中的错误仅记录警告
+    pub async fn restart<E, A, R>(
+        self: &Arc<Self>,
+        ah_config: AH::Config,
+        eh_config: EH::Config,
+        ah_first: bool,
+    ) -> WalleResult<()>
+    where
+        E: Send + Sync + 'static,
+        A: Send + Sync + 'static,
+        R: Send + Sync + 'static,
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+        AH::Config: Clone + Send + Sync + 'static,
+        EH::Config: Clone + Send + Sync + 'static,
+    {
+        match self.shutdown::<E, A, R>(ah_first).await {
+            Ok(()) | Err(WalleError::NotStarted) => {}
+            Err(e) => tracing::warn!(target: WALLE_CORE, "restart shutdown error: {}", e),
+        }
+        self.start::<E, A, R>(ah_config, eh_config, ah_first).await
+    }
+    /// 运行期间重新配置，仅启停发生变化的监听与连接，为 None 的一方保持不变
+    pub async fn reconfigure<E, A, R>(
+        self: &Arc<Self>,
+        ah_config: Option<AH::Config>,
+        eh_config: Option<EH::Config>,
+    ) -> WalleResult<()>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+        AH::Config: Clone + Send + Sync + 'static,
+        EH::Config: Clone + Send + Sync + 'static,
+    {
+        if !self.is_started() {
+            return Err(WalleError::NotStarted);
+        }
+        // 两方配置均校验通过后再应用
+        if let Some(config) = &ah_config {
+            self.action_handler.validate_config(config)?;
+        }
+        if let Some(config) = &eh_config {
+            self.event_handler.validate_config(config)?;
+        }
+        // 同时更新重启 handler 任务时使用的配置
+        if let Some(stored) = self
+            .configs
+            .lock()
+            .unwrap()
+            .as_mut()
+            .and_then(|c| c.downcast_mut::<(AH::Config, EH::Config)>())
+        {
+            if let Some(config) = &ah_config {
+                stored.0 = config.clone();
+            }
+            if let Some(config) = &eh_config {
+                stored.1 = config.clone();
+            }
+        }
+        if let Some(config) = ah_config {
+            self.action_handler.reconfigure(self, config).await?;
+        }
+        if let Some(config) = eh_config {
+            self.event_handler.reconfigure(self, config).await?;
+        }
         Ok(())
     }
     pub async fn handle_event<E, A, R>(self: &Arc<Self>, event: E) -> WalleResult<()>
@@ -0,0 +1,236 @@
+//! OneBot 运行任务状态与重启策略
+
+use std::time::Duration;
+
+use tokio::task::{JoinError, JoinHandle};
+
+/// 任务状态
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TaskState {
+    Starting,
+    Running,
+    Stopped,
+    /// 任务 panic 或意外退出，附带原因
+    Failed(String),
+}
+
+/// 命名任务的状态
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskStatus {
+    pub name: String,
+    pub state: TaskState,
+    /// 已重启次数
+    pub restarts: u32,
+}
+
+/// 失败任务的重启策略
+///
+/// OneBot 每隔 `backoff` 检查一次，重启次数未超过 `max_restarts` 的失败组件将以当前配置重新启动，
+/// 超过后保持失败状态直到重新配置或重启 OneBot；`max_restarts` 为 0 则不重启
+///
+/// ActionHandler 或 EventHandler `start` 返回的任务退出时，将中止该 handler 的其余任务，
+/// 并以最近一次的配置重新调用 `start`
+#[derive(Debug, Clone)]
+pub struct RestartPolicy {
+    pub max_restarts: u32,
+    pub backoff: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            max_restarts: 3,
+            backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+/// OneBot 停止时等待任务结束的默认时长，超时后任务将被中止
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 连接停止时等待进行中的 action 响应与排队事件发送的默认时长，应小于停止超时
+pub const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// OneBot 任务所属
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TaskOwner {
+    ActionHandler,
+    EventHandler,
+    OneBot,
+}
+
+impl TaskOwner {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Self::ActionHandler => "action_handler",
+            Self::EventHandler => "event_handler",
+            Self::OneBot => "supervisor",
+        }
+    }
+}
+
+/// OneBot 持有的命名任务
+pub(crate) struct NamedTask {
+    pub(crate) owner: TaskOwner,
+    pub(crate) status: TaskStatus,
+    /// 已回收的失败任务为 None
+    pub(crate) handle: Option<JoinHandle<()>>,
+    pub(crate) panicked: bool,
+}
+
+impl NamedTask {
+    pub(crate) fn failed(owner: TaskOwner, reason: String, restarts: u32) -> Self {
+        Self {
+            owner,
+            status: TaskStatus {
+                name: owner.name().to_owned(),
+                state: TaskState::Failed(reason),
+                restarts,
+            },
+            handle: None,
+            panicked: false,
+        }
+    }
+    /// 当前状态，已结束但尚未回收的任务为 Stopped
+    pub(crate) fn status(&self) -> TaskStatus {
+        let mut status = self.status.clone();
+        if status.state == TaskState::Running
+            && self.handle.as_ref().is_some_and(|h| h.is_finished())
+        {
+            status.state = TaskState::Stopped;
+        }
+        status
+    }
+}
+
+/// 为 handler 返回的任务命名，多个任务时名称带有序号
+pub(crate) fn named_tasks(
+    owner: TaskOwner,
+    handles: Vec<JoinHandle<()>>,
+    restarts: u32,
+) -> Vec<NamedTask> {
+    let many = handles.len() > 1;
+    handles
+        .into_iter()
+        .enumerate()
+        .map(|(i, handle)| NamedTask {
+            owner,
+            status: TaskStatus {
+                name: match many {
+                    true => format!("{}[{}]", owner.name(), i),
+                    false => owner.name().to_owned(),
+                },
+                state: TaskState::Running,
+                restarts,
+            },
+            handle: Some(handle),
+            panicked: false,
+        })
+        .collect()
+}
+
+/// 任务失败原因，panic 时为 panic 信息
+pub(crate) fn failure_reason(e: JoinError) -> String {
+    if e.is_cancelled() {
+        return "cancelled".to_owned();
+    }
+    let panic = e.into_panic();
+    if let Some(s) = panic.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = panic.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "panicked".to_owned()
+    }
+}
+
+#[tokio::test]
+async fn lifecycle_test() {
+    use crate::action::Action;
+    use crate::event::Event;
+    use crate::resp::Resp;
+    use crate::structs::Version;
+    use crate::testing::MockActionHandler;
+    use crate::{ActionHandler, EventHandler, OneBot, WalleError, WalleResult};
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+    use tokio::task::JoinHandle;
+
+    struct TaskHandler(Arc<AtomicU32>);
+
+    impl EventHandler<Event, Action, Resp> for TaskHandler {
+        type Config = &'static str;
+        async fn start<AH, EH>(
+            &self,
+            _ob: &Arc<OneBot<AH, EH>>,
+            config: &'static str,
+        ) -> WalleResult<Vec<JoinHandle<()>>>
+        where
+            AH: ActionHandler<Event, Action, Resp> + Send + Sync + 'static,
+            EH: EventHandler<Event, Action, Resp> + Send + Sync + 'static,
+        {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(vec![tokio::spawn(async move {
+                match config {
+                    "panic" => panic!("boom"),
+                    _ => std::future::pending().await,
+                }
+            })])
+        }
+        async fn call<AH, EH>(&self, _event: Event, _ob: &Arc<OneBot<AH, EH>>) -> WalleResult<()>
+        where
+            AH: ActionHandler<Event, Action, Resp> + Send + Sync + 'static,
+            EH: EventHandler<Event, Action, Resp> + Send + Sync + 'static,
+        {
+            Ok(())
+        }
+    }
+
+    let starts = Arc::new(AtomicU32::new(0));
+    let ob = Arc::new(
+        OneBot::new(
+            MockActionHandler::new(),
+            TaskHandler(starts.clone()),
+            Version {
+                implt: "test".to_owned(),
+                version: crate::VERSION.to_owned(),
+                onebot_version: 12.to_string(),
+            },
+        )
+        .with_shutdown_timeout(Duration::from_millis(100))
+        .with_restart_policy(RestartPolicy {
+            max_restarts: 1,
+            backoff: Duration::from_millis(50),
+        }),
+    );
+    ob.start::<Event, Action, Resp>((), "panic", true)
+        .await
+        .unwrap();
+    // 重启一次后再次 panic，超出重启次数后标记为失败
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(starts.load(Ordering::SeqCst), 2);
+    let status = ob.task_status::<Event, Action, Resp>();
+    assert!(status.iter().any(|s| s.name == "event_handler"
+        && s.state == TaskState::Failed("boom".to_owned())
+        && s.restarts == 1));
+    assert!(status
+        .iter()
+        .any(|s| s.name == "supervisor" && s.state == TaskState::Running));
+    match ob.shutdown::<Event, Action, Resp>(true).await {
+        Err(WalleError::TaskPanicked(name, reason)) => {
+            assert_eq!(name, "event_handler");
+            assert_eq!(reason, "boom");
+        }
+        r => panic!("unexpected {:?}", r),
+    }
+
+    // 忽略停止信号的任务在超时后被中止
+    ob.restart::<Event, Action, Resp>((), "hang", true)
+        .await
+        .unwrap();
+    assert!(matches!(
+        ob.shutdown::<Event, Action, Resp>(true).await,
+        Err(WalleError::ShutdownTimeout(1))
+    ));
+    assert!(ob.task_status::<Event, Action, Resp>().is_empty());
+}
@@ -2,7 +2,10 @@ use std::{collections::HashMap, convert::Infallible, sync::Arc, time::Duration};
 
 use crate::{
     config::{HttpClient, HttpServer},
-    error::{WalleError, WalleResult},
+    error::WalleResult,
+    event::EventKind,
+    obc::components::Signal,
+    obc::net::{HyperClient, Listener, RoutePath},
     prelude::Bot,
     structs::Selft,
     util::{AuthReqHeaderExt, Echo, GetSelf, ProtocolItem},
@@ -16,11 +19,10 @@ use hyper::{
     Method, Request, Response,
 };
 use hyper_util::{
-    client::legacy::{connect::HttpConnector, Client},
     rt::{TokioExecutor, TokioIo},
     server::conn::auto::Builder as ServerAutoBuilder,
 };
-use tokio::{net::TcpListener, task::JoinHandle};
+use tokio::task::JoinHandle;
 use tracing::{info, warn};
 
 use super::{AppOBC, EchoMap};
@@ -34,10 +36,11 @@ where
         &self,
         ob: &Arc<OneBot<AH, EH>>,
         config: Vec<HttpServer>,
+        signal: &Signal,
         tasks: &mut Vec<JoinHandle<()>>,
     ) -> WalleResult<()>
     where
-        E: ProtocolItem + GetSelf + Clone,
+        E: ProtocolItem + GetSelf + Clone + EventKind,
         AH: ActionHandler<E, A, R> + Send + Sync + 'static,
         EH: EventHandler<E, A, R> + Send + Sync + 'static,
     {
@@ -45,14 +48,19 @@ where
             let echo_map = self.echos.clone();
             let path = webhook.path.clone();
             let access_token = webhook.access_token.clone();
-            let mut signal_rx = ob.get_signal_rx()?;
+            let mut signal_rx = signal.subscribe();
             let ob = ob.clone();
-            let addr = std::net::SocketAddr::new(webhook.host, webhook.port);
+            let mut listener = Listener::bind(
+                webhook.host,
+                webhook.port,
+                &webhook.unix,
+                vec![RoutePath::endpoint(&webhook.path)],
+            )
+            .await?;
             info!(
                 target: crate::WALLE_CORE,
-                "Starting HTTP Webhook server on http://{}", addr
+                "Starting HTTP Webhook server on {}", listener.display("http")
             );
-            let listener = TcpListener::bind(&addr).await.map_err(WalleError::from)?;
             let map = self.get_bot_map().clone();
             let serv = service_fn(move |req: Request<Incoming>| {
                 let path = path.clone();
@@ -98,6 +106,12 @@ where
                     let body = String::from_utf8(req.collect().await.unwrap().to_bytes().to_vec())
                         .unwrap();
                     match E::json_decode(&body) {
+                        Ok(event) if event.ty() == "meta" => {
+                            // meta 事件不携带 self 字段，不登记 bot
+                            if let Err(e) = ob.handle_event(event).await {
+                                warn!(target: super::OBC, "{}", e);
+                            }
+                        }
                         Ok(event) => {
                             let (seq, mut action_rx) = map.new_connect();
                             let selft = event.get_self();
@@ -121,7 +135,7 @@ where
                                 let echo_s = a.get_echo();
                                 echo_map.remove(&echo_s);
                                 map.connect_closs(&seq);
-                                return Ok(Response::new(a.json_encode().into()));
+                                return Ok(Response::new(a.json_encode()));
                             }
                         }
                         Err(s) => warn!(target: crate::WALLE_CORE, "Webhook json error: {}", s),
@@ -134,10 +148,12 @@ where
                     let service = serv.clone();
                     tokio::select! {
                         _ = signal_rx.recv() => break,
-                        Ok((tcp_stream, _)) = listener.accept() => {
+                        Some((stream, _)) = listener.accept() => {
                             tokio::spawn(async move {
-                                let io = TokioIo::new(tcp_stream);
-                                ServerAutoBuilder::new(TokioExecutor::new())
+                                let mut builder = ServerAutoBuilder::new(TokioExecutor::new());
+                                builder.http1().keep_alive(!stream.is_routed());
+                                let io = TokioIo::new(stream);
+                                builder
                                     .serve_connection(io, service)
                                     .await
                                     .unwrap();
@@ -152,12 +168,13 @@ where
 
     pub(crate) async fn http<E, AH, EH>(
         &self,
-        ob: &Arc<OneBot<AH, EH>>,
+        _ob: &Arc<OneBot<AH, EH>>,
         config: HashMap<String, HttpClient>,
+        signal: &Signal,
         tasks: &mut Vec<JoinHandle<()>>,
     ) -> WalleResult<()>
     where
-        E: ProtocolItem + GetSelf + Clone,
+        E: ProtocolItem + GetSelf + Clone + EventKind,
         AH: ActionHandler<E, A, R> + Send + Sync + 'static,
         EH: EventHandler<E, A, R> + Send + Sync + 'static,
     {
@@ -175,48 +192,50 @@ where
                 }],
                 &implt,
             );
-            let ob = ob.clone();
             let echo_map = self.echos.clone();
-            let mut signal_rx = ob.get_signal_rx()?;
-            let cli = Client::builder(TokioExecutor::new()).build_http::<String>();
+            let map = self.get_bot_map().clone();
+            let mut signal_rx = signal.subscribe();
+            let cli = HyperClient::new();
             tasks.push(tokio::spawn(async move {
                 loop {
                     tokio::select! {
                         _ = signal_rx.recv() => break,
-                        Some(action) = rx.recv() => {
-                            tokio::spawn(http_push(
-                                action,
-                                http.clone(),
-                                echo_map.clone(),
-                                cli.clone(),
-                            ));
+                        action = rx.recv() => match action {
+                            Some(action) => {
+                                tokio::spawn(http_push(
+                                    action,
+                                    http.clone(),
+                                    echo_map.clone(),
+                                    cli.clone(),
+                                ));
+                            }
+                            None => break,
                         }
                     }
                 }
+                map.connect_closs(&seq);
             }));
         }
         Ok(())
     }
 }
 
-async fn http_push<A, R>(
-    action: Echo<A>,
-    http: HttpClient,
-    echo_map: EchoMap<R>,
-    cli: Client<HttpConnector, String>,
-) where
+async fn http_push<A, R>(action: Echo<A>, http: HttpClient, echo_map: EchoMap<R>, cli: HyperClient)
+where
     A: ProtocolItem,
     R: ProtocolItem,
 {
     let (action, echo_s) = action.unpack();
     let req = Request::builder()
         .method(Method::POST)
-        .uri(&http.url)
         .header_auth_token(&http.access_token)
-        .header(CONTENT_TYPE, crate::util::ContentType::Json.to_string())
-        .body(action.json_encode()) //todo
-        .unwrap();
-    match tokio::time::timeout(Duration::from_secs(http.timeout), cli.request(req)).await {
+        .header(CONTENT_TYPE, crate::util::ContentType::Json.to_string());
+    match tokio::time::timeout(
+        Duration::from_secs(http.timeout),
+        cli.request(&http.url, req, action.json_encode()), //todo
+    )
+    .await
+    {
         Ok(Ok(resp)) => {
             let body = resp.collect().await.unwrap().to_bytes();
             let r: R = serde_json::from_reader(body.reader()).unwrap();
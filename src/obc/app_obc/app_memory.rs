@@ -0,0 +1,105 @@
+use std::sync::Arc;
+
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tracing::{info, trace, warn};
+
+use crate::config::MemoryClient;
+use crate::event::EventKind;
+use crate::obc::components::Signal;
+use crate::obc::memory::{connect, MemoryStream};
+use crate::util::{GetSelf, ProtocolItem};
+use crate::{ActionHandler, EventHandler, OneBot, WalleResult};
+
+use super::{AppOBC, BotMap, EchoMap};
+
+impl<A, R> AppOBC<A, R>
+where
+    A: ProtocolItem,
+    R: ProtocolItem,
+{
+    pub(crate) async fn memory<E, AH, EH>(
+        &self,
+        ob: &Arc<OneBot<AH, EH>>,
+        config: Vec<MemoryClient>,
+        signal: &Signal,
+        tasks: &mut Vec<JoinHandle<()>>,
+    ) -> WalleResult<()>
+    where
+        E: ProtocolItem + GetSelf + Clone + EventKind,
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        for mc in config {
+            info!(target: super::OBC, "Start try connect to memory {}", mc.name);
+            let ob = ob.clone();
+            let echo_map = self.echos.clone();
+            let mut signal_rx = signal.subscribe();
+            let signal = signal.clone();
+            let bot_map = self.get_bot_map().clone();
+            tasks.push(tokio::spawn(async move {
+                while signal_rx.try_recv().is_err() {
+                    match connect(&mc.name) {
+                        Ok(stream) => {
+                            memory_loop(
+                                ob.clone(),
+                                signal.subscribe(),
+                                stream,
+                                echo_map.clone(),
+                                bot_map.clone(),
+                            )
+                            .await;
+                            warn!(target: super::OBC, "Disconnected from memory {}", mc.name);
+                        }
+                        Err(_) => {
+                            tokio::select! {
+                                _ = signal_rx.recv() => break,
+                                _ = tokio::time::sleep(std::time::Duration::from_secs(
+                                    mc.reconnect_interval as u64,
+                                )) => {}
+                            }
+                        }
+                    }
+                }
+            }));
+        }
+        Ok(())
+    }
+}
+
+async fn memory_loop<E, A, R, AH, EH>(
+    ob: Arc<OneBot<AH, EH>>,
+    mut signal_rx: broadcast::Receiver<()>,
+    mut stream: MemoryStream,
+    echo_map: EchoMap<R>,
+    bot_map: Arc<BotMap<A>>,
+) where
+    E: ProtocolItem + GetSelf + Clone + EventKind,
+    A: ProtocolItem,
+    R: ProtocolItem,
+    AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+    EH: EventHandler<E, A, R> + Send + Sync + 'static,
+{
+    let (seq, mut action_rx) = bot_map.new_connect();
+    let mut implt = None;
+    loop {
+        tokio::select! {
+            _ = signal_rx.recv() => break,
+            action = action_rx.recv() => match action {
+                Some(action) => if !stream.send(action.json_encode()) {
+                    break;
+                },
+                // 连接已被移出 BotMap（如心跳超时）
+                None => break,
+            },
+            text = stream.recv() => {
+                let Some(text) = text else { break };
+                trace!(target: crate::WALLE_CORE, "memory recv: {}", text);
+                let item = ProtocolItem::json_decode(&text);
+                let meta = || ProtocolItem::json_decode(&text);
+                super::handle_recv(item, meta, &ob, &echo_map, &seq, &mut implt, &bot_map);
+            }
+        }
+    }
+    bot_map.connect_closs(&seq);
+}
@@ -1,13 +1,16 @@
 use crate::{
-    config::{WebSocketClient, WebSocketServer},
-    error::{WalleError, WalleResult},
-    event::{Event, MetaDetailEvent, MetaTypes},
+    config::{KeepAlive, OneBotVersion, WebSocketClient, WebSocketServer},
+    error::WalleResult,
+    event::EventKind,
+    resp::{resp_error, Resp},
     util::{AuthReqHeaderExt, Echo, GetSelf, ProtocolItem},
     ActionHandler, EventHandler, OneBot,
 };
 use crate::{
     obc::{
-        ws_util::{try_connect, upgrade_websocket},
+        components::{subscribe_conn, Signal},
+        net::{Listener, RoutePath, Stream},
+        ws_util::{try_connect, upgrade_websocket, KeepAliveTick, KeepAliveTimer},
         AppOBC, EchoMap,
     },
     util::ContentType,
@@ -16,9 +19,7 @@ use crate::{
 use std::sync::Arc;
 
 use futures_util::{SinkExt, StreamExt};
-use serde::{Deserialize, Serialize};
-use tokio::net::TcpListener;
-use tokio::net::TcpStream;
+use tokio::sync::broadcast;
 use tokio::task::JoinHandle;
 use tokio_tungstenite::tungstenite::http::{header::USER_AGENT, Request};
 use tokio_tungstenite::tungstenite::Message as WsMsg;
@@ -34,10 +35,11 @@ where
         &self,
         ob: &Arc<OneBot<AH, EH>>,
         config: Vec<WebSocketClient>,
+        signal: &Signal,
         tasks: &mut Vec<JoinHandle<()>>,
     ) -> WalleResult<()>
     where
-        E: ProtocolItem + GetSelf + Clone,
+        E: ProtocolItem + GetSelf + Clone + EventKind,
         AH: ActionHandler<E, A, R> + Send + Sync + 'static,
         EH: EventHandler<E, A, R> + Send + Sync + 'static,
     {
@@ -45,7 +47,8 @@ where
             info!(target: super::OBC, "Start try connect to {}", wsc.url);
             let ob = ob.clone();
             let echo_map = self.echos.clone();
-            let mut signal_rx = ob.get_signal_rx()?;
+            let mut signal_rx = signal.subscribe();
+            let signal = signal.clone();
             let bot_map = self.get_bot_map().clone();
             tasks.push(tokio::spawn(async move {
                 while signal_rx.try_recv().is_err() {
@@ -57,16 +60,31 @@ where
                             format!("OneBot/12 Walle-App/{}", crate::VERSION),
                         )
                         .header_auth_token(&wsc.access_token);
-                    match try_connect(&wsc, req).await {
+                    let ws_stream = tokio::select! {
+                        ws_stream = try_connect(&wsc, req) => ws_stream,
+                        _ = signal_rx.recv() => break,
+                    };
+                    match ws_stream {
                         Some(ws_stream) => {
-                            ws_loop(ob, ws_stream, echo_map, bot_map.clone()).await;
+                            ws_loop(
+                                ob,
+                                signal.subscribe(),
+                                ws_stream,
+                                echo_map,
+                                bot_map.clone(),
+                                wsc.keepalive.clone(),
+                                wsc.onebot_version,
+                            )
+                            .await;
                             warn!(target: crate::WALLE_CORE, "Disconnected from {}", wsc.url);
                         }
                         None => {
-                            tokio::time::sleep(std::time::Duration::from_secs(
-                                wsc.reconnect_interval as u64,
-                            ))
-                            .await;
+                            tokio::select! {
+                                _ = tokio::time::sleep(std::time::Duration::from_secs(
+                                    wsc.reconnect_interval as u64,
+                                )) => {}
+                                _ = signal_rx.recv() => break,
+                            }
                         }
                     }
                 }
@@ -78,38 +96,58 @@ where
         &self,
         ob: &Arc<OneBot<AH, EH>>,
         config: Vec<WebSocketServer>,
+        signal: &Signal,
         tasks: &mut Vec<JoinHandle<()>>,
     ) -> WalleResult<()>
     where
-        E: ProtocolItem + GetSelf + Clone,
+        E: ProtocolItem + GetSelf + Clone + EventKind,
         AH: ActionHandler<E, A, R> + Send + Sync + 'static,
         EH: EventHandler<E, A, R> + Send + Sync + 'static,
     {
         for wss in config {
-            let addr = std::net::SocketAddr::new(wss.host, wss.port);
-            let tcp_listener = TcpListener::bind(&addr).await.map_err(WalleError::IO)?;
+            let mut listener = Listener::bind(
+                wss.host,
+                wss.port,
+                &wss.unix,
+                vec![RoutePath::endpoint(&wss.path)],
+            )
+            .await?;
+            let addr = listener.display("ws");
             info!(
                 target: super::OBC,
-                "Websocket server listening on ws://{}", addr
+                "Websocket server listening on {}", addr
             );
             let ob = ob.clone();
-            let mut signal_rx = ob.get_signal_rx()?;
+            let mut signal_rx = signal.subscribe();
+            let signal = signal.clone();
             let echo_map = self.echos.clone();
             let bot_map = self.get_bot_map().clone();
             tasks.push(tokio::spawn(async move {
                 loop {
                     tokio::select! {
                         _ = signal_rx.recv() => {
-                            info!(target: super::OBC, "Stop listening on ws://{}", addr);
+                            info!(target: super::OBC, "Stop listening on {}", addr);
                             break;
                         }
-                        Ok((stream, _)) = tcp_listener.accept() => {
+                        Some((stream, peer)) = listener.accept() => {
                             if let Some((ws_stream, _implt)) =
-                                upgrade_websocket(&wss.access_token, &wss.path, stream)
+                                upgrade_websocket(&wss.access_token, &wss.path, stream, &peer)
                                     .await
                             {
+                                let Some(conn_signal_rx) = subscribe_conn(&signal, &mut signal_rx) else {
+                                    info!(target: super::OBC, "Stop listening on {}", addr);
+                                    break;
+                                };
                                 let ob = ob.clone();
-                                tokio::spawn(ws_loop(ob.clone(), ws_stream, echo_map.clone(), bot_map.clone()));
+                                tokio::spawn(ws_loop(
+                                    ob.clone(),
+                                    conn_signal_rx,
+                                    ws_stream,
+                                    echo_map.clone(),
+                                    bot_map.clone(),
+                                    wss.keepalive.clone(),
+                                    wss.onebot_version,
+                                ));
                             }
                         }
                     }
@@ -122,31 +160,56 @@ where
 
 async fn ws_loop<E, A, R, AH, EH>(
     ob: Arc<OneBot<AH, EH>>,
-    mut ws_stream: WebSocketStream<TcpStream>,
+    mut signal_rx: broadcast::Receiver<()>,
+    mut ws_stream: WebSocketStream<Stream>,
     echo_map: EchoMap<R>,
     bot_map: Arc<super::BotMap<A>>,
-    // implt: String,
+    keepalive: Option<KeepAlive>,
+    version: OneBotVersion,
 ) where
-    E: ProtocolItem + GetSelf + Clone,
+    E: ProtocolItem + GetSelf + Clone + EventKind,
     A: ProtocolItem,
     R: ProtocolItem,
     AH: ActionHandler<E, A, R> + Send + Sync + 'static,
     EH: EventHandler<E, A, R> + Send + Sync + 'static,
 {
     let (seq, mut action_rx) = bot_map.new_connect();
-    let mut signal_rx = ob.get_signal_rx().unwrap(); //todo
-    let mut implt = None;
+    // OneBot 11 没有 connect 事件，直接以 lifecycle 与 heartbeat 事件中的 self_id 注册 bot
+    let mut implt = (version == OneBotVersion::V11).then(|| V11_IMPLT.to_owned());
+    let mut keepalive = KeepAliveTimer::new(keepalive);
     loop {
         tokio::select! {
             _ = signal_rx.recv() => break,
-            Some(action) = action_rx.recv() => {
-                if ws_stream.send(action.to_ws_msg(&ContentType::Json)).await.is_err() { //todo
+            tick = keepalive.tick() => match tick {
+                KeepAliveTick::Ping => if ws_stream.send(WsMsg::Ping(vec![])).await.is_err() {
+                    break;
+                },
+                KeepAliveTick::Timeout => {
+                    warn!(target: super::OBC, "ws pong timeout, disconnect");
                     break;
                 }
             },
+            action = action_rx.recv() => match action {
+                Some(action) => if let Some(msg) = encode_action(action, version, &echo_map) {
+                    if ws_stream.send(msg).await.is_err() { //todo
+                        break;
+                    }
+                },
+                // 连接已被移出 BotMap（如心跳超时）
+                None => break,
+            },
             Some(msg) = ws_stream.next() => {
-                match msg {
-                    Ok(msg) => if ws_recv(
+                if let Ok(WsMsg::Pong(_)) = msg {
+                    keepalive.pong();
+                }
+                let msg = match msg {
+                    Ok(msg) => decode_msg(msg, version),
+                    Err(_) => {
+                        break;
+                    }
+                };
+                if let Some(msg) = msg {
+                    if ws_recv(
                         msg,
                         &ob,
                         &mut ws_stream,
@@ -156,9 +219,6 @@ async fn ws_loop<E, A, R, AH, EH>(
                         &bot_map,
                     ).await {
                         break;
-                    },
-                    Err(_) => {
-                        break;
                     }
                 }
             }
@@ -171,66 +231,29 @@ async fn ws_loop<E, A, R, AH, EH>(
 async fn ws_recv<E, A, R, AH, EH>(
     msg: WsMsg,
     ob: &Arc<OneBot<AH, EH>>,
-    ws_stream: &mut WebSocketStream<TcpStream>,
+    ws_stream: &mut WebSocketStream<Stream>,
     echo_map: &EchoMap<R>,
     seq: &usize,
     implt: &mut Option<String>,
     bot_map: &Arc<super::BotMap<A>>,
 ) -> bool
 where
-    E: ProtocolItem + Clone + GetSelf,
+    E: ProtocolItem + Clone + GetSelf + EventKind,
     A: ProtocolItem,
     R: ProtocolItem,
     AH: ActionHandler<E, A, R> + Send + Sync + 'static,
     EH: EventHandler<E, A, R> + Send + Sync + 'static,
 {
-    #[derive(Debug, Deserialize, Serialize)]
-    #[serde(untagged)]
-    enum ReceiveItem<E, R> {
-        Event(E),
-        Resp(Echo<R>),
-    }
-
-    let handle_ok = |item: Result<ReceiveItem<E, R>, String>| async move {
-        match item {
-            Ok(ReceiveItem::Event(event)) => {
-                let ob = ob.clone();
-                tokio::spawn(async move { ob.handle_event(event).await });
-            }
-            Ok(ReceiveItem::Resp(resp)) => {
-                let (r, echos) = resp.unpack();
-                if let Some((_, tx)) = echo_map.remove(&echos) {
-                    tx.send(r).ok();
-                }
-            }
-            Err(s) => warn!(target: super::OBC, "serde failed: {}", s),
-        }
-    };
-
-    let meta_process = |meta: Result<Event, String>| async move {
-        if let Ok(event) = meta.and_then(|e: Event| {
-            <MetaDetailEvent as TryFrom<Event>>::try_from(e).map_err(|e| e.to_string())
-        }) {
-            match event.detail_type {
-                MetaTypes::Connect(c) => *implt = Some(c.version.implt),
-                MetaTypes::StatusUpdate(s) => {
-                    if let Some(some_implt) = implt {
-                        bot_map.connect_update(seq, s.status.bots, some_implt)
-                    }
-                }
-                _ => {}
-            }
-        }
-    };
-
     match msg {
         WsMsg::Text(text) => {
-            meta_process(ProtocolItem::json_decode(&text)).await;
-            handle_ok(ProtocolItem::json_decode(&text)).await;
+            let item = ProtocolItem::json_decode(&text);
+            let meta = || ProtocolItem::json_decode(&text);
+            super::handle_recv(item, meta, ob, echo_map, seq, implt, bot_map);
         }
         WsMsg::Binary(b) => {
-            meta_process(ProtocolItem::rmp_decode(&b)).await;
-            handle_ok(ProtocolItem::rmp_decode(&b)).await;
+            let item = ProtocolItem::rmp_decode(&b);
+            let meta = || ProtocolItem::rmp_decode(&b);
+            super::handle_recv(item, meta, ob, echo_map, seq, implt, bot_map);
         }
         WsMsg::Ping(b) => {
             if ws_stream.send(WsMsg::Pong(b)).await.is_err() {
@@ -243,3 +266,81 @@ where
     }
     false
 }
+
+const V11_IMPLT: &str = "onebot11";
+
+/// OneBot 11 连接发送前将 action 转换为 OneBot 11 动作
+///
+/// 转换失败时不发送，直接以错误响应回复该 action
+fn encode_action<A: ProtocolItem, R: ProtocolItem>(
+    action: Echo<A>,
+    version: OneBotVersion,
+    echo_map: &EchoMap<R>,
+) -> Option<WsMsg> {
+    let error = match version {
+        OneBotVersion::V12 => return Some(action.to_ws_msg(&ContentType::Json)),
+        #[cfg(feature = "v11")]
+        OneBotVersion::V11 => match crate::v11::ws::action_from_v12(&action) {
+            Ok(text) => return Some(WsMsg::Text(text)),
+            Err(e) => format!("convert action to OneBot 11 failed: {}", e),
+        },
+        #[cfg(not(feature = "v11"))]
+        OneBotVersion::V11 => "OneBot 11 requires feature v11".to_owned(),
+    };
+    warn!(target: super::OBC, "{}", error);
+    if let Some((_, tx)) = echo_map.remove(&action.get_echo()) {
+        match R::json_decode(&Resp::from(resp_error::bad_request(error)).json_encode()) {
+            Ok(resp) => {
+                tx.send(resp).ok();
+            }
+            Err(e) => warn!(target: super::OBC, "build error resp failed: {}", e),
+        }
+    }
+    None
+}
+
+/// OneBot 11 连接收到的事件与响应转换为 OneBot 12 json，转换失败返回 None
+fn decode_msg(msg: WsMsg, version: OneBotVersion) -> Option<WsMsg> {
+    match (version, msg) {
+        (OneBotVersion::V12, msg) => Some(msg),
+        #[cfg(feature = "v11")]
+        (OneBotVersion::V11, WsMsg::Text(text)) => crate::v11::ws::recv_to_v12(&text)
+            .map(WsMsg::Text)
+            .map_err(|e| warn!(target: super::OBC, "convert OneBot 11 message failed: {}", e))
+            .ok(),
+        #[cfg(feature = "v11")]
+        (OneBotVersion::V11, msg) => Some(msg),
+        #[cfg(not(feature = "v11"))]
+        (OneBotVersion::V11, _) => {
+            warn!(target: super::OBC, "OneBot 11 requires feature v11");
+            None
+        }
+    }
+}
+
+#[cfg(feature = "v11")]
+#[test]
+fn encode_action_test() {
+    use crate::util::{EchoInner, EchoS};
+    use crate::value_map;
+
+    let echo_map: EchoMap<Resp> = Default::default();
+    let (tx, mut rx) = tokio::sync::oneshot::channel();
+    let echo = EchoS(Some(EchoInner::S("1".to_owned())));
+    echo_map.insert(echo.clone(), tx);
+    // 无法转换为 OneBot 11 的 action 不发送，回复错误响应
+    let action = echo.pack(value_map! { "params": {} });
+    assert!(encode_action(action, OneBotVersion::V11, &echo_map).is_none());
+    assert_eq!(rx.try_recv().unwrap().retcode, 10001);
+    assert!(echo_map.is_empty());
+
+    let action = echo.pack(crate::action::Action {
+        action: "get_self_info".to_owned(),
+        params: Default::default(),
+        selft: None,
+    });
+    assert!(matches!(
+        encode_action(action, OneBotVersion::V11, &echo_map),
+        Some(WsMsg::Text(text)) if text.contains("get_login_info")
+    ));
+}
@@ -1,21 +1,35 @@
 use std::collections::HashSet;
-use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
-use std::sync::{Arc, OnceLock};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex as StdMutex, OnceLock};
+use std::time::{Duration, Instant};
 
+#[cfg(any(feature = "http", feature = "websocket"))]
+use super::components::listen_name;
+use super::components::Components;
+use super::queue::{channel, QueueReceiver, QueueSender};
 use super::OBC;
 use crate::ah::GenStatus;
-use crate::util::{Echo, EchoInner, EchoS, GetSelf, ProtocolItem};
-use crate::{structs, ActionHandler, EventHandler, OneBot};
+#[cfg(feature = "http")]
+use crate::config::{HttpClient, HttpServer};
+use crate::config::{MemoryClient, QueueConfig};
+#[cfg(feature = "websocket")]
+use crate::config::{WebSocketClient, WebSocketServer};
+use crate::event::{Event, EventKind, MetaDetailEvent, MetaTypes};
+use crate::lifecycle::{RestartPolicy, TaskStatus};
+use crate::util::{Echo, EchoInner, EchoS, GetSelf, ProtocolItem, ValueMapExt};
+use crate::{structs, value_map, ActionHandler, EventHandler, OneBot};
 use crate::{WalleError, WalleResult};
 
 use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
 use structs::{Bot, Selft};
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::{broadcast, oneshot};
 use tokio::task::JoinHandle;
 use tracing::{info, warn};
 
 #[cfg(feature = "http")]
 mod app_http;
+mod app_memory;
 #[cfg(feature = "websocket")]
 mod app_ws;
 
@@ -25,19 +39,44 @@ pub(crate) type EchoMap<R> = Arc<DashMap<EchoS, oneshot::Sender<R>>>;
 ///
 /// AppOBC impl ActionHandler 接收 Action 并外发处理
 ///
-/// Event 泛型要求实现 Clone + SelfId trait
-/// Action 泛型要求实现 SelfId + ActionType trait
+/// Event 泛型要求实现 Clone + GetSelf + EventKind trait
+/// Action 泛型要求实现 GetSelf trait
 pub struct AppOBC<A, R> {
-    pub(crate) _block_meta_event: AtomicBool,   //todo
-    pub(crate) echos: EchoMap<R>,               // echo channel sender 暂存 Map
-    pub(crate) seq: AtomicU64,                  // 用于生成 echo
+    pub(crate) _block_meta_event: AtomicBool, // 是否拦截 meta 事件
+    pub(crate) echos: EchoMap<R>,             // echo channel sender 暂存 Map
+    pub(crate) seq: AtomicU64,                // 用于生成 echo
     pub(crate) _bots: OnceLock<Arc<BotMap<A>>>, // Bot action channel map
+    pub(crate) components: Arc<Components>,   // 运行中的监听与连接
+    pub(crate) applied: StdMutex<Option<crate::config::AppConfig>>, // 最近一次应用的配置
+    pub(crate) action_timeout: Duration,      // action 发送与等待响应的超时
+}
+
+/// AppOBC 默认的 action 超时
+pub const DEFAULT_ACTION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// AppOBC 运行组件，每个监听、连接设置项与心跳检测各为一个组件
+enum AppComponent {
+    #[cfg(feature = "websocket")]
+    WebSocket(WebSocketClient),
+    #[cfg(feature = "websocket")]
+    WebSocketRev(WebSocketServer),
+    Memory(MemoryClient),
+    #[cfg(feature = "http")]
+    Http(String, HttpClient),
+    #[cfg(feature = "http")]
+    HttpWebhook(HttpServer),
+    HeartbeatWatchdog,
 }
 
 impl<A, R> AppOBC<A, R> {
     pub fn new() -> Self {
         Default::default()
     }
+    /// 设置 action 超时，action 队列已满无法发送或超时未收到响应时返回错误
+    pub fn with_action_timeout(mut self, timeout: Duration) -> Self {
+        self.action_timeout = timeout;
+        self
+    }
     pub fn block_meta_event(&self, b: bool) {
         self._block_meta_event.swap(b, Ordering::Relaxed);
     }
@@ -58,6 +97,9 @@ impl<A, R> Default for AppOBC<A, R> {
             echos: Arc::new(DashMap::new()),
             seq: AtomicU64::default(),
             _bots: OnceLock::new(),
+            components: Arc::default(),
+            applied: StdMutex::default(),
+            action_timeout: DEFAULT_ACTION_TIMEOUT,
         }
     }
 }
@@ -79,22 +121,14 @@ impl<A, R> GenStatus for AppOBC<A, R> {
     fn gen_status(&self) -> structs::Status {
         structs::Status {
             good: true, // todo
-            bots: self
-                .get_bot_map()
-                .bots
-                .iter()
-                .map(|i| structs::Bot {
-                    selft: i.key().clone(),
-                    online: !i.value().1.is_empty(),
-                })
-                .collect(),
+            bots: self.get_bot_map().bots_status(),
         }
     }
 }
 
 impl<E, A, R> ActionHandler<E, A, R> for AppOBC<A, R>
 where
-    E: ProtocolItem + Clone + GetSelf,
+    E: ProtocolItem + Clone + GetSelf + EventKind,
     A: ProtocolItem + GetSelf,
     R: ProtocolItem,
 {
@@ -108,52 +142,81 @@ where
         AH: ActionHandler<E, A, R> + Send + Sync + 'static,
         EH: EventHandler<E, A, R> + Send + Sync + 'static,
     {
-        let mut tasks = vec![];
-        #[cfg(feature = "websocket")]
-        {
-            self.wsr(ob, config.websocket_rev, &mut tasks).await?;
-            self.ws(ob, config.websocket, &mut tasks).await?;
+        self.apply_config(ob, config).await?;
+        Ok(vec![self.components.supervise(ob.get_signal_rx()?)])
+    }
+    async fn reconfigure<AH, EH>(
+        &self,
+        ob: &Arc<OneBot<AH, EH>>,
+        config: crate::config::AppConfig,
+    ) -> WalleResult<()>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        self.apply_config(ob, config).await
+    }
+    fn validate_config(&self, config: &crate::config::AppConfig) -> WalleResult<()> {
+        crate::config::LoadConfig::validate(config)
+    }
+    fn task_status(&self) -> Vec<TaskStatus> {
+        self.components.status()
+    }
+    async fn restart_failed<AH, EH>(&self, ob: &Arc<OneBot<AH, EH>>, policy: &RestartPolicy)
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        if !self.components.reap(policy).await {
+            return;
         }
-        #[cfg(feature = "http")]
-        {
-            self.webhook(ob, config.http_webhook, &mut tasks).await?;
-            self.http(ob, config.http, &mut tasks).await?;
+        let applied = self.applied.lock().unwrap().clone();
+        if let Some(config) = applied {
+            if let Err(e) = self.apply_config(ob, config).await {
+                warn!(target: OBC, "restart components failed: {}", e);
+            }
         }
-        Ok(tasks)
     }
     async fn call<AH, EH>(&self, action: A, _ob: &Arc<OneBot<AH, EH>>) -> WalleResult<R>
     where
         AH: ActionHandler<E, A, R> + Send + Sync + 'static,
         EH: EventHandler<E, A, R> + Send + Sync + 'static,
     {
-        match self.get_bot_map().get_bot_tx(&action.get_self()) {
-            Some(action_txs) => {
-                let (tx, rx) = oneshot::channel();
-                let seq = self.next_seg();
-                self.echos.insert(seq.clone(), tx);
-                action_txs
-                    .first()
-                    .unwrap() //todo
-                    .send(seq.pack(action))
-                    .map_err(|e| {
-                        warn!(target: super::OBC, "send action error: {}", e);
-                        WalleError::Other(e.to_string())
-                    })?;
-                match tokio::time::timeout(std::time::Duration::from_secs(10), rx).await {
-                    Ok(Ok(res)) => Ok(res),
-                    Ok(Err(e)) => {
-                        warn!(target: super::OBC, "resp recv error: {:?}", e);
-                        Err(WalleError::Other(e.to_string()))
-                    }
-                    Err(_) => {
-                        warn!(target: super::OBC, "resp timeout");
-                        Err(WalleError::Other("resp timeout".to_string()))
-                    }
-                }
+        let Some(action_tx) = self
+            .get_bot_map()
+            .get_bot_tx(&action.get_self())
+            .and_then(|txs| txs.into_iter().next())
+        else {
+            warn!(target: super::OBC, "bot not found");
+            return Err(WalleError::BotNotExist);
+        };
+        let (tx, rx) = oneshot::channel();
+        let seq = self.next_seg();
+        self.echos.insert(seq.clone(), tx);
+        let deadline = tokio::time::Instant::now() + self.action_timeout;
+        match tokio::time::timeout_at(deadline, action_tx.send(seq.pack(action))).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                warn!(target: super::OBC, "send action error: {}", e);
+                self.echos.remove(&seq);
+                return Err(e);
             }
-            None => {
-                warn!(target: super::OBC, "bot not found");
-                Err(WalleError::BotNotExist)
+            Err(_) => {
+                warn!(target: super::OBC, "send action timeout");
+                self.echos.remove(&seq);
+                return Err(WalleError::ActionSendError);
+            }
+        }
+        match tokio::time::timeout_at(deadline, rx).await {
+            Ok(Ok(res)) => Ok(res),
+            Ok(Err(e)) => {
+                warn!(target: super::OBC, "resp recv error: {:?}", e);
+                Err(WalleError::Other(e.to_string()))
+            }
+            Err(_) => {
+                warn!(target: super::OBC, "resp timeout");
+                self.echos.remove(&seq);
+                Err(WalleError::Other("resp timeout".to_string()))
             }
         }
     }
@@ -162,20 +225,256 @@ where
         event: E,
         _ob: &Arc<OneBot<AH, EH>>,
     ) -> WalleResult<E> {
-        if self._block_meta_event.load(Ordering::Relaxed) {
-            use core::any::Any;
-            let event: Box<dyn Any> = Box::new(event.clone());
-            if let Ok(ty) = event.downcast::<crate::event::Event>().map(|e| e.ty) {
-                if &ty == "meta" {
-                    return Err(WalleError::Other("blocked".to_string()));
+        if self._block_meta_event.load(Ordering::Relaxed) && event.ty() == "meta" {
+            return Err(WalleError::Other("blocked".to_string()));
+        }
+        Ok(event)
+    }
+}
+
+impl<A, R> AppOBC<A, R>
+where
+    A: ProtocolItem + GetSelf,
+    R: ProtocolItem,
+{
+    /// 对比运行中的组件，停止被移除或变化的组件后启动新组件
+    ///
+    /// action 队列设置仅对之后建立的连接生效
+    async fn apply_config<E, AH, EH>(
+        &self,
+        ob: &Arc<OneBot<AH, EH>>,
+        config: crate::config::AppConfig,
+    ) -> WalleResult<()>
+    where
+        E: ProtocolItem + Clone + GetSelf + EventKind,
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        let _reload = self.components.lock().await;
+        *self.applied.lock().unwrap() = Some(config.clone());
+        if let Some(b) = config.block_meta_event {
+            self.block_meta_event(b);
+        }
+        *self.get_bot_map().queue.lock().unwrap() = config.action_queue.unwrap_or_default();
+        let multiple = config.heartbeat_timeout_multiple.unwrap_or_default();
+        self.get_bot_map()
+            .hb_timeout_multiple
+            .store(multiple, Ordering::Relaxed);
+        let mut wanted = vec![];
+        if multiple > 0 {
+            wanted.push((
+                Components::key("heartbeat_watchdog", &()),
+                AppComponent::HeartbeatWatchdog,
+            ));
+        }
+        wanted.extend(
+            config
+                .memory
+                .into_iter()
+                .map(|c| (Components::key("memory", &c), AppComponent::Memory(c))),
+        );
+        #[cfg(feature = "websocket")]
+        {
+            wanted.extend(config.websocket_rev.into_iter().map(|c| {
+                let key = Components::key("websocket_rev", &c);
+                (key, AppComponent::WebSocketRev(c))
+            }));
+            wanted.extend(
+                config
+                    .websocket
+                    .into_iter()
+                    .map(|c| (Components::key("websocket", &c), AppComponent::WebSocket(c))),
+            );
+        }
+        #[cfg(feature = "http")]
+        {
+            wanted.extend(config.http_webhook.into_iter().map(|c| {
+                let key = Components::key("http_webhook", &c);
+                (key, AppComponent::HttpWebhook(c))
+            }));
+            let mut http: Vec<_> = config.http.into_iter().collect();
+            http.sort_by(|a, b| a.0.cmp(&b.0));
+            wanted.extend(http.into_iter().map(|(bot_id, c)| {
+                let key = Components::key("http", &(&bot_id, &c));
+                (key, AppComponent::Http(bot_id, c))
+            }));
+        }
+        for (key, component) in self.components.retain(wanted).await {
+            let signal = self.components.starting(&key, component.name());
+            let mut tasks = vec![];
+            let result = match component {
+                AppComponent::HeartbeatWatchdog => {
+                    let signal_rx = signal.subscribe();
+                    tasks.push(start_hb_watchdog(ob, self.get_bot_map().clone(), signal_rx));
+                    Ok(())
                 }
+                AppComponent::Memory(c) => self.memory(ob, vec![c], &signal, &mut tasks).await,
+                #[cfg(feature = "websocket")]
+                AppComponent::WebSocketRev(c) => self.wsr(ob, vec![c], &signal, &mut tasks).await,
+                #[cfg(feature = "websocket")]
+                AppComponent::WebSocket(c) => self.ws(ob, vec![c], &signal, &mut tasks).await,
+                #[cfg(feature = "http")]
+                AppComponent::HttpWebhook(c) => {
+                    self.webhook(ob, vec![c], &signal, &mut tasks).await
+                }
+                #[cfg(feature = "http")]
+                AppComponent::Http(bot_id, c) => {
+                    let config = std::collections::HashMap::from([(bot_id, c)]);
+                    self.http(ob, config, &signal, &mut tasks).await
+                }
+            };
+            self.components
+                .started(&key, tasks, result, &ob.restart_policy)?;
+        }
+        Ok(())
+    }
+}
+
+impl AppComponent {
+    fn name(&self) -> String {
+        match self {
+            #[cfg(feature = "websocket")]
+            Self::WebSocket(c) => format!("websocket {}", c.url),
+            #[cfg(feature = "websocket")]
+            Self::WebSocketRev(c) => {
+                listen_name("websocket_rev", &c.host, c.port, &c.path, &c.unix)
             }
+            Self::Memory(c) => format!("memory {}", c.name),
+            #[cfg(feature = "http")]
+            Self::Http(bot_id, c) => format!("http {} {}", bot_id, c.url),
+            #[cfg(feature = "http")]
+            Self::HttpWebhook(c) => listen_name("http_webhook", &c.host, c.port, &c.path, &c.unix),
+            Self::HeartbeatWatchdog => "heartbeat_watchdog".to_owned(),
         }
-        Ok(event)
     }
 }
 
-type BotContent<A> = (String, Vec<mpsc::UnboundedSender<Echo<A>>>);
+/// 定期检查所有连接的心跳，移除超时连接并产生一个所有 bot 均离线的 `meta.status_update` 事件
+fn start_hb_watchdog<E, A, R, AH, EH>(
+    ob: &Arc<OneBot<AH, EH>>,
+    bot_map: Arc<BotMap<A>>,
+    mut signal_rx: broadcast::Receiver<()>,
+) -> JoinHandle<()>
+where
+    E: ProtocolItem,
+    A: Send + Sync + 'static,
+    AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+    EH: EventHandler<E, A, R> + Send + Sync + 'static,
+{
+    let ob = ob.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = signal_rx.recv() => break,
+                _ = tokio::time::sleep(Duration::from_secs(1)) => {
+                    for seq in bot_map.expired_conns() {
+                        warn!(target: OBC, "Connection {} heartbeat timeout, disconnect", seq);
+                        let selfts = bot_map.connect_closs(&seq);
+                        if selfts.is_empty() {
+                            continue;
+                        }
+                        let event = Event {
+                            id: crate::util::new_uuid(),
+                            time: crate::util::timestamp_nano_f64(),
+                            ty: "meta".to_owned(),
+                            detail_type: "status_update".to_owned(),
+                            sub_type: "".to_owned(),
+                            extra: value_map! {
+                                "status": structs::Status {
+                                    good: true,
+                                    bots: selfts
+                                        .into_iter()
+                                        .map(|selft| Bot { selft, online: false })
+                                        .collect(),
+                                }
+                            },
+                        };
+                        match E::json_decode(&event.json_encode()) {
+                            // AppOBC 自身产生的事件，不经过 meta 事件拦截直接交给 EventHandler
+                            Ok(event) => {
+                                let ob = ob.clone();
+                                tokio::spawn(async move {
+                                    if let Err(e) = ob.event_handler.call(event, &ob).await {
+                                        warn!(target: OBC, "handle disconnect event failed: {}", e);
+                                    }
+                                });
+                            }
+                            Err(e) => warn!(target: OBC, "build disconnect event failed: {}", e),
+                        }
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// 连接收到的 Event 或 Resp
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub(crate) enum ReceiveItem<E, R> {
+    Event(E),
+    Resp(Echo<R>),
+}
+
+/// 处理连接收到的 Event 或 Resp
+///
+/// `meta` 事件将以标准 Event 再解析一次，用于记录实现端与更新 BotMap
+pub(crate) fn handle_recv<E, A, R, AH, EH>(
+    item: Result<ReceiveItem<E, R>, String>,
+    meta: impl FnOnce() -> Result<Event, String>,
+    ob: &Arc<OneBot<AH, EH>>,
+    echo_map: &EchoMap<R>,
+    seq: &usize,
+    implt: &mut Option<String>,
+    bot_map: &BotMap<A>,
+) where
+    E: ProtocolItem + EventKind,
+    A: ProtocolItem,
+    R: ProtocolItem,
+    AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+    EH: EventHandler<E, A, R> + Send + Sync + 'static,
+{
+    // 仅当事件 type 为 meta 时才需要以标准 Event 再解析一次
+    if matches!(&item, Ok(ReceiveItem::Event(e)) if e.ty() == "meta") {
+        if let Ok(event) = meta().and_then(|e: Event| {
+            <MetaDetailEvent as TryFrom<Event>>::try_from(e).map_err(|e| e.to_string())
+        }) {
+            match event.detail_type {
+                MetaTypes::Connect(c) => *implt = Some(c.version.implt),
+                MetaTypes::Heartbeat(hb) => {
+                    bot_map.heartbeat(seq, hb.interval);
+                    // heartbeat 同样携带 status 字段
+                    if let (Some(some_implt), Ok(status)) = (
+                        implt.as_ref(),
+                        event.extra.get_downcast::<structs::Status>("status"),
+                    ) {
+                        bot_map.connect_update(seq, status.bots, some_implt)
+                    }
+                }
+                MetaTypes::StatusUpdate(s) => {
+                    if let Some(some_implt) = implt {
+                        bot_map.connect_update(seq, s.status.bots, some_implt)
+                    }
+                }
+            }
+        }
+    }
+    match item {
+        Ok(ReceiveItem::Event(event)) => {
+            let ob = ob.clone();
+            tokio::spawn(async move { ob.handle_event(event).await });
+        }
+        Ok(ReceiveItem::Resp(resp)) => {
+            let (r, echos) = resp.unpack();
+            if let Some((_, tx)) = echo_map.remove(&echos) {
+                tx.send(r).ok();
+            }
+        }
+        Err(s) => warn!(target: OBC, "serde failed: {}", s),
+    }
+}
+
+type BotContent<A> = (String, Vec<QueueSender<Echo<A>>>);
 
 #[derive(Debug)]
 pub struct BotMap<A> {
@@ -188,7 +487,15 @@ pub struct BotMap<A> {
     /// 根据连接序列号获取其 action_tx 和 所有 bot self
     ///
     /// value: (action_tx, selfts)
-    conns: DashMap<usize, (mpsc::UnboundedSender<Echo<A>>, HashSet<Selft>)>,
+    conns: DashMap<usize, (QueueSender<Echo<A>>, HashSet<Selft>)>,
+    /// 根据连接序列号获取其最后一次心跳时间与心跳间隔（毫秒）
+    ///
+    /// 从未收到心跳的连接不会被检测
+    heartbeats: DashMap<usize, (Instant, u32)>,
+    /// 心跳超时倍数，为 0 则不检测
+    hb_timeout_multiple: AtomicU32,
+    /// 新连接的 action 队列设置
+    queue: StdMutex<QueueConfig>,
 }
 
 impl<A> Default for BotMap<A> {
@@ -197,54 +504,148 @@ impl<A> Default for BotMap<A> {
             conn_seq: AtomicUsize::default(),
             bots: DashMap::default(),
             conns: DashMap::default(),
+            heartbeats: DashMap::default(),
+            hb_timeout_multiple: AtomicU32::default(),
+            queue: StdMutex::default(),
         }
     }
 }
 
 impl<A> BotMap<A> {
     /// 登记一个新链接，返回新链接的 conn_seq，并返回一个接收 Echo<A> 的 Receiver
-    fn new_connect(&self) -> (usize, mpsc::UnboundedReceiver<Echo<A>>) {
+    fn new_connect(&self) -> (usize, QueueReceiver<Echo<A>>) {
         let seq = self.conn_seq.fetch_add(1, Ordering::Relaxed);
-        let (tx, rx) = mpsc::unbounded_channel();
+        let (tx, rx) = channel(&self.queue.lock().unwrap());
         self.conns.insert(seq, (tx, HashSet::default()));
         (seq, rx)
     }
-    /// 根据 conn_seq 关闭一个链接，并移除所有相关的 bot 的 action_tx
-    fn connect_closs(&self, tx_seq: &usize) {
-        if let Some(selfts) = self.conns.remove(tx_seq) {
-            for selft in selfts.1 .1 {
-                let mut bot = self.bots.get_mut(&selft).unwrap();
-                bot.value_mut()
-                    .1
-                    .retain(|htx| !htx.same_channel(&selfts.1 .0));
-                if bot.value().1.is_empty() {
-                    drop(bot);
-                    self.bots.remove(&selft);
+    /// 根据 conn_seq 关闭一个链接，并移除所有相关的 bot 的 action_tx，返回该连接上的所有 bot self
+    fn connect_closs(&self, tx_seq: &usize) -> HashSet<Selft> {
+        self.heartbeats.remove(tx_seq);
+        if let Some((_, (tx, selfts))) = self.conns.remove(tx_seq) {
+            for selft in &selfts {
+                // connect_update 可能尚未将该连接加入 bots
+                if let Some(mut bot) = self.bots.get_mut(selft) {
+                    bot.value_mut().1.retain(|htx| !htx.same_channel(&tx));
+                    if bot.value().1.is_empty() {
+                        drop(bot);
+                        self.bots.remove(selft);
+                    }
                 }
             }
+            selfts
+        } else {
+            HashSet::default()
+        }
+    }
+    /// 记录一个连接收到的心跳
+    fn heartbeat(&self, tx_seq: &usize, interval: u32) {
+        if self.conns.contains_key(tx_seq) {
+            self.heartbeats.insert(*tx_seq, (Instant::now(), interval));
         }
     }
+    /// 心跳是否超时
+    fn hb_expired(&self, (last, interval): (Instant, u32)) -> bool {
+        let multiple = self.hb_timeout_multiple.load(Ordering::Relaxed);
+        multiple != 0 && last.elapsed() > Duration::from_millis(interval as u64 * multiple as u64)
+    }
+    /// 连接心跳是否超时
+    fn is_expired(&self, tx_seq: &usize) -> bool {
+        let hb = self.heartbeats.get(tx_seq).map(|hb| *hb.value());
+        hb.is_some_and(|hb| self.hb_expired(hb))
+    }
+    /// 所有心跳超时的连接
+    fn expired_conns(&self) -> Vec<usize> {
+        let heartbeats: Vec<_> = self
+            .heartbeats
+            .iter()
+            .map(|i| (*i.key(), *i.value()))
+            .collect();
+        heartbeats
+            .into_iter()
+            .filter(|(_, hb)| self.hb_expired(*hb))
+            .map(|(seq, _)| seq)
+            .collect()
+    }
+    /// bot 是否存在至少一个心跳未超时的连接
+    fn bot_alive(&self, bot: &Selft) -> bool {
+        // 先收集连接序列号，避免同时持有 conns 与 heartbeats 的锁
+        let seqs: Vec<usize> = self
+            .conns
+            .iter()
+            .filter(|i| i.value().1.contains(bot))
+            .map(|i| *i.key())
+            .collect();
+        seqs.iter().any(|seq| !self.is_expired(seq))
+    }
+    /// 所有 bot 的在线状态
+    fn bots_status(&self) -> Vec<Bot> {
+        // 先收集 bot 列表，避免同时持有 bots 与 conns 的锁
+        let bots: Vec<(Selft, bool)> = self
+            .bots
+            .iter()
+            .map(|i| (i.key().clone(), !i.value().1.is_empty()))
+            .collect();
+        bots.into_iter()
+            .map(|(selft, has_tx)| Bot {
+                online: has_tx && self.bot_alive(&selft),
+                selft,
+            })
+            .collect()
+    }
     /// 更新一个连接的 bot 列表
+    ///
+    /// 加锁顺序固定为先 bots 后 conns，除此处外不会同时持有两者的锁
     fn connect_update(&self, tx_seq: &usize, bots: Vec<Bot>, implt: &str) {
-        let mut get = self.conns.get_mut(tx_seq).unwrap();
-        let tx = get.0.clone();
-        let selfts = &mut get.1;
-        for bot in bots {
-            match (bot.online, selfts.contains(&bot.selft)) {
-                (true, false) => {
-                    selfts.insert(bot.selft.clone());
-                    self.bots
+        // 先更新 conns 并释放其锁，再更新 bots
+        let (tx, changed) = {
+            let Some(mut get) = self.conns.get_mut(tx_seq) else {
+                // 连接已关闭
+                return;
+            };
+            let tx = get.0.clone();
+            let selfts = &mut get.1;
+            let mut changed = vec![];
+            for bot in bots {
+                match (bot.online, selfts.contains(&bot.selft)) {
+                    (true, false) => {
+                        selfts.insert(bot.selft.clone());
+                        changed.push(bot);
+                    }
+                    (false, true) => {
+                        selfts.remove(&bot.selft);
+                        changed.push(bot);
+                    }
+                    _ => {}
+                }
+            }
+            (tx, changed)
+        };
+        for bot in changed {
+            match bot.online {
+                true => {
+                    let mut entry = self
+                        .bots
                         .entry(bot.selft.clone())
-                        .or_insert((implt.to_string(), vec![]))
-                        .1
-                        .push(tx.clone());
+                        .or_insert((implt.to_string(), vec![]));
+                    // 持有 bots 的锁时确认连接仍未关闭，connect_closs 先移除 conns 再等待该锁，
+                    // 因此不会留下已关闭连接的 action_tx
+                    if !self.conns.contains_key(tx_seq) {
+                        let empty = entry.1.is_empty();
+                        drop(entry);
+                        if empty {
+                            self.bots.remove_if(&bot.selft, |_, v| v.1.is_empty());
+                        }
+                        return;
+                    }
+                    entry.1.push(tx.clone());
+                    drop(entry);
                     info!(
                         target: OBC,
                         "New Bot connected: {}-{}", bot.selft.platform, bot.selft.user_id
                     );
                 }
-                (false, true) => {
-                    selfts.remove(&bot.selft);
+                false => {
                     if let Some(mut bots) = self.bots.get_mut(&bot.selft) {
                         bots.value_mut().1.retain(|htx| !htx.same_channel(&tx));
                         if bots.1.is_empty() {
@@ -257,12 +658,11 @@ impl<A> BotMap<A> {
                         "Bot disconnected: {}-{}", bot.selft.platform, bot.selft.user_id
                     );
                 }
-                _ => {}
             }
         }
     }
     /// 获取一个 bot 的 action_tx
-    fn get_bot_tx(&self, bot: &Selft) -> Option<Vec<mpsc::UnboundedSender<Echo<A>>>> {
+    fn get_bot_tx(&self, bot: &Selft) -> Option<Vec<QueueSender<Echo<A>>>> {
         self.bots.get(bot).as_deref().cloned().map(|v| v.1)
     }
 }
@@ -275,10 +675,7 @@ fn test_bot_map() {
     let (seq, _) = map.new_connect();
     assert_eq!(seq, 1);
     assert_eq!(
-        map.conns
-            .iter()
-            .map(|i| i.key().clone())
-            .collect::<HashSet<_>>(),
+        map.conns.iter().map(|i| *i.key()).collect::<HashSet<_>>(),
         HashSet::from([1, 0])
     );
     let self0 = Selft {
@@ -303,3 +700,286 @@ fn test_bot_map() {
     assert!(map.get_bot_tx(&self0).is_some());
     assert!(map.get_bot_tx(&self1).is_none());
 }
+
+#[tokio::test]
+async fn action_timeout_test() {
+    use crate::action::Action;
+    use crate::config::OverflowPolicy;
+    use crate::event::Event;
+    use crate::resp::Resp;
+    use crate::testing::MockEventHandler;
+
+    let ob = Arc::new(OneBot::new(
+        AppOBC::<Action, Resp>::new().with_action_timeout(Duration::from_millis(50)),
+        MockEventHandler::new(),
+        structs::Version {
+            implt: "test".to_owned(),
+            version: crate::VERSION.to_owned(),
+            onebot_version: 12.to_string(),
+        },
+    ));
+    let map = ob.action_handler.get_bot_map();
+    *map.queue.lock().unwrap() = QueueConfig {
+        capacity: 1,
+        overflow: OverflowPolicy::Block,
+    };
+    let selft = Selft {
+        platform: "test".to_owned(),
+        user_id: "bot".to_owned(),
+    };
+    let (seq, _rx) = map.new_connect();
+    map.connect_update(
+        &seq,
+        vec![Bot {
+            selft: selft.clone(),
+            online: true,
+        }],
+        "test",
+    );
+    let action = || Action {
+        action: "get_self_info".to_owned(),
+        params: Default::default(),
+        selft: Some(selft.clone()),
+    };
+    // 第一个 action 进入队列后等待响应超时，第二个 action 因队列已满发送超时
+    assert!(matches!(
+        ob.handle_action::<Event, Action, Resp>(action()).await,
+        Err(WalleError::Other(e)) if e == "resp timeout"
+    ));
+    assert!(matches!(
+        ob.handle_action::<Event, Action, Resp>(action()).await,
+        Err(WalleError::ActionSendError)
+    ));
+    assert!(ob.action_handler.echos.is_empty());
+}
+
+#[test]
+fn test_bot_map_heartbeat() {
+    let map = BotMap::<crate::action::Action>::default();
+    map.hb_timeout_multiple.store(1, Ordering::Relaxed);
+    let (seq, _) = map.new_connect();
+    let selft = Selft {
+        platform: "".to_owned(),
+        user_id: "0".to_owned(),
+    };
+    map.connect_update(
+        &seq,
+        vec![Bot {
+            selft: selft.clone(),
+            online: true,
+        }],
+        "",
+    );
+    // 从未收到心跳的连接不检测
+    assert!(map.expired_conns().is_empty());
+    assert!(map.bot_alive(&selft));
+    map.heartbeat(&seq, 0);
+    std::thread::sleep(Duration::from_millis(1));
+    assert_eq!(map.expired_conns(), vec![seq]);
+    assert!(!map.bot_alive(&selft));
+    assert_eq!(map.connect_closs(&seq), HashSet::from([selft.clone()]));
+    assert!(map.get_bot_tx(&selft).is_none());
+    assert!(map.expired_conns().is_empty());
+}
+
+#[test]
+fn test_bot_map_concurrent() {
+    let map = Arc::new(BotMap::<crate::action::Action>::default());
+    map.hb_timeout_multiple.store(2, Ordering::Relaxed);
+    let (seq, _rx) = map.new_connect();
+    let selft = Selft {
+        platform: "".to_owned(),
+        user_id: "0".to_owned(),
+    };
+    let (done_tx, done_rx) = std::sync::mpsc::channel();
+    let workers = [
+        {
+            let map = map.clone();
+            let selft = selft.clone();
+            std::thread::spawn(move || {
+                for i in 0..10000 {
+                    let bot = Bot {
+                        selft: selft.clone(),
+                        online: i % 2 == 0,
+                    };
+                    map.connect_update(&seq, vec![bot], "");
+                    map.heartbeat(&seq, 5000);
+                }
+            })
+        },
+        {
+            let map = map.clone();
+            std::thread::spawn(move || {
+                for _ in 0..10000 {
+                    map.bots_status();
+                    map.expired_conns();
+                }
+            })
+        },
+    ];
+    std::thread::spawn(move || {
+        workers.into_iter().for_each(|w| w.join().unwrap());
+        done_tx.send(()).ok();
+    });
+    done_rx
+        .recv_timeout(Duration::from_secs(30))
+        .expect("bot map deadlock");
+    // 心跳间隔以毫秒计，5000ms * 2 内不会超时
+    assert!(map.expired_conns().is_empty());
+}
+
+#[test]
+fn test_bot_map_close_update() {
+    let map = Arc::new(BotMap::<crate::action::Action>::default());
+    let selft = Selft {
+        platform: "".to_owned(),
+        user_id: "0".to_owned(),
+    };
+    for _ in 0..1000 {
+        let (seq, _rx) = map.new_connect();
+        let update = {
+            let map = map.clone();
+            let selft = selft.clone();
+            std::thread::spawn(move || {
+                let bot = Bot {
+                    selft,
+                    online: true,
+                };
+                map.connect_update(&seq, vec![bot], "");
+            })
+        };
+        let close = {
+            let map = map.clone();
+            std::thread::spawn(move || {
+                map.connect_closs(&seq);
+            })
+        };
+        update.join().unwrap();
+        close.join().unwrap();
+        // 连接关闭后不会留下其 action_tx
+        assert!(map.get_bot_tx(&selft).is_none());
+        assert!(map.conns.is_empty());
+    }
+}
+
+#[tokio::test]
+async fn hb_watchdog_test() {
+    use crate::action::Action;
+    use crate::config::AppConfig;
+    use crate::obc::memory::MemoryListener;
+    use crate::resp::Resp;
+    use crate::structs::Version;
+    use crate::testing::MockEventHandler;
+
+    let mut listener = MemoryListener::bind("hb_watchdog_test").unwrap();
+    let eh = MockEventHandler::new();
+    let ob = Arc::new(OneBot::new(
+        AppOBC::<Action, Resp>::default(),
+        eh.clone(),
+        Version {
+            implt: "test".to_owned(),
+            version: crate::VERSION.to_owned(),
+            onebot_version: 12.to_string(),
+        },
+    ));
+    // 默认配置拦截 meta 事件
+    let config = AppConfig {
+        heartbeat_timeout_multiple: Some(1),
+        memory: vec![MemoryClient {
+            name: "hb_watchdog_test".to_owned(),
+            ..Default::default()
+        }],
+        ..AppConfig::empty()
+    };
+    ob.start::<Event, Action, Resp>(config, (), true)
+        .await
+        .unwrap();
+    let stream = listener.accept().await.unwrap();
+    let meta = |detail_type: &str, extra: serde_json::Value| {
+        let mut event = serde_json::json!({
+            "id": detail_type,
+            "time": 0.0,
+            "type": "meta",
+            "detail_type": detail_type,
+            "sub_type": "",
+        });
+        event
+            .as_object_mut()
+            .unwrap()
+            .extend(extra.as_object().unwrap().clone());
+        event.to_string()
+    };
+    let version = serde_json::json!({ "version": {
+        "impl": "test",
+        "version": "0",
+        "onebot_version": "12",
+    }});
+    assert!(stream.send(meta("connect", version)));
+    let status = serde_json::json!({
+        "interval": 100,
+        "status": {
+            "good": true,
+            "bots": [{ "self": { "platform": "test", "user_id": "bot" }, "online": true }],
+        },
+    });
+    assert!(stream.send(meta("heartbeat", status)));
+
+    // 停止发送心跳后产生 bot 离线事件
+    let event = eh
+        .wait_for(|e| e.detail_type == "status_update", Duration::from_secs(3))
+        .await
+        .unwrap();
+    assert!(!event.id.is_empty());
+    assert!(eh.events().iter().all(|e| e.detail_type != "heartbeat"));
+    ob.shutdown::<Event, Action, Resp>(true).await.unwrap();
+}
+
+#[cfg(feature = "impl-obc")]
+#[tokio::test]
+async fn hb_loopback_test() {
+    use crate::config::{AppConfig, Heartbeat, ImplConfig, MemoryServer};
+    use crate::testing::{Loopback, MockActionHandler, MockEventHandler};
+
+    let selft = Selft {
+        platform: "test".to_owned(),
+        user_id: "bot".to_owned(),
+    };
+    let impl_config = ImplConfig {
+        websocket_rev: vec![],
+        memory: vec![MemoryServer {
+            name: "hb_loopback_test".to_owned(),
+        }],
+        heartbeat: Heartbeat {
+            enabled: true,
+            interval: 1,
+        },
+        ..Default::default()
+    };
+    // 使用默认的心跳超时倍数
+    let app_config = AppConfig {
+        memory: vec![MemoryClient {
+            name: "hb_loopback_test".to_owned(),
+            ..Default::default()
+        }],
+        ..AppConfig::empty()
+    };
+    let loopback = Loopback::start_with(
+        MockActionHandler::new().with_bot(selft.clone()),
+        (),
+        impl_config,
+        MockEventHandler::new(),
+        (),
+        app_config,
+    )
+    .await
+    .unwrap();
+
+    // 经过数次 watchdog 检测后仍保持最初的连接
+    tokio::time::sleep(Duration::from_millis(3500)).await;
+    let bot_map = loopback.app_ob.action_handler.get_bot_map();
+    assert_eq!(bot_map.conn_seq.load(Ordering::Relaxed), 1);
+    assert!(bot_map.conns.contains_key(&0));
+    assert!(bot_map.heartbeats.contains_key(&0));
+    assert!(loopback.app_ob.contains_bot(&selft));
+    loopback.shutdown().await;
+}
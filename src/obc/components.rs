@@ -0,0 +1,332 @@
+//! OBC 运行组件
+//!
+//! 每个监听、连接设置项作为一个组件运行，拥有独立的停止信号；
+//! 重新配置时按设置项对比新旧配置，仅启停发生变化的组件，
+//! 组件任务意外退出时标记为失败，由 OneBot 按重启策略重新启动
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex as StdMutex};
+
+use serde::Serialize;
+use tokio::sync::{broadcast, Mutex, MutexGuard};
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+use crate::lifecycle::{failure_reason, RestartPolicy, TaskState, TaskStatus};
+use crate::WalleResult;
+
+/// 组件停止信号，组件被移除或 OneBot 停止时发送
+pub(crate) type Signal = broadcast::Sender<()>;
+
+/// 为监听接受的新连接订阅停止信号
+///
+/// 停止信号不会发送给之后才订阅的接收端，因此订阅后检查监听自身的接收端，
+/// 监听已收到停止信号时返回 None，调用方应丢弃该连接并停止监听
+#[cfg_attr(
+    not(any(feature = "impl-obc", feature = "websocket")),
+    allow(dead_code)
+)]
+pub(crate) fn subscribe_conn(
+    signal: &Signal,
+    listener_rx: &mut broadcast::Receiver<()>,
+) -> Option<broadcast::Receiver<()>> {
+    let rx = signal.subscribe();
+    match listener_rx.try_recv() {
+        Err(broadcast::error::TryRecvError::Empty) => Some(rx),
+        _ => None,
+    }
+}
+
+/// 运行中的组件
+#[derive(Default)]
+pub(crate) struct Components {
+    running: StdMutex<Vec<Component>>,
+    /// 等待重启的失败组件
+    pending: StdMutex<HashMap<String, TaskStatus>>,
+    reload: Mutex<()>,
+}
+
+struct Component {
+    key: String,
+    status: TaskStatus,
+    signal: Signal,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl Components {
+    /// 组件 key，设置项任意字段变化都视为不同组件
+    pub(crate) fn key<T: Serialize>(kind: &str, config: &T) -> String {
+        format!(
+            "{}:{}",
+            kind,
+            serde_json::to_string(config).unwrap_or_default()
+        )
+    }
+    /// 重新配置期间持有，保证同时只有一次重新配置
+    pub(crate) async fn lock(&self) -> MutexGuard<'_, ()> {
+        self.reload.lock().await
+    }
+    /// 停止并等待所有不在 `wanted` 中的组件，返回尚未运行的设置项
+    pub(crate) async fn retain<T>(&self, wanted: Vec<(String, T)>) -> Vec<(String, T)> {
+        let stopped: Vec<Component> = {
+            let mut running = self.running.lock().unwrap();
+            let (keep, stop) = std::mem::take(&mut *running)
+                .into_iter()
+                .partition(|c| wanted.iter().any(|(key, _)| key == &c.key));
+            *running = keep;
+            stop
+        };
+        self.pending
+            .lock()
+            .unwrap()
+            .retain(|key, _| wanted.iter().any(|(k, _)| k == key));
+        for component in stopped {
+            component.stop().await;
+        }
+        let running = self.running.lock().unwrap();
+        wanted
+            .into_iter()
+            .filter(|(key, _)| !running.iter().any(|c| &c.key == key))
+            .collect()
+    }
+    /// 登记一个启动中的组件，返回其停止信号
+    pub(crate) fn starting(&self, key: &str, name: String) -> Signal {
+        let restarts = self
+            .pending
+            .lock()
+            .unwrap()
+            .remove(key)
+            .map(|status| status.restarts + 1)
+            .unwrap_or_default();
+        let signal = broadcast::channel(1).0;
+        self.running.lock().unwrap().push(Component {
+            key: key.to_owned(),
+            status: TaskStatus {
+                name,
+                state: TaskState::Starting,
+                restarts,
+            },
+            signal: signal.clone(),
+            tasks: vec![],
+        });
+        signal
+    }
+    /// 组件启动完成，启动失败的组件按重启策略等待重启
+    pub(crate) fn started(
+        &self,
+        key: &str,
+        tasks: Vec<JoinHandle<()>>,
+        result: WalleResult<()>,
+        policy: &RestartPolicy,
+    ) -> WalleResult<()> {
+        let mut running = self.running.lock().unwrap();
+        let Some(index) = running
+            .iter()
+            .position(|c| c.key == key && c.status.state == TaskState::Starting)
+        else {
+            return result;
+        };
+        match &result {
+            Ok(()) => {
+                running[index].status.state = TaskState::Running;
+                running[index].tasks = tasks;
+            }
+            Err(e) => {
+                let component = &mut running[index];
+                warn!(target: super::OBC, "{} start failed: {}", component.status.name, e);
+                component.signal.send(()).ok();
+                component.status.state = TaskState::Failed(e.to_string());
+                if component.status.restarts < policy.max_restarts {
+                    let component = running.remove(index);
+                    self.pending
+                        .lock()
+                        .unwrap()
+                        .insert(component.key, component.status);
+                }
+            }
+        }
+        result
+    }
+    /// 回收意外退出的组件，返回是否有组件等待重启
+    pub(crate) async fn reap(&self, policy: &RestartPolicy) -> bool {
+        let exited: Vec<Component> = {
+            let mut running = self.running.lock().unwrap();
+            let (exited, keep) = std::mem::take(&mut *running).into_iter().partition(|c| {
+                c.status.state == TaskState::Running && c.tasks.iter().any(|t| t.is_finished())
+            });
+            *running = keep;
+            exited
+        };
+        for mut component in exited {
+            component.signal.send(()).ok();
+            let mut reason = "exited unexpectedly".to_owned();
+            for task in std::mem::take(&mut component.tasks) {
+                if let Err(e) = task.await {
+                    reason = failure_reason(e);
+                }
+            }
+            warn!(target: super::OBC, "{} failed: {}", component.status.name, reason);
+            component.status.state = TaskState::Failed(reason);
+            if component.status.restarts < policy.max_restarts {
+                self.pending
+                    .lock()
+                    .unwrap()
+                    .insert(component.key, component.status);
+            } else {
+                self.running.lock().unwrap().push(component);
+            }
+        }
+        !self.pending.lock().unwrap().is_empty()
+    }
+    pub(crate) fn status(&self) -> Vec<TaskStatus> {
+        let pending = self.pending.lock().unwrap();
+        self.running
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|c| c.status.clone())
+            .chain(pending.values().cloned())
+            .collect()
+    }
+    /// 收到 OneBot 停止信号后停止所有组件，该任务被中止时同时中止所有组件任务
+    pub(crate) fn supervise(
+        self: &Arc<Self>,
+        mut signal_rx: broadcast::Receiver<()>,
+    ) -> JoinHandle<()> {
+        let components = self.clone();
+        tokio::spawn(async move {
+            signal_rx.recv().await.ok();
+            components.pending.lock().unwrap().clear();
+            let all = std::mem::take(&mut *components.running.lock().unwrap());
+            let mut tasks = AbortOnDrop(vec![]);
+            for component in all {
+                component.signal.send(()).ok();
+                tasks.0.extend(component.tasks);
+            }
+            for task in tasks.0.iter_mut() {
+                task.await.ok();
+            }
+        })
+    }
+}
+
+impl Component {
+    async fn stop(self) {
+        self.signal.send(()).ok();
+        for task in self.tasks {
+            task.await.ok();
+        }
+    }
+}
+
+/// 监听组件名
+#[cfg(any(feature = "http", feature = "websocket"))]
+pub(crate) fn listen_name(
+    kind: &str,
+    host: &std::net::IpAddr,
+    port: u16,
+    path: &Option<String>,
+    unix: &Option<crate::config::UnixSocket>,
+) -> String {
+    let path = path.as_deref().unwrap_or("/");
+    match unix {
+        Some(unix) => format!("{} unix:{}{}", kind, unix.path, path),
+        None => format!("{} {}:{}{}", kind, host, port, path),
+    }
+}
+
+struct AbortOnDrop(Vec<JoinHandle<()>>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.iter().for_each(JoinHandle::abort);
+    }
+}
+
+#[cfg(feature = "impl-obc")]
+#[tokio::test]
+async fn reconfigure_test() {
+    use crate::action::Action;
+    use crate::config::{Heartbeat, ImplConfig, MemoryServer};
+    use crate::eh::EHExt;
+    use crate::event::Event;
+    use crate::obc::{memory::connect, ImplOBC};
+    use crate::resp::Resp;
+    use crate::structs::{Selft, Version};
+    use crate::testing::{MockActionHandler, MockEventHandler, MockEvents};
+    use crate::{OneBot, WalleError};
+    use std::time::Duration;
+
+    let config = |names: &[&str]| ImplConfig {
+        memory: names
+            .iter()
+            .map(|name| MemoryServer {
+                name: name.to_string(),
+            })
+            .collect(),
+        websocket_rev: vec![],
+        heartbeat: Heartbeat {
+            enabled: false,
+            interval: 4,
+        },
+        ..Default::default()
+    };
+    let selft = Selft {
+        platform: "test".to_owned(),
+        user_id: "bot".to_owned(),
+    };
+    let ob = Arc::new(OneBot::new(
+        MockActionHandler::new().with_bot(selft.clone()),
+        // 不支持重新配置的 handler 忽略新配置
+        EHExt::<Event, Action, Resp>::join(
+            ImplOBC::<Event>::new("test".to_owned()),
+            MockEventHandler::new(),
+        ),
+        Version {
+            implt: "test".to_owned(),
+            version: crate::VERSION.to_owned(),
+            onebot_version: 12.to_string(),
+        },
+    ));
+    assert!(matches!(
+        ob.reconfigure::<Event, Action, Resp>(None, Some((config(&[]), ())))
+            .await,
+        Err(WalleError::NotStarted)
+    ));
+    ob.start::<Event, Action, Resp>((), (config(&["reconf_keep", "reconf_old"]), ()), true)
+        .await
+        .unwrap();
+    let mut kept = connect("reconf_keep").unwrap();
+    assert!(connect("reconf_old").is_ok());
+
+    // 校验失败时不应用任何改变
+    assert!(matches!(
+        ob.reconfigure::<Event, Action, Resp>(None, Some((config(&["reconf_bad", ""]), ())))
+            .await,
+        Err(WalleError::Config(_))
+    ));
+    assert!(connect("reconf_bad").is_err());
+    assert!(connect("reconf_old").is_ok());
+    ob.reconfigure::<Event, Action, Resp>(None, Some((config(&["reconf_keep", "reconf_new"]), ())))
+        .await
+        .unwrap();
+    assert!(connect("reconf_old").is_err());
+    assert!(connect("reconf_new").is_ok());
+    // 未变化的组件保持运行，已建立的连接不受影响
+    let event = MockEvents::new(selft).private_message("alice", "hi");
+    let id = event.id.clone();
+    ob.handle_event::<Event, Action, Resp>(event).await.unwrap();
+    loop {
+        let text = tokio::time::timeout(Duration::from_secs(1), kept.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        if text.contains(&id) {
+            break;
+        }
+    }
+
+    ob.shutdown::<Event, Action, Resp>(true).await.unwrap();
+    assert!(connect("reconf_keep").is_err());
+    assert!(connect("reconf_new").is_err());
+}
@@ -1,32 +1,42 @@
 use std::{convert::Infallible, sync::Arc, time::Duration};
 
-use http_body_util::{BodyExt, Full};
+use futures_util::stream::BoxStream;
+use http_body_util::{BodyExt, Either, Full, StreamBody};
 use hyper::{
-    body::{Buf, Bytes, Incoming},
+    body::{Buf, Bytes, Frame, Incoming},
     header::{AUTHORIZATION, CONTENT_TYPE},
     service::service_fn,
     Method, Request, Response, StatusCode,
 };
 use hyper_util::{
-    client::legacy::{connect::HttpConnector, Client},
     rt::{TokioExecutor, TokioIo},
     server::conn::auto::Builder as ServerAutoBuilder,
 };
-use tokio::{net::TcpListener, task::JoinHandle};
+use tokio::sync::broadcast::error::TryRecvError;
+use tokio::task::{JoinHandle, JoinSet};
 use tracing::{info, trace, warn};
 
 use crate::{
     config::{HttpClient, HttpServer},
-    error::{WalleError, WalleResult},
+    error::WalleResult,
+    event::EventKind,
+    obc::components::{subscribe_conn, Signal},
+    obc::net::{HyperClient, Listener, RoutePath},
     resp::{resp_error, Resp},
     util::{AuthReqHeaderExt, ContentType, Echo, ProtocolItem},
     ActionHandler, EventHandler, OneBot,
 };
 
+use super::impl_sse::{SseBody, SseHub};
 use super::ImplOBC;
 
 type FullBytesResp = Response<Full<Bytes>>;
-type HyperClient = Client<HttpConnector, String>;
+
+/// 文件下载响应体，按块读取文件
+type FileBody = StreamBody<BoxStream<'static, std::io::Result<Frame<Bytes>>>>;
+
+/// HTTP 服务响应体
+type HttpBody = Either<Full<Bytes>, Either<SseBody, FileBody>>;
 
 fn empty_error_response(code: u16) -> FullBytesResp {
     Response::builder()
@@ -39,6 +49,52 @@ fn error_response(code: u16, body: &'static str) -> FullBytesResp {
     Response::builder().status(code).body(body.into()).unwrap()
 }
 
+/// 校验 access_token，通过时返回 None
+fn check_auth(req: &Request<Incoming>, access_token: Option<&str>) -> Option<FullBytesResp> {
+    use crate::obc::check_query;
+    let token = access_token?;
+    if let Some(header_token) = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|a| a.to_str().ok())
+    {
+        if header_token != format!("Bearer {}", token).as_str() {
+            return Some(error_response(403, "Authorization Header is invalid"));
+        }
+    } else if let Some(query_token) = check_query(req.uri()) {
+        if token != query_token {
+            return Some(error_response(403, "Authorization Query is invalid"));
+        }
+    } else {
+        return Some(error_response(403, "Missing Authorization Header"));
+    }
+    None
+}
+
+/// 处理 SSE 事件流请求
+fn serve_sse<E>(
+    hub: &SseHub<E>,
+    req: &Request<Incoming>,
+    access_token: Option<&str>,
+    signal: &Signal,
+) -> Response<HttpBody>
+where
+    E: ProtocolItem + Clone + EventKind,
+{
+    if req.method() != Method::GET {
+        return empty_error_response(405).map(Either::Left);
+    }
+    if let Some(resp) = check_auth(req, access_token) {
+        return resp.map(Either::Left);
+    }
+    let last_id = req
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|v| v.to_str().ok());
+    hub.subscribe(last_id, signal.subscribe())
+        .map(|b| Either::Right(Either::Left(b)))
+}
+
 fn encode2resp<T: ProtocolItem>(t: T, content_type: &ContentType) -> FullBytesResp {
     match content_type {
         ContentType::Json => Response::builder()
@@ -52,14 +108,124 @@ fn encode2resp<T: ProtocolItem>(t: T, content_type: &ContentType) -> FullBytesRe
     }
 }
 
+/// 构造 `Content-Disposition`，`filename` 为去除控制字符与引号的 ASCII 名称，
+/// `filename*` 为 RFC 5987 编码的原始名称
+#[cfg(feature = "file-store")]
+fn content_disposition(name: &str) -> String {
+    use std::fmt::Write;
+    let fallback: String = name
+        .chars()
+        .map(|c| match c {
+            ' ' => ' ',
+            '"' | '\\' => '_',
+            c if c.is_ascii_graphic() => c,
+            _ => '_',
+        })
+        .collect();
+    let mut encoded = String::new();
+    for b in name.bytes() {
+        if b.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            let _ = write!(encoded, "%{:02X}", b);
+        }
+    }
+    format!(
+        "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+        fallback, encoded
+    )
+}
+
+/// 处理文件下载请求，需要有效的签名或 access_token
+#[cfg(feature = "file-store")]
+async fn serve_file(
+    store: &crate::file_store::FileStore,
+    signer: &crate::file_store::FileUrl,
+    file_id: &str,
+    req: &Request<Incoming>,
+    access_token: Option<&str>,
+) -> Response<HttpBody> {
+    use crate::obc::check_query;
+    use futures_util::StreamExt;
+    use tokio::io::AsyncReadExt;
+
+    let query = req
+        .uri()
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter_map(|v| v.split_once('='))
+        .collect::<std::collections::HashMap<_, _>>();
+    let signed = match (
+        query.get("expires").and_then(|e| e.parse().ok()),
+        query.get("sign"),
+    ) {
+        (Some(expires), Some(sign)) => signer.verify(file_id, expires, sign),
+        _ => false,
+    };
+    let authorized = signed
+        || access_token.is_some_and(|token| {
+            req.headers()
+                .get(AUTHORIZATION)
+                .and_then(|a| a.to_str().ok())
+                .map(|h| h == format!("Bearer {}", token))
+                .or_else(|| check_query(req.uri()).map(|q| q == token))
+                .unwrap_or(false)
+        });
+    if !authorized {
+        return error_response(403, "Invalid or expired file url").map(Either::Left);
+    }
+    let Some(stored) = store.get(file_id) else {
+        return empty_error_response(404).map(Either::Left);
+    };
+    let file = match tokio::fs::File::open(store.file_path(file_id)).await {
+        Ok(file) => file,
+        Err(e) => {
+            warn!(target: super::OBC, "open file {} failed: {}", file_id, e);
+            return empty_error_response(500).map(Either::Left);
+        }
+    };
+    let size = match file.metadata().await {
+        Ok(meta) => meta.len(),
+        Err(e) => {
+            warn!(target: super::OBC, "read file {} failed: {}", file_id, e);
+            return empty_error_response(500).map(Either::Left);
+        }
+    };
+    let stream = futures_util::stream::try_unfold(file, |mut file| async move {
+        let mut buf = vec![0; 64 * 1024];
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(None);
+        }
+        buf.truncate(n);
+        Ok(Some((Frame::data(Bytes::from(buf)), file)))
+    });
+    Response::builder()
+        .header(CONTENT_TYPE, "application/octet-stream")
+        .header(hyper::header::CONTENT_LENGTH, size)
+        .header(
+            hyper::header::CONTENT_DISPOSITION,
+            content_disposition(&stored.name),
+        )
+        .body(Either::Right(Either::Right(StreamBody::new(
+            stream.boxed(),
+        ))))
+        .unwrap_or_else(|e| {
+            warn!(target: super::OBC, "build file {} response failed: {}", file_id, e);
+            empty_error_response(500).map(Either::Left)
+        })
+}
+
 impl<E> ImplOBC<E>
 where
-    E: ProtocolItem + Clone,
+    E: ProtocolItem + Clone + EventKind,
 {
     pub(crate) async fn http<A, R, AH, EH>(
         &self,
         ob: &Arc<OneBot<AH, EH>>,
         config: Vec<HttpServer>,
+        signal: &Signal,
         tasks: &mut Vec<JoinHandle<()>>,
     ) -> WalleResult<()>
     where
@@ -70,108 +236,201 @@ where
     {
         for http in config {
             let ob_ = ob.clone();
-            let addr = std::net::SocketAddr::new(http.host, http.port);
+            let signal_ = signal.clone();
+            let mut paths = vec![RoutePath::endpoint(&http.path)];
+            if let Some(sse) = &http.sse {
+                paths.push(RoutePath::Exact(sse.path.clone()));
+            }
+            if let Some(files) = &http.files {
+                paths.push(RoutePath::Prefix(format!(
+                    "{}/",
+                    files.path.trim_end_matches('/')
+                )));
+            }
+            let mut listener = Listener::bind(http.host, http.port, &http.unix, paths).await?;
             info!(
                 target: crate::WALLE_CORE,
-                "Starting HTTP server on http://{}", addr
+                "Starting HTTP server on {}", listener.display("http")
             );
             let access_token = http.access_token.clone();
             let path = http.path.clone();
+            #[cfg(feature = "file-store")]
+            let files = match (&http.files, &self.file_store) {
+                (Some(files), Some(store)) => {
+                    let prefix = files.path.trim_end_matches('/').to_owned();
+                    let base = files.public_url.clone().unwrap_or_else(|| {
+                        if http.unix.is_some() || http.host.is_unspecified() {
+                            warn!(
+                                target: super::OBC,
+                                "files.public_url is not set, file urls of {} are not reachable",
+                                listener.display("http")
+                            );
+                        }
+                        let addr = std::net::SocketAddr::new(http.host, http.port);
+                        format!("http://{}{}", addr, prefix)
+                    });
+                    // 每个服务使用独立的随机密钥签名，不复用 access_token
+                    let secret = rand::random::<[u8; 32]>();
+                    let signer = store.add_signed_url(base, &secret, files.expire);
+                    Some((prefix, store.clone(), signer))
+                }
+                _ => None,
+            };
+            let sse = http.sse.map(SseHub::new);
+            if let Some(hub) = sse.clone() {
+                let event_rx = self.subscribe_events();
+                let mut signal_rx = signal.subscribe();
+                tasks.push(tokio::spawn(async move {
+                    tokio::select! {
+                        _ = signal_rx.recv() => {}
+                        _ = hub.record(event_rx) => {}
+                    }
+                }));
+            }
+            #[cfg(feature = "file-store")]
+            let signer = files
+                .as_ref()
+                .map(|(_, store, signer)| (store.clone(), signer.clone()));
             let serv = service_fn(move |req: Request<Incoming>| {
                 let path = path.clone();
                 let access_token = access_token.clone();
                 let ob = ob_.clone();
+                #[cfg(feature = "file-store")]
+                let files = files.clone();
+                let sse = sse.clone();
+                let signal = signal_.clone();
                 async move {
-                    use crate::obc::check_query;
-                    if req.method() != Method::POST {
-                        return Ok::<_, Infallible>(empty_error_response(405));
+                    if let Some(hub) = sse.filter(|hub| req.uri().path() == hub.path()) {
+                        return Ok::<_, Infallible>(serve_sse(
+                            &hub,
+                            &req,
+                            access_token.as_deref(),
+                            &signal,
+                        ));
                     }
-                    if path
-                        .map(|p| req.uri().path() != p)
-                        .unwrap_or(!["/", ""].contains(&req.uri().path()))
-                    {
-                        return Ok(empty_error_response(404));
-                    }
-                    let content_type = match req
-                        .headers()
-                        .get(CONTENT_TYPE)
-                        .and_then(|v| v.to_str().ok())
-                        .and_then(ContentType::new)
-                    {
-                        Some(t) => t,
-                        None => return Ok(empty_error_response(415)),
-                    };
-
-                    if let Some(ref token) = access_token {
-                        if let Some(header_token) = req
-                            .headers()
-                            .get(AUTHORIZATION)
-                            .and_then(|a| a.to_str().ok())
+                    #[cfg(feature = "file-store")]
+                    if let Some((prefix, store, signer)) = files {
+                        if let Some(file_id) = req
+                            .uri()
+                            .path()
+                            .strip_prefix(prefix.as_str())
+                            .and_then(|p| p.strip_prefix('/'))
                         {
-                            if header_token != format!("Bearer {}", token).as_str() {
-                                return Ok(error_response(403, "Authorization Header is invalid"));
+                            if req.method() != Method::GET {
+                                return Ok(empty_error_response(405).map(Either::Left));
                             }
-                        } else if let Some(query_token) = check_query(req.uri()) {
-                            if token != query_token {
-                                return Ok(error_response(403, "Authorization Query is invalid"));
-                            }
-                        } else {
-                            return Ok(error_response(403, "Missing Authorization Header"));
+                            return Ok(serve_file(
+                                &store,
+                                &signer,
+                                file_id,
+                                &req,
+                                access_token.as_deref(),
+                            )
+                            .await);
                         }
                     }
-                    let data = req.collect().await.unwrap().to_bytes();
-                    let action: Result<Echo<A>, _> = match content_type {
-                        ContentType::Json => {
-                            ProtocolItem::json_decode(&String::from_utf8(data.to_vec()).unwrap())
+                    let resp: Result<FullBytesResp, Infallible> = async move {
+                        if req.method() != Method::POST {
+                            return Ok::<_, Infallible>(empty_error_response(405));
                         }
-                        ContentType::MsgPack => ProtocolItem::rmp_decode(&data),
-                    };
-                    match action {
-                        Ok(action) => {
-                            let (action, echo) = action.unpack();
-                            match ob.handle_action(action).await {
-                                Ok(r) => Ok(encode2resp(echo.pack(r), &content_type)),
-                                Err(e) => {
-                                    warn!(target: super::OBC, "handle action error: {}", e);
-                                    Ok(encode2resp::<Resp>(
-                                        resp_error::bad_handler(e).into(),
-                                        &content_type,
-                                    ))
+                        if path
+                            .map(|p| req.uri().path() != p)
+                            .unwrap_or(!["/", ""].contains(&req.uri().path()))
+                        {
+                            return Ok(empty_error_response(404));
+                        }
+                        let content_type = match req
+                            .headers()
+                            .get(CONTENT_TYPE)
+                            .and_then(|v| v.to_str().ok())
+                            .and_then(ContentType::new)
+                        {
+                            Some(t) => t,
+                            None => return Ok(empty_error_response(415)),
+                        };
+
+                        if let Some(resp) = check_auth(&req, access_token.as_deref()) {
+                            return Ok(resp);
+                        }
+                        let data = req.collect().await.unwrap().to_bytes();
+                        let action: Result<Echo<A>, _> = match content_type {
+                            ContentType::Json => {
+                                ProtocolItem::json_decode(std::str::from_utf8(&data).unwrap())
+                            }
+                            ContentType::MsgPack => ProtocolItem::rmp_decode(&data),
+                        };
+                        match action {
+                            Ok(action) => {
+                                let (action, echo) = action.unpack();
+                                match ob.handle_action(action).await {
+                                    Ok(r) => Ok(encode2resp(echo.pack(r), &content_type)),
+                                    Err(e) => {
+                                        warn!(target: super::OBC, "handle action error: {}", e);
+                                        Ok(encode2resp::<Resp>(
+                                            resp_error::bad_handler(e).into(),
+                                            &content_type,
+                                        ))
+                                    }
                                 }
                             }
+                            Err(e) => Ok(encode2resp(
+                                if e.starts_with("missing field") {
+                                    trace!(
+                                        target: crate::WALLE_CORE,
+                                        "Http call action miss field: {e}",
+                                    );
+                                    Resp::from(resp_error::bad_segment_data(e))
+                                } else {
+                                    warn!(target: crate::WALLE_CORE, "Http call action ser error: {e}",);
+                                    resp_error::unsupported_action(e).into()
+                                },
+                                &content_type,
+                            )),
                         }
-                        Err(e) => Ok(encode2resp(
-                            if e.starts_with("missing field") {
-                                trace!(
-                                    target: crate::WALLE_CORE,
-                                    "Http call action miss field: {e}",
-                                );
-                                Resp::from(resp_error::bad_segment_data(e))
-                            } else {
-                                warn!(target: crate::WALLE_CORE, "Http call action ser error: {e}",);
-                                resp_error::unsupported_action(e).into()
-                            },
-                            &content_type,
-                        )),
                     }
+                    .await;
+                    resp.map(|r| r.map(Either::Left))
                 }
             });
-            let ob = ob.clone();
-            let listener = TcpListener::bind(&addr).await.map_err(WalleError::from)?;
-            let mut signal_rx = ob.get_signal_rx()?;
+            let mut signal_rx = signal.subscribe();
+            let signal = signal.clone();
+            let drain_timeout = ob.drain_timeout;
             tasks.push(tokio::spawn(async move {
+                let mut conns = JoinSet::new();
                 loop {
                     tokio::select! {
                         _ = signal_rx.recv() => break,
-                        Ok((tcp_stream, _)) = listener.accept() => {
+                        Some(_) = conns.join_next() => {}
+                        Some((stream, _)) = listener.accept() => {
                             let serv = serv.clone();
-                            tokio::spawn(async move {
-                                let io = TokioIo::new(tcp_stream);
-                                ServerAutoBuilder::new(TokioExecutor::new()).serve_connection(io, serv).await.unwrap();
+                            let Some(mut signal_rx) = subscribe_conn(&signal, &mut signal_rx) else {
+                                break;
+                            };
+                            conns.spawn(async move {
+                                let mut builder = ServerAutoBuilder::new(TokioExecutor::new());
+                                builder.http1().keep_alive(!stream.is_routed());
+                                let io = TokioIo::new(stream);
+                                let mut conn = std::pin::pin!(builder.serve_connection(io, serv));
+                                tokio::select! {
+                                    _ = conn.as_mut() => return,
+                                    _ = signal_rx.recv() => {}
+                                }
+                                // 处理完进行中的请求后关闭连接
+                                conn.as_mut().graceful_shutdown();
+                                if tokio::time::timeout(drain_timeout, conn).await.is_err() {
+                                    warn!(target: super::OBC, "http drain timeout, close connection");
+                                }
                             });
                         }
                     }
                 }
+                // 停止接受新连接，等待已有连接完成 drain
+                drop(listener);
+                while conns.join_next().await.is_some() {}
+                #[cfg(feature = "file-store")]
+                if let Some((store, signer)) = signer {
+                    store.remove_url(&signer);
+                }
             }));
         }
         Ok(())
@@ -181,6 +440,7 @@ where
         &self,
         ob: &Arc<OneBot<AH, EH>>,
         config: Vec<HttpClient>,
+        signal: &Signal,
         tasks: &mut Vec<JoinHandle<()>>,
     ) -> WalleResult<()>
     where
@@ -191,34 +451,51 @@ where
         EH: EventHandler<E, A, R> + Send + Sync + 'static,
     {
         let ob = ob.clone();
-        let mut event_rx = self.event_tx.subscribe();
-        let mut signal_rx = ob.get_signal_rx()?;
+        let mut event_rx = self.subscribe_events();
+        let mut signal_rx = signal.subscribe();
         let r#impl = self.implt.clone();
-        let cli: HyperClient = Client::builder(TokioExecutor::new()).build_http::<String>();
+        let cli = HyperClient::new();
+        let drain_timeout = ob.drain_timeout;
         tasks.push(tokio::spawn(async move {
+            let mut pushes = JoinSet::new();
             loop {
                 tokio::select! {
                     _ = signal_rx.recv() => break,
+                    Some(_) = pushes.join_next() => {}
                     Ok(event) = event_rx.recv() => webhook_push(
                         &ob,
                         event,
                         &r#impl,
                         &config,
-                        &cli
-                    ).await
+                        &cli,
+                        &mut pushes,
+                    )
+                }
+            }
+            // 推送已排队的事件，并等待进行中的推送完成
+            loop {
+                match event_rx.try_recv() {
+                    Ok(event) => webhook_push(&ob, event, &r#impl, &config, &cli, &mut pushes),
+                    Err(TryRecvError::Lagged(_)) => continue,
+                    Err(_) => break,
                 }
             }
+            let drain = async { while pushes.join_next().await.is_some() {} };
+            if tokio::time::timeout(drain_timeout, drain).await.is_err() {
+                warn!(target: super::OBC, "webhook drain timeout, drop pending pushes");
+            }
         }));
         Ok(())
     }
 }
 
-async fn webhook_push<E, A, R, AH, EH>(
+fn webhook_push<E, A, R, AH, EH>(
     ob: &Arc<OneBot<AH, EH>>,
     event: E,
     r#impl: &str,
     config: &Vec<HttpClient>,
     cli: &HyperClient,
+    pushes: &mut JoinSet<()>,
 ) where
     E: ProtocolItem,
     A: ProtocolItem,
@@ -230,29 +507,32 @@ async fn webhook_push<E, A, R, AH, EH>(
     for webhook in config {
         let req = Request::builder()
             .method(Method::POST)
-            .uri(&webhook.url)
             .header(CONTENT_TYPE, "application/json")
             .header("X-OneBot-Version", 12.to_string())
             .header("X-Impl", r#impl.to_owned())
-            .header_auth_token(&webhook.access_token)
-            .body(date.clone())
-            .unwrap();
+            .header_auth_token(&webhook.access_token);
+        let body = date.clone();
+        let url = webhook.url.clone();
         let ob = ob.clone();
         let timeout = webhook.timeout;
         let cli = cli.clone();
-        tokio::spawn(async move {
-            let resp =
-                match tokio::time::timeout(Duration::from_secs(timeout), cli.request(req)).await {
-                    Ok(Ok(r)) => r,
-                    Ok(Err(e)) => {
-                        warn!(target: crate::WALLE_CORE, "{}", e);
-                        return;
-                    }
-                    Err(_) => {
-                        warn!(target: crate::WALLE_CORE, "push event timeout");
-                        return;
-                    }
-                };
+        pushes.spawn(async move {
+            let resp = match tokio::time::timeout(
+                Duration::from_secs(timeout),
+                cli.request(&url, req, body),
+            )
+            .await
+            {
+                Ok(Ok(r)) => r,
+                Ok(Err(e)) => {
+                    warn!(target: crate::WALLE_CORE, "{}", e);
+                    return;
+                }
+                Err(_) => {
+                    warn!(target: crate::WALLE_CORE, "push event timeout");
+                    return;
+                }
+            };
             match resp.status() {
                 StatusCode::NO_CONTENT => (),
                 StatusCode::OK => {
@@ -273,3 +553,14 @@ async fn webhook_push<E, A, R, AH, EH>(
         });
     }
 }
+
+#[cfg(feature = "file-store")]
+#[test]
+fn content_disposition_test() {
+    let header = content_disposition("a\"b\r\nc 文件.txt");
+    assert_eq!(
+        header,
+        "attachment; filename=\"a_b__c __.txt\"; filename*=UTF-8''a%22b%0D%0Ac%20%E6%96%87%E4%BB%B6.txt"
+    );
+    assert!(hyper::header::HeaderValue::from_str(&header).is_ok());
+}
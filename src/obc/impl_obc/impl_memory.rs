@@ -0,0 +1,265 @@
+use std::sync::Arc;
+
+use tokio::sync::broadcast::{
+    self,
+    error::{RecvError, TryRecvError},
+};
+use tokio::task::{JoinHandle, JoinSet};
+use tracing::{info, trace, warn};
+
+use crate::config::{MemoryServer, QueueConfig};
+use crate::event::{Event, EventKind};
+use crate::obc::components::{subscribe_conn, Signal};
+use crate::obc::memory::{MemoryListener, MemoryStream};
+use crate::obc::queue::channel;
+use crate::util::{Echo, ProtocolItem};
+use crate::{ActionHandler, EventHandler, OneBot, WalleResult};
+
+use super::ImplOBC;
+
+impl<E> ImplOBC<E>
+where
+    E: ProtocolItem + Clone + EventKind,
+{
+    pub(crate) async fn memory<A, R, AH, EH>(
+        &self,
+        ob: &Arc<OneBot<AH, EH>>,
+        config: Vec<MemoryServer>,
+        signal: &Signal,
+        tasks: &mut Vec<JoinHandle<()>>,
+    ) -> WalleResult<()>
+    where
+        A: ProtocolItem,
+        R: ProtocolItem,
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        for ms in config {
+            let mut listener = MemoryListener::bind(&ms.name)?;
+            info!(target: super::OBC, "Memory server listening on {}", ms.name);
+            let mut shutdown_signal_rx = signal.subscribe();
+            let signal = signal.clone();
+            let event_rx = self.subscribe_events();
+            let hb_rx = self.subscribe_hb();
+            let resp_queue = self.resp_queue.lock().unwrap().clone();
+            let ob = ob.clone();
+            tasks.push(tokio::spawn(async move {
+                let mut conns = JoinSet::new();
+                loop {
+                    tokio::select! {
+                        Some(stream) = listener.accept() => {
+                            let Some(signal_rx) = subscribe_conn(&signal, &mut shutdown_signal_rx) else {
+                                break;
+                            };
+                            info!(target: super::OBC, "New memory connection on {}", ms.name);
+                            conns.spawn(memory_loop(
+                                ob.clone(),
+                                signal_rx,
+                                event_rx.resubscribe(),
+                                hb_rx.resubscribe(),
+                                stream,
+                                resp_queue.clone(),
+                            ));
+                        }
+                        Some(_) = conns.join_next() => {}
+                        _ = shutdown_signal_rx.recv() => break,
+                    }
+                }
+                // 停止接受新连接，等待已有连接完成 drain
+                drop(listener);
+                while conns.join_next().await.is_some() {}
+            }));
+        }
+        Ok(())
+    }
+}
+
+async fn memory_loop<E, A, R, AH, EH>(
+    ob: Arc<OneBot<AH, EH>>,
+    mut shutdown_signal_rx: broadcast::Receiver<()>,
+    mut event_rx: broadcast::Receiver<E>,
+    mut hb_rx: broadcast::Receiver<Event>,
+    mut stream: MemoryStream,
+    resp_queue: QueueConfig,
+) where
+    E: ProtocolItem + Clone + EventKind,
+    A: ProtocolItem,
+    R: ProtocolItem,
+    AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+    EH: EventHandler<E, A, R> + Send + Sync + 'static,
+{
+    let (resp_tx, mut resp_rx) = channel(&resp_queue);
+    for event in super::connect_events(&ob) {
+        if !stream.send(event.json_encode()) {
+            return;
+        }
+    }
+    let mut draining = false;
+    loop {
+        tokio::select! {
+            _ = shutdown_signal_rx.recv() => {
+                draining = true;
+                break;
+            }
+            event = event_rx.recv() => match event {
+                Ok(event) => {
+                    let json = event.json_encode();
+                    trace!(target: crate::WALLE_CORE, "memory send: {}", json);
+                    if !stream.send(json) {
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(n)) => {
+                    warn!(target: super::OBC, "memory event receiver lagged, {} events skipped", n);
+                }
+                Err(RecvError::Closed) => break,
+            },
+            hb = hb_rx.recv() => match hb {
+                Ok(hb) => if !stream.send(hb.json_encode()) {
+                    break;
+                },
+                Err(RecvError::Lagged(n)) => {
+                    warn!(target: super::OBC, "memory heartbeat receiver lagged, {} heartbeats skipped", n);
+                }
+                Err(RecvError::Closed) => break,
+            },
+            text = stream.recv() => {
+                let Some(text) = text else { break };
+                trace!(target: crate::WALLE_CORE, "memory recv: {}", text);
+                match Echo::<A>::json_decode(&text) {
+                    Ok(action) => super::spawn_action(&ob, action, resp_tx.clone()),
+                    Err(msg) => match serde_json::from_str(&text) {
+                        Ok(a) => if !stream.send(super::action_error(a, msg).json_encode()) {
+                            break;
+                        },
+                        Err(_) => warn!(target: crate::WALLE_CORE, "json deserialize failed: {:?}", text),
+                    },
+                }
+            },
+            resp = resp_rx.recv() => {
+                let Some(resp) = resp else { break };
+                if !stream.send(resp.json_encode()) {
+                    break;
+                }
+            }
+        }
+    }
+    if draining {
+        // 不再接收新 action，等待进行中的 action 响应，再发送已排队的事件
+        drop(resp_tx);
+        let drain = async {
+            while let Some(resp) = resp_rx.recv().await {
+                if !stream.send(resp.json_encode()) {
+                    return;
+                }
+            }
+            loop {
+                match event_rx.try_recv() {
+                    Ok(event) => {
+                        if !stream.send(event.json_encode()) {
+                            return;
+                        }
+                    }
+                    Err(TryRecvError::Lagged(_)) => continue,
+                    Err(_) => return,
+                }
+            }
+        };
+        if tokio::time::timeout(ob.drain_timeout, drain).await.is_err() {
+            warn!(target: super::OBC, "memory drain timeout, close connection");
+        }
+    }
+}
+
+#[tokio::test]
+async fn drain_test() {
+    use crate::action::Action;
+    use crate::ah::GenStatus;
+    use crate::config::{Heartbeat, ImplConfig};
+    use crate::resp::Resp;
+    use crate::structs::{Selft, Status, Version};
+    use crate::value_map;
+    use std::time::Duration;
+
+    /// 所有 action 都需要 300ms 才能完成
+    struct SlowHandler;
+
+    impl GenStatus for SlowHandler {
+        fn gen_status(&self) -> Status {
+            Status {
+                good: true,
+                bots: vec![],
+            }
+        }
+        fn contains_bot(&self, _bot: &Selft) -> bool {
+            false
+        }
+    }
+
+    impl ActionHandler<Event, Action, Resp> for SlowHandler {
+        type Config = ();
+        async fn start<AH, EH>(
+            &self,
+            _ob: &Arc<OneBot<AH, EH>>,
+            _config: (),
+        ) -> WalleResult<Vec<JoinHandle<()>>>
+        where
+            AH: ActionHandler<Event, Action, Resp> + Send + Sync + 'static,
+            EH: EventHandler<Event, Action, Resp> + Send + Sync + 'static,
+        {
+            Ok(vec![])
+        }
+        async fn call<AH, EH>(
+            &self,
+            _action: Action,
+            _ob: &Arc<OneBot<AH, EH>>,
+        ) -> WalleResult<Resp>
+        where
+            AH: ActionHandler<Event, Action, Resp> + Send + Sync + 'static,
+            EH: EventHandler<Event, Action, Resp> + Send + Sync + 'static,
+        {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            Ok(value_map! { "done": true }.into())
+        }
+    }
+
+    let ob = Arc::new(OneBot::new(
+        SlowHandler,
+        ImplOBC::<Event>::new("test".to_owned()),
+        Version {
+            implt: "test".to_owned(),
+            version: crate::VERSION.to_owned(),
+            onebot_version: 12.to_string(),
+        },
+    ));
+    let config = ImplConfig {
+        memory: vec![MemoryServer {
+            name: "drain_test".to_owned(),
+        }],
+        websocket_rev: vec![],
+        heartbeat: Heartbeat {
+            enabled: false,
+            interval: 4,
+        },
+        ..Default::default()
+    };
+    ob.start::<Event, Action, Resp>((), config, true)
+        .await
+        .unwrap();
+    let mut client = crate::obc::memory::connect("drain_test").unwrap();
+    assert!(client.send(r#"{"action":"slow","params":{},"echo":"drain"}"#.to_owned()));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // 停止时等待进行中的 action 返回响应后才关闭连接
+    let ob_ = ob.clone();
+    let shutdown = tokio::spawn(async move { ob_.shutdown::<Event, Action, Resp>(true).await });
+    let mut resp = None;
+    while let Some(text) = client.recv().await {
+        if text.contains("\"drain\"") {
+            resp = Some(text);
+        }
+    }
+    assert!(resp.unwrap().contains("\"done\":true"));
+    shutdown.await.unwrap().unwrap();
+    assert!(crate::obc::memory::connect("drain_test").is_err());
+}
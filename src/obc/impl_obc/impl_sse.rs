@@ -0,0 +1,271 @@
+//! 实现端 HTTP SSE 事件流
+
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use hyper::body::{Body, Bytes, Frame};
+use hyper::header::{CACHE_CONTROL, CONTENT_TYPE};
+use hyper::Response;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::config::SseServer;
+use crate::event::EventKind;
+use crate::util::ProtocolItem;
+
+/// SSE 响应体
+pub(super) struct SseBody(mpsc::Receiver<Bytes>);
+
+impl Body for SseBody {
+    type Data = Bytes;
+    type Error = Infallible;
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+        self.0.poll_recv(cx).map(|b| b.map(|b| Ok(Frame::data(b))))
+    }
+}
+
+/// SSE 事件分发，缓存最近的事件用于 `Last-Event-ID` 续传
+pub(super) struct SseHub<E> {
+    path: String,
+    keepalive: u32,
+    capacity: usize,
+    events: Mutex<VecDeque<E>>,
+    tx: broadcast::Sender<E>,
+}
+
+impl<E> SseHub<E>
+where
+    E: ProtocolItem + Clone + EventKind,
+{
+    pub(super) fn new(config: SseServer) -> Arc<Self> {
+        let (tx, _) = broadcast::channel(config.buffer.max(16));
+        Arc::new(Self {
+            path: config.path,
+            keepalive: config.keepalive,
+            capacity: config.buffer,
+            events: Mutex::default(),
+            tx,
+        })
+    }
+    pub(super) fn path(&self) -> &str {
+        &self.path
+    }
+    /// 持续从 ImplOBC 接收事件，缓存并分发给 SSE 连接
+    ///
+    /// 缓存与分发在同一锁内完成，保证新连接续传的事件与之后收到的事件不重不漏
+    pub(super) async fn record(&self, mut event_rx: broadcast::Receiver<E>) {
+        loop {
+            match event_rx.recv().await {
+                Ok(event) => {
+                    let mut events = self.events.lock().unwrap();
+                    if self.capacity > 0 {
+                        if events.len() >= self.capacity {
+                            events.pop_front();
+                        }
+                        events.push_back(event.clone());
+                    }
+                    self.tx.send(event).ok();
+                }
+                Err(RecvError::Lagged(n)) => {
+                    warn!(target: super::OBC, "sse receiver lagged, {} events skipped", n)
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+    /// 建立 SSE 连接
+    ///
+    /// `last_id` 为缓存中的事件 id 时续传其后的事件，不在缓存中时续传所有缓存事件
+    pub(super) fn subscribe(
+        &self,
+        last_id: Option<&str>,
+        mut signal_rx: broadcast::Receiver<()>,
+    ) -> Response<SseBody> {
+        let (mut rx, replay) = {
+            let events = self.events.lock().unwrap();
+            let replay: Vec<E> = match last_id {
+                Some(id) => {
+                    let skip = events
+                        .iter()
+                        .position(|e| e.id() == id)
+                        .map(|i| i + 1)
+                        .unwrap_or_default();
+                    events.iter().skip(skip).cloned().collect()
+                }
+                None => vec![],
+            };
+            (self.tx.subscribe(), replay)
+        };
+        if !replay.is_empty() {
+            info!(target: super::OBC, "sse resume {} events", replay.len());
+        }
+        let keepalive = self.keepalive;
+        let (tx, body_rx) = mpsc::channel(16);
+        tokio::spawn(async move {
+            for event in replay {
+                if tx.send(encode(&event)).await.is_err() {
+                    return;
+                }
+            }
+            let mut keepalive = (keepalive > 0)
+                .then(|| tokio::time::interval(Duration::from_secs(keepalive as u64)));
+            loop {
+                let frame = tokio::select! {
+                    _ = signal_rx.recv() => break,
+                    _ = tx.closed() => break,
+                    _ = tick(&mut keepalive) => Bytes::from_static(b": keepalive\n\n"),
+                    event = rx.recv() => match event {
+                        Ok(event) => encode(&event),
+                        Err(RecvError::Lagged(n)) => {
+                            warn!(target: super::OBC, "sse connection lagged, {} events skipped", n);
+                            continue;
+                        }
+                        Err(RecvError::Closed) => break,
+                    },
+                };
+                if tx.send(frame).await.is_err() {
+                    break;
+                }
+            }
+        });
+        Response::builder()
+            .header(CONTENT_TYPE, "text/event-stream")
+            .header(CACHE_CONTROL, "no-cache")
+            .body(SseBody(body_rx))
+            .unwrap()
+    }
+}
+
+async fn tick(interval: &mut Option<tokio::time::Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+fn encode<E: ProtocolItem + EventKind>(event: &E) -> Bytes {
+    let data = event.json_encode();
+    if event.id().is_empty() {
+        format!("data: {}\n\n", data).into()
+    } else {
+        format!("id: {}\ndata: {}\n\n", event.id(), data).into()
+    }
+}
+
+#[tokio::test]
+async fn sse_test() {
+    use crate::action::Action;
+    use crate::config::{Heartbeat, HttpServer, ImplConfig};
+    use crate::event::Event;
+    use crate::obc::ImplOBC;
+    use crate::resp::Resp;
+    use crate::structs::{Selft, Version};
+    use crate::testing::{MockActionHandler, MockEvents};
+    use crate::OneBot;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    async fn get(port: u16, head: &str) -> TcpStream {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let req = format!("GET {}\r\nHost: localhost\r\n\r\n", head);
+        stream.write_all(req.as_bytes()).await.unwrap();
+        stream
+    }
+    async fn read_until(stream: &mut TcpStream, pat: &str) -> String {
+        let mut buf = String::new();
+        while !buf.contains(pat) {
+            let mut b = [0u8; 1024];
+            let n = tokio::time::timeout(Duration::from_secs(1), stream.read(&mut b))
+                .await
+                .unwrap()
+                .unwrap();
+            assert!(n > 0, "connection closed: {}", buf);
+            buf.push_str(std::str::from_utf8(&b[..n]).unwrap());
+        }
+        buf
+    }
+
+    let port = std::net::TcpListener::bind(("127.0.0.1", 0))
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let selft = Selft {
+        platform: "test".to_owned(),
+        user_id: "bot".to_owned(),
+    };
+    let ob = Arc::new(OneBot::new(
+        MockActionHandler::new().with_bot(selft.clone()),
+        ImplOBC::<Event>::new("test".to_owned()),
+        Version {
+            implt: "test".to_owned(),
+            version: crate::VERSION.to_owned(),
+            onebot_version: 12.to_string(),
+        },
+    ));
+    ob.start::<Event, Action, Resp>(
+        (),
+        ImplConfig {
+            http: vec![HttpServer {
+                port,
+                access_token: Some("token".to_owned()),
+                sse: Some(SseServer {
+                    keepalive: 0,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+            websocket: vec![],
+            websocket_rev: vec![],
+            heartbeat: Heartbeat {
+                enabled: false,
+                interval: 0,
+            },
+            ..Default::default()
+        },
+        true,
+    )
+    .await
+    .unwrap();
+
+    let mut denied = get(port, "/events HTTP/1.1").await;
+    assert!(read_until(&mut denied, "\r\n\r\n").await.contains("403"));
+
+    let mut stream = get(port, "/events?access_token=token HTTP/1.1").await;
+    let head = read_until(&mut stream, "\r\n\r\n").await;
+    assert!(head.contains("text/event-stream"));
+    let events = MockEvents::new(selft);
+    let mut ids = vec![];
+    for _ in 0..3 {
+        let event = events.private_message("alice", "hi");
+        ids.push(event.id.clone());
+        events.inject(&ob, |_| event).await.unwrap();
+    }
+    let line = |i: usize| format!("id: {}\n", ids[i]);
+    let body = read_until(&mut stream, &line(2)).await;
+    assert!(body.contains(&format!("{}data: {{", line(0))));
+
+    // 从第一个事件之后续传
+    let mut resumed = get(
+        port,
+        &format!(
+            "/events?access_token=token HTTP/1.1\r\nLast-Event-ID: {}",
+            ids[0]
+        ),
+    )
+    .await;
+    let body = read_until(&mut resumed, &line(2)).await;
+    assert!(body.contains(&line(1)));
+    assert!(!body.contains(&line(0)));
+    ob.shutdown::<Event, Action, Resp>(true).await.unwrap();
+}
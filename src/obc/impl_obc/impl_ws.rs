@@ -1,34 +1,43 @@
 use crate::{
-    error::{WalleError, WalleResult},
-    resp::{resp_error, Resp},
-    util::{AuthReqHeaderExt, Echo, ProtocolItem, ValueMap},
-    value_map, ActionHandler, EventHandler, OneBot,
+    config::{KeepAlive, QueueConfig},
+    error::WalleResult,
+    util::{AuthReqHeaderExt, Echo, ProtocolItem},
+    ActionHandler, EventHandler, OneBot,
 };
 use crate::{
-    event::Event,
+    event::{Event, EventKind},
     obc::{
-        ws_util::{try_connect, upgrade_websocket},
+        components::{subscribe_conn, Signal},
+        net::{Listener, RoutePath, Stream},
+        queue::{channel, QueueSender},
+        ws_util::{try_connect, upgrade_websocket, KeepAliveTick, KeepAliveTimer},
         ImplOBC,
     },
 };
+
+use super::replay::ReplayBuffer;
 use futures_util::{SinkExt, StreamExt};
-use std::{sync::Arc, time::Duration};
-use tokio::net::TcpStream;
-use tokio::sync::broadcast;
-use tokio::task::JoinHandle;
+use std::sync::Arc;
+use tokio::sync::broadcast::{
+    self,
+    error::{RecvError, TryRecvError},
+};
+use tokio::task::{JoinHandle, JoinSet};
 use tokio_tungstenite::tungstenite::http::{header::USER_AGENT, Request};
+use tokio_tungstenite::tungstenite::protocol::{frame::coding::CloseCode, CloseFrame};
 use tokio_tungstenite::tungstenite::Message as WsMsg;
 use tokio_tungstenite::WebSocketStream;
 use tracing::{info, trace, warn};
 
 impl<E> ImplOBC<E>
 where
-    E: ProtocolItem + Clone,
+    E: ProtocolItem + Clone + EventKind,
 {
     pub(crate) async fn ws<A, R, AH, EH>(
         &self,
         ob: &Arc<OneBot<AH, EH>>,
         config: Vec<crate::config::WebSocketServer>,
+        signal: &Signal,
         tasks: &mut Vec<JoinHandle<()>>,
     ) -> WalleResult<()>
     where
@@ -38,34 +47,59 @@ where
         EH: EventHandler<E, A, R> + Send + Sync + 'static,
     {
         for wss in config {
-            // 创建tcp监听
-            let addr = std::net::SocketAddr::new(wss.host, wss.port);
-            let tcp_listener = tokio::net::TcpListener::bind(&addr)
-                .await
-                .map_err(WalleError::from)?;
+            // 创建 tcp 或 unix socket 监听
+            let mut listener = Listener::bind(
+                wss.host,
+                wss.port,
+                &wss.unix,
+                vec![RoutePath::endpoint(&wss.path)],
+            )
+            .await?;
             info!(
                 target: super::OBC,
-                "Websocket server listening on ws://{}", addr
+                "Websocket server listening on {}", listener.display("ws")
             );
-            let mut shutdown_signal_rx = ob.get_signal_rx()?;
-            let event_rx = self.event_tx.subscribe();
-            let hb_rx = self.hb_tx.subscribe();
+            let mut shutdown_signal_rx = signal.subscribe();
+            let signal = signal.clone();
+            let event_rx = self.subscribe_events();
+            let hb_rx = self.subscribe_hb();
+            let resp_queue = self.resp_queue.lock().unwrap().clone();
             let ob = ob.clone();
             tasks.push(tokio::spawn(async move {
+            let mut conns = JoinSet::new();
             loop { tokio::select! {
-                    Ok((stream, addr)) = tcp_listener.accept() => {
-                        if let Some((ws_stream, _)) = upgrade_websocket(&wss.access_token, &wss.path, stream).await {
+                    Some((stream, addr)) = listener.accept() => {
+                        if let Some((ws_stream, _)) = upgrade_websocket(&wss.access_token, &wss.path, stream, &addr).await {
                             info!(target: super::OBC, "New websocket connection from {}", addr);
-                            tokio::spawn(ws_loop(
-                                ob.clone(),
-                                event_rx.resubscribe(),
-                                hb_rx.resubscribe(),
-                                ws_stream,
-                            ));
+                            let ob = ob.clone();
+                            let mut event_rx = event_rx.resubscribe();
+                            let hb_rx = hb_rx.resubscribe();
+                            let keepalive = wss.keepalive.clone();
+                            let resp_queue = resp_queue.clone();
+                            let Some(signal_rx) = subscribe_conn(&signal, &mut shutdown_signal_rx) else {
+                                break;
+                            };
+                            conns.spawn(async move {
+                                ws_loop(
+                                    ob,
+                                    signal_rx,
+                                    &mut event_rx,
+                                    hb_rx,
+                                    ws_stream,
+                                    keepalive,
+                                    resp_queue,
+                                    None,
+                                )
+                                .await
+                            });
                         }
                     }
+                    Some(_) = conns.join_next() => {}
                     _ = shutdown_signal_rx.recv() => break,
                 }}
+                // 停止接受新连接，等待已有连接完成 drain
+                drop(listener);
+                while conns.join_next().await.is_some() {}
             }));
         }
         Ok(())
@@ -75,6 +109,7 @@ where
         &self,
         ob: &Arc<OneBot<AH, EH>>,
         config: Vec<crate::config::WebSocketClient>,
+        signal: &Signal,
         tasks: &mut Vec<JoinHandle<()>>,
     ) -> WalleResult<()>
     where
@@ -84,13 +119,17 @@ where
         EH: EventHandler<E, A, R> + Send + Sync + 'static,
     {
         for wsr in config {
-            let event_rx = self.event_tx.subscribe();
-            let hb_rx = self.hb_tx.subscribe();
-            let mut signal_rx = ob.get_signal_rx()?;
+            let mut event_rx = self.subscribe_events();
+            let hb_rx = self.subscribe_hb();
+            let mut signal_rx = signal.subscribe();
+            let signal = signal.clone();
+            let resp_queue = self.resp_queue.lock().unwrap().clone();
             let ob = ob.clone();
             let implt = self.implt.clone();
             tasks.push(tokio::spawn(async move {
                 info!(target: super::OBC, "Start try connect to {}", wsr.url);
+                // 启用重放时始终使用同一个 receiver，断线期间的事件将被缓存
+                let mut replay = wsr.replay.as_ref().map(ReplayBuffer::new);
                 while signal_rx.try_recv().is_err() {
                     let req = Request::builder()
                         .header(
@@ -99,22 +138,41 @@ where
                         )
                         .header("Sec-WebSocket-Protocol", format!("{}.{}", 12, implt))
                         .header_auth_token(&wsr.access_token);
-                    match try_connect(&wsr, req).await {
+                    let ws_stream = tokio::select! {
+                        ws_stream = try_connect(&wsr, req) => ws_stream,
+                        _ = record(&mut replay, &mut event_rx) => unreachable!(),
+                        _ = signal_rx.recv() => break,
+                    };
+                    match ws_stream {
                         Some(ws_stream) => {
+                            let mut fresh_rx;
+                            let event_rx = if replay.is_some() {
+                                &mut event_rx
+                            } else {
+                                fresh_rx = event_rx.resubscribe();
+                                &mut fresh_rx
+                            };
                             ws_loop(
                                 ob.clone(),
-                                event_rx.resubscribe(),
+                                signal.subscribe(),
+                                event_rx,
                                 hb_rx.resubscribe(),
                                 ws_stream,
+                                wsr.keepalive.clone(),
+                                resp_queue.clone(),
+                                replay.as_mut(),
                             )
                             .await;
                             warn!(target: super::OBC, "Disconnected from {}", wsr.url);
                         }
                         None => {
-                            tokio::time::sleep(std::time::Duration::from_secs(
-                                wsr.reconnect_interval as u64,
-                            ))
-                            .await;
+                            tokio::select! {
+                                _ = tokio::time::sleep(std::time::Duration::from_secs(
+                                    wsr.reconnect_interval as u64,
+                                )) => {}
+                                _ = record(&mut replay, &mut event_rx) => unreachable!(),
+                                _ = signal_rx.recv() => break,
+                            }
                         }
                     }
                 }
@@ -124,75 +182,106 @@ where
     }
 }
 
+/// 断线期间缓存事件，未启用重放时永远等待
+async fn record<E>(replay: &mut Option<ReplayBuffer<E>>, event_rx: &mut broadcast::Receiver<E>)
+where
+    E: Clone + EventKind,
+{
+    match replay {
+        Some(replay) => replay.record(event_rx).await,
+        None => std::future::pending().await,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn ws_loop<E, A, R, AH, EH>(
     ob: Arc<OneBot<AH, EH>>,
-    mut event_rx: broadcast::Receiver<E>,
+    mut shutdown_signal_rx: broadcast::Receiver<()>,
+    event_rx: &mut broadcast::Receiver<E>,
     mut hb_rx: broadcast::Receiver<Event>,
-    mut ws_stream: WebSocketStream<TcpStream>,
+    mut ws_stream: WebSocketStream<Stream>,
+    keepalive: Option<KeepAlive>,
+    resp_queue: QueueConfig,
+    mut replay: Option<&mut ReplayBuffer<E>>,
 ) where
-    E: ProtocolItem + Clone,
+    E: ProtocolItem + Clone + EventKind,
     A: ProtocolItem,
     R: ProtocolItem,
     AH: ActionHandler<E, A, R> + Send + Sync + 'static,
     EH: EventHandler<E, A, R> + Send + Sync + 'static,
 {
-    let (json_resp_tx, mut json_resp_rx) = tokio::sync::mpsc::unbounded_channel();
-    let (rmp_resp_tx, mut rmp_resp_rx) = tokio::sync::mpsc::unbounded_channel();
-    let mut shutdown_signal_rx = ob.get_signal_rx().unwrap(); //todo
-    let connect_evnet = Event {
-        id: "".to_owned(),
-        time: crate::util::timestamp_nano_f64(),
-        ty: "meta".to_owned(),
-        detail_type: "connect".to_owned(),
-        sub_type: "".to_owned(),
-        extra: value_map! {
-            "version": ob.version.clone(),
-        },
-    };
-    if ws_stream
-        .send(WsMsg::Text(connect_evnet.json_encode()))
-        .await
-        .is_err()
-    {
-        warn!(target: super::OBC, "ws send meta.connect event failed, disconnect");
-        return;
+    let (json_resp_tx, mut json_resp_rx) = channel(&resp_queue);
+    let (rmp_resp_tx, mut rmp_resp_rx) = channel(&resp_queue);
+    let mut keepalive = KeepAliveTimer::new(keepalive);
+    for event in super::connect_events(&ob) {
+        if ws_stream
+            .send(WsMsg::Text(event.json_encode()))
+            .await
+            .is_err()
+        {
+            warn!(
+                target: super::OBC,
+                "ws send meta.{} event failed, disconnect", event.detail_type
+            );
+            return;
+        }
     }
-    let status_event = Event {
-        id: "".to_owned(),
-        time: crate::util::timestamp_nano_f64(),
-        ty: "meta".to_owned(),
-        detail_type: "status_update".to_owned(),
-        sub_type: "".to_owned(),
-        extra: value_map! {
-            "status": ob.action_handler.gen_status(),
-        },
-    };
-    if ws_stream
-        .send(WsMsg::Text(status_event.json_encode()))
-        .await
-        .is_err()
-    {
-        warn!(target: super::OBC, "ws send meta.status_update event failed, disconnect");
-        return;
+    // replay events buffered while disconnected
+    if let Some(replay) = replay.as_mut() {
+        let mut events = replay.drain().into_iter();
+        while let Some(event) = events.next() {
+            let json = event.json_encode();
+            trace!(target: crate::WALLE_CORE, "ws replay: {}", json);
+            if ws_stream.send(WsMsg::Text(json)).await.is_err() {
+                warn!(target: super::OBC, "ws replay event failed, disconnect");
+                replay.push(event);
+                events.for_each(|e| replay.push(e));
+                return;
+            }
+        }
     }
+    let mut close = CloseCode::Normal;
     loop {
         tokio::select! {
             // shutdown
-            _ = shutdown_signal_rx.recv() => break,
+            _ = shutdown_signal_rx.recv() => {
+                close = CloseCode::Away;
+                break;
+            }
+            // keepalive ping
+            tick = keepalive.tick() => match tick {
+                KeepAliveTick::Ping => if ws_stream.send(WsMsg::Ping(vec![])).await.is_err() {
+                    break;
+                },
+                KeepAliveTick::Timeout => {
+                    warn!(target: super::OBC, "ws pong timeout, disconnect");
+                    break;
+                }
+            },
             // send event
             event = event_rx.recv() => {
                 match event {
                     Ok(event) => {
+                        if replay.as_ref().is_some_and(|r| r.is_replayed(&event)) {
+                            continue;
+                        }
                         // event will always send as json
-                        let event = event.json_encode();
-                        trace!(target: crate::WALLE_CORE, "ws send: {}", event);
-                        if ws_stream.send(WsMsg::Text(event)).await.is_err() {
-                            // send failed, break loop and close connection
+                        let json = event.json_encode();
+                        trace!(target: crate::WALLE_CORE, "ws send: {}", json);
+                        if ws_stream.send(WsMsg::Text(json)).await.is_err() {
+                            // send failed, keep it for replay, break loop and close connection
+                            if let Some(replay) = replay.as_mut() {
+                                replay.push(event);
+                            }
                             break;
                         }
                     }
-                    Err(_) => {
-                        // channel all sender are dropped or channel is fulled will break loop and close connection
+                    Err(RecvError::Lagged(n)) => {
+                        // receiver lagged, skip lost events
+                        warn!(target: super::OBC, "ws event receiver lagged, {} events skipped", n);
+                    }
+                    Err(RecvError::Closed) => {
+                        // channel all sender are dropped will break loop and close connection
                         break;
                     }
                 }
@@ -207,7 +296,10 @@ async fn ws_loop<E, A, R, AH, EH>(
                             break;
                         }
                     }
-                    Err(_) => {
+                    Err(RecvError::Lagged(n)) => {
+                        warn!(target: super::OBC, "ws heartbeat receiver lagged, {} heartbeats skipped", n);
+                    }
+                    Err(RecvError::Closed) => {
                         break;
                     }
                 }
@@ -215,6 +307,9 @@ async fn ws_loop<E, A, R, AH, EH>(
             // recv maybe action
             Some(ws_msg) = ws_stream.next() => {
                 trace!(target: crate::WALLE_CORE, "ws recv: {:?}", ws_msg);
+                if let Ok(WsMsg::Pong(_)) = ws_msg {
+                    keepalive.pong();
+                }
                 match ws_msg {
                     // handle action request
                     Ok(ws_msg) => if ws_recv(
@@ -228,7 +323,12 @@ async fn ws_loop<E, A, R, AH, EH>(
                 }
             },
             // send action response by json
-            Some(resp) = json_resp_rx.recv() => {
+            resp = json_resp_rx.recv() => {
+                // resp queue closed by overflow policy
+                let Some(resp) = resp else {
+                    close = CloseCode::Policy;
+                    break;
+                };
                 trace!(target: crate::WALLE_CORE, "ws send json: {:?}", resp);
                 // send action response
                 if ws_stream.send(WsMsg::Text(resp.json_encode())).await.is_err() {
@@ -236,7 +336,11 @@ async fn ws_loop<E, A, R, AH, EH>(
                 }
             },
             // send action response by msgpack
-            Some(resp) = rmp_resp_rx.recv() => {
+            resp = rmp_resp_rx.recv() => {
+                let Some(resp) = resp else {
+                    close = CloseCode::Policy;
+                    break;
+                };
                 trace!(target: crate::WALLE_CORE, "ws send rmp: {:?}", resp);
                 // send action response
                 if ws_stream.send(WsMsg::Binary(resp.rmp_encode())).await.is_err() {
@@ -245,16 +349,70 @@ async fn ws_loop<E, A, R, AH, EH>(
             }
         }
     }
-    ws_stream.send(WsMsg::Close(None)).await.ok();
+    if close == CloseCode::Away {
+        // 不再接收新 action，等待进行中的 action 响应，再发送已排队的事件
+        drop((json_resp_tx, rmp_resp_tx));
+        let drain = async {
+            let (mut json_done, mut rmp_done) = (false, false);
+            while !(json_done && rmp_done) {
+                tokio::select! {
+                    resp = json_resp_rx.recv(), if !json_done => match resp {
+                        Some(resp) => if ws_stream.send(WsMsg::Text(resp.json_encode())).await.is_err() {
+                            return;
+                        },
+                        None => json_done = true,
+                    },
+                    resp = rmp_resp_rx.recv(), if !rmp_done => match resp {
+                        Some(resp) => if ws_stream.send(WsMsg::Binary(resp.rmp_encode())).await.is_err() {
+                            return;
+                        },
+                        None => rmp_done = true,
+                    },
+                }
+            }
+            loop {
+                let event = match event_rx.try_recv() {
+                    Ok(event) => event,
+                    Err(TryRecvError::Lagged(_)) => continue,
+                    Err(_) => break,
+                };
+                if replay.as_ref().is_some_and(|r| r.is_replayed(&event)) {
+                    continue;
+                }
+                if ws_stream
+                    .send(WsMsg::Text(event.json_encode()))
+                    .await
+                    .is_err()
+                {
+                    if let Some(replay) = replay.as_mut() {
+                        replay.push(event);
+                    }
+                    return;
+                }
+            }
+        };
+        if tokio::time::timeout(ob.drain_timeout, drain).await.is_err() {
+            warn!(target: super::OBC, "ws drain timeout, close connection");
+        }
+    }
+    let frame = CloseFrame {
+        code: close,
+        reason: match close {
+            CloseCode::Away => "server shutting down".into(),
+            CloseCode::Policy => "queue overflow".into(),
+            _ => "".into(),
+        },
+    };
+    ws_stream.send(WsMsg::Close(Some(frame))).await.ok();
 }
 
 // handle ws received maybe action
 pub(crate) async fn ws_recv<E, A, R, AH, EH>(
     ws_msg: WsMsg,
     ob: &Arc<OneBot<AH, EH>>,
-    ws_stream: &mut WebSocketStream<TcpStream>,
-    json_resp_sender: &tokio::sync::mpsc::UnboundedSender<Echo<R>>,
-    rmp_resp_sender: &tokio::sync::mpsc::UnboundedSender<Echo<R>>,
+    ws_stream: &mut WebSocketStream<Stream>,
+    json_resp_sender: &QueueSender<Echo<R>>,
+    rmp_resp_sender: &QueueSender<Echo<R>>,
 ) -> bool
 where
     E: ProtocolItem,
@@ -263,38 +421,13 @@ where
     AH: ActionHandler<E, A, R> + Send + Sync + 'static,
     EH: EventHandler<E, A, R> + Send + Sync + 'static,
 {
-    let err_handle = |a: Echo<ValueMap>, msg: String| -> Echo<Resp> {
-        let (_, echo_s) = a.unpack();
-        warn!(target: crate::WALLE_CORE, "action warn: {}", msg);
-        if msg.starts_with("missing field") {
-            echo_s.pack(Resp::from(resp_error::bad_segment_data(msg)))
-        } else {
-            echo_s.pack(resp_error::unsupported_action(msg).into())
-        }
-    };
-
     match ws_msg {
         WsMsg::Text(text) => match serde_json::from_str::<'_, Echo<A>>(&text) {
-            Ok(action) => {
-                let (action, echos) = action.unpack();
-                let tx = json_resp_sender.clone();
-                let ob = ob.clone();
-                tokio::spawn(async move {
-                    tokio::time::timeout(Duration::from_secs(10), async move {
-                        match ob.handle_action(action).await {
-                            Ok(r) => {
-                                tx.send(echos.pack(r)).ok();
-                            }
-                            Err(e) => warn!(target: super::OBC, "handle action error: {}", e),
-                        }
-                    })
-                    .await
-                });
-                //todo
-            }
+            Ok(action) => super::spawn_action(ob, action, json_resp_sender.clone()),
             Err(msg) => match serde_json::from_str(&text) {
                 Ok(a) => {
-                    let resp = serde_json::to_string(&err_handle(a, msg.to_string())).unwrap();
+                    let resp =
+                        serde_json::to_string(&super::action_error(a, msg.to_string())).unwrap();
                     if ws_stream.send(WsMsg::Text(resp)).await.is_err() {
                         return true;
                     }
@@ -309,25 +442,10 @@ where
             },
         },
         WsMsg::Binary(v) => match rmp_serde::from_read::<_, Echo<A>>(v.as_slice()) {
-            Ok(action) => {
-                let (action, echos) = action.unpack();
-                let tx = rmp_resp_sender.clone();
-                let ob = ob.clone();
-                tokio::spawn(async move {
-                    tokio::time::timeout(Duration::from_secs(10), async move {
-                        match ob.handle_action(action).await {
-                            Ok(r) => {
-                                tx.send(echos.pack(r)).ok();
-                            }
-                            Err(e) => warn!(target: super::OBC, "handle action error: {}", e),
-                        }
-                    })
-                    .await
-                });
-            }
+            Ok(action) => super::spawn_action(ob, action, rmp_resp_sender.clone()),
             Err(msg) => match rmp_serde::from_read(v.as_slice()) {
                 Ok(a) => {
-                    let resp = rmp_serde::to_vec(&err_handle(a, msg.to_string())).unwrap();
+                    let resp = rmp_serde::to_vec(&super::action_error(a, msg.to_string())).unwrap();
                     if ws_stream.send(WsMsg::Binary(resp)).await.is_err() {
                         return true;
                     }
@@ -337,11 +455,7 @@ where
                 }
             },
         },
-        WsMsg::Ping(b) => {
-            if ws_stream.send(WsMsg::Pong(b)).await.is_err() {
-                return true;
-            }
-        }
+        WsMsg::Ping(b) => return ws_stream.send(WsMsg::Pong(b)).await.is_err(),
         WsMsg::Close(_) => return true,
         _ => {}
     }
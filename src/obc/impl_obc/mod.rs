@@ -1,32 +1,88 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex as StdMutex, RwLock as StdRwLock};
+use std::time::Duration;
 
+#[cfg(any(feature = "http", feature = "websocket"))]
+use super::components::listen_name;
+use super::components::Components;
+use super::queue::QueueSender;
 use super::OBC;
-use crate::event::Event;
-use crate::util::ProtocolItem;
+#[cfg(feature = "http")]
+use crate::config::{HttpClient, HttpServer};
+use crate::config::{MemoryServer, QueueConfig};
+#[cfg(feature = "websocket")]
+use crate::config::{WebSocketClient, WebSocketServer};
+use crate::event::{Event, EventKind};
+use crate::lifecycle::{RestartPolicy, TaskStatus};
+use crate::resp::{resp_error, Resp};
+use crate::util::{Echo, ProtocolItem, ValueMap};
 use crate::WalleResult;
 use crate::{ActionHandler, EventHandler, OneBot};
 use tokio::sync::broadcast;
 use tokio::task::JoinHandle;
+use tracing::warn;
 
 #[cfg(feature = "http")]
 mod impl_http;
+mod impl_memory;
+#[cfg(feature = "http")]
+mod impl_sse;
 #[cfg(feature = "websocket")]
 mod impl_ws;
+#[cfg(feature = "websocket")]
+mod replay;
 
 /// OneBotConnect 实现端实现
 ///
 /// ImplOBC impl EventHandler 接收 Event 并外发处理
 ///
-/// ImplOBC 仅对 Event 泛型要求 Clone trait
+/// ImplOBC 仅对 Event 泛型要求 Clone + EventKind trait
 pub struct ImplOBC<E> {
     pub implt: String,
-    pub(crate) event_tx: tokio::sync::broadcast::Sender<E>,
-    pub(crate) hb_tx: tokio::sync::broadcast::Sender<crate::event::Event>,
+    /// 未设置 `ImplConfig.event_capacity` 时使用的事件广播容量
+    capacity: usize,
+    broadcast: StdRwLock<Broadcast<E>>,
+    pub(crate) resp_queue: StdMutex<QueueConfig>,
+    #[cfg(feature = "file-store")]
+    pub(crate) file_store: Option<Arc<crate::file_store::FileStore>>,
+    pub(crate) components: Arc<Components>,
+    /// 最近一次应用的配置，用于重启失败的组件
+    pub(crate) applied: StdMutex<Option<crate::config::ImplConfig>>,
+}
+
+/// 事件与心跳广播，容量变化时整体替换
+struct Broadcast<E> {
+    capacity: usize,
+    event_tx: broadcast::Sender<E>,
+    hb_tx: broadcast::Sender<Event>,
+}
+
+impl<E: Clone> Broadcast<E> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            event_tx: broadcast::channel(capacity).0,
+            hb_tx: broadcast::channel(capacity).0,
+        }
+    }
+}
+
+/// ImplOBC 运行组件，每个监听、连接设置项与心跳各为一个组件
+enum ImplComponent {
+    #[cfg(feature = "websocket")]
+    WebSocket(WebSocketServer),
+    #[cfg(feature = "websocket")]
+    WebSocketRev(WebSocketClient),
+    Memory(MemoryServer),
+    #[cfg(feature = "http")]
+    Http(HttpServer),
+    #[cfg(feature = "http")]
+    HttpWebhook(HttpClient),
+    Heartbeat(u32),
 }
 
 impl<E, A, R> EventHandler<E, A, R> for ImplOBC<E>
 where
-    E: ProtocolItem + Clone,
+    E: ProtocolItem + Clone + EventKind,
     A: ProtocolItem,
     R: ProtocolItem,
 {
@@ -40,43 +96,199 @@ where
         AH: ActionHandler<E, A, R> + Send + Sync + 'static,
         EH: EventHandler<E, A, R> + Send + Sync + 'static,
     {
-        let mut tasks = vec![];
-        #[cfg(feature = "websocket")]
-        {
-            self.ws(ob, config.websocket, &mut tasks).await?;
-            self.wsr(ob, config.websocket_rev, &mut tasks).await?;
-        }
-        #[cfg(feature = "http")]
-        {
-            self.http(ob, config.http, &mut tasks).await?;
-            self.webhook(ob, config.http_webhook, &mut tasks).await?;
+        self.apply_config(ob, config).await?;
+        Ok(vec![self.components.supervise(ob.get_signal_rx()?)])
+    }
+    async fn reconfigure<AH, EH>(
+        &self,
+        ob: &Arc<OneBot<AH, EH>>,
+        config: crate::config::ImplConfig,
+    ) -> WalleResult<()>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        self.apply_config(ob, config).await
+    }
+    fn validate_config(&self, config: &crate::config::ImplConfig) -> WalleResult<()> {
+        crate::config::LoadConfig::validate(config)
+    }
+    fn task_status(&self) -> Vec<TaskStatus> {
+        self.components.status()
+    }
+    async fn restart_failed<AH, EH>(&self, ob: &Arc<OneBot<AH, EH>>, policy: &RestartPolicy)
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        if !self.components.reap(policy).await {
+            return;
         }
-        if config.heartbeat.enabled {
-            tasks.push(start_hb(ob, config.heartbeat.interval, self.hb_tx.clone()))
+        let applied = self.applied.lock().unwrap().clone();
+        if let Some(config) = applied {
+            if let Err(e) = self.apply_config(ob, config).await {
+                warn!(target: OBC, "restart components failed: {}", e);
+            }
         }
-        Ok(tasks)
     }
     async fn call<AH, EH>(&self, event: E, _ob: &Arc<OneBot<AH, EH>>) -> WalleResult<()>
     where
         AH: ActionHandler<E, A, R> + Send + Sync + 'static,
         EH: EventHandler<E, A, R> + Send + Sync + 'static,
     {
-        self.event_tx.send(event).ok();
+        self.broadcast.read().unwrap().event_tx.send(event).ok();
         Ok(())
     }
 }
 
+/// ImplOBC 默认事件广播容量
+pub const DEFAULT_EVENT_CAPACITY: usize = 1024;
+
 impl<E> ImplOBC<E> {
     pub fn new(implt: String) -> Self
     where
         E: Clone,
     {
-        let (event_tx, _) = tokio::sync::broadcast::channel(1024); //todo
-        let (hb_tx, _) = tokio::sync::broadcast::channel(1024);
+        Self::with_capacity(implt, DEFAULT_EVENT_CAPACITY)
+    }
+    /// 指定事件广播容量，连接接收落后超过该容量时将跳过最旧的事件
+    ///
+    /// 设置 `ImplConfig.event_capacity` 时以配置为准
+    pub fn with_capacity(implt: String, capacity: usize) -> Self
+    where
+        E: Clone,
+    {
+        let capacity = capacity.max(1);
         Self {
             implt,
-            event_tx,
-            hb_tx,
+            capacity,
+            broadcast: StdRwLock::new(Broadcast::new(capacity)),
+            resp_queue: StdMutex::default(),
+            #[cfg(feature = "file-store")]
+            file_store: None,
+            components: Arc::default(),
+            applied: StdMutex::default(),
+        }
+    }
+    /// 设置 FileStore，HTTP 服务设置 `files` 后将提供文件下载并为 `get_file` 生成签名 url
+    #[cfg(feature = "file-store")]
+    pub fn with_file_store(mut self, file_store: Arc<crate::file_store::FileStore>) -> Self {
+        self.file_store = Some(file_store);
+        self
+    }
+    pub(crate) fn subscribe_events(&self) -> broadcast::Receiver<E> {
+        self.broadcast.read().unwrap().event_tx.subscribe()
+    }
+    pub(crate) fn subscribe_hb(&self) -> broadcast::Receiver<Event> {
+        self.broadcast.read().unwrap().hb_tx.subscribe()
+    }
+}
+
+impl<E> ImplOBC<E>
+where
+    E: ProtocolItem + Clone + EventKind,
+{
+    /// 对比运行中的组件，停止被移除或变化的组件后启动新组件
+    ///
+    /// resp 队列设置变化时重启所有 WebSocket 与内存传输组件，
+    /// 事件广播容量变化时重启所有组件
+    async fn apply_config<A, R, AH, EH>(
+        &self,
+        ob: &Arc<OneBot<AH, EH>>,
+        config: crate::config::ImplConfig,
+    ) -> WalleResult<()>
+    where
+        A: ProtocolItem,
+        R: ProtocolItem,
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        let _reload = self.components.lock().await;
+        *self.applied.lock().unwrap() = Some(config.clone());
+        let queue = config.resp_queue.unwrap_or_default();
+        *self.resp_queue.lock().unwrap() = queue.clone();
+        let capacity = config.event_capacity.unwrap_or(self.capacity).max(1);
+        let mut wanted = vec![];
+        #[cfg(feature = "websocket")]
+        {
+            wanted.extend(config.websocket.into_iter().map(|c| {
+                let key = Components::key("websocket", &(&c, &queue, capacity));
+                (key, ImplComponent::WebSocket(c))
+            }));
+            wanted.extend(config.websocket_rev.into_iter().map(|c| {
+                let key = Components::key("websocket_rev", &(&c, &queue, capacity));
+                (key, ImplComponent::WebSocketRev(c))
+            }));
+        }
+        wanted.extend(config.memory.into_iter().map(|c| {
+            let key = Components::key("memory", &(&c, &queue, capacity));
+            (key, ImplComponent::Memory(c))
+        }));
+        #[cfg(feature = "http")]
+        {
+            wanted.extend(config.http.into_iter().map(|c| {
+                let key = Components::key("http", &(&c, capacity));
+                (key, ImplComponent::Http(c))
+            }));
+            wanted.extend(config.http_webhook.into_iter().map(|c| {
+                let key = Components::key("http_webhook", &(&c, capacity));
+                (key, ImplComponent::HttpWebhook(c))
+            }));
+        }
+        if config.heartbeat.enabled {
+            let interval = config.heartbeat.interval;
+            wanted.push((
+                Components::key("heartbeat", &(interval, capacity)),
+                ImplComponent::Heartbeat(interval),
+            ));
+        }
+        let start = self.components.retain(wanted).await;
+        // 使用旧广播的组件均已停止
+        if self.broadcast.read().unwrap().capacity != capacity {
+            *self.broadcast.write().unwrap() = Broadcast::new(capacity);
+        }
+        for (key, component) in start {
+            let signal = self.components.starting(&key, component.name());
+            let mut tasks = vec![];
+            let result = match component {
+                #[cfg(feature = "websocket")]
+                ImplComponent::WebSocket(c) => self.ws(ob, vec![c], &signal, &mut tasks).await,
+                #[cfg(feature = "websocket")]
+                ImplComponent::WebSocketRev(c) => self.wsr(ob, vec![c], &signal, &mut tasks).await,
+                ImplComponent::Memory(c) => self.memory(ob, vec![c], &signal, &mut tasks).await,
+                #[cfg(feature = "http")]
+                ImplComponent::Http(c) => self.http(ob, vec![c], &signal, &mut tasks).await,
+                #[cfg(feature = "http")]
+                ImplComponent::HttpWebhook(c) => {
+                    self.webhook(ob, vec![c], &signal, &mut tasks).await
+                }
+                ImplComponent::Heartbeat(interval) => {
+                    let signal_rx = signal.subscribe();
+                    let hb_tx = self.broadcast.read().unwrap().hb_tx.clone();
+                    tasks.push(start_hb(ob, interval, hb_tx, signal_rx));
+                    Ok(())
+                }
+            };
+            self.components
+                .started(&key, tasks, result, &ob.restart_policy)?;
+        }
+        Ok(())
+    }
+}
+
+impl ImplComponent {
+    fn name(&self) -> String {
+        match self {
+            #[cfg(feature = "websocket")]
+            Self::WebSocket(c) => listen_name("websocket", &c.host, c.port, &c.path, &c.unix),
+            #[cfg(feature = "websocket")]
+            Self::WebSocketRev(c) => format!("websocket_rev {}", c.url),
+            Self::Memory(c) => format!("memory {}", c.name),
+            #[cfg(feature = "http")]
+            Self::Http(c) => listen_name("http", &c.host, c.port, &c.path, &c.unix),
+            #[cfg(feature = "http")]
+            Self::HttpWebhook(c) => format!("http_webhook {}", c.url),
+            Self::Heartbeat(_) => "heartbeat".to_owned(),
         }
     }
 }
@@ -93,7 +305,8 @@ where
         detail_type: "heartbeat".to_string(),
         sub_type: "".to_string(),
         extra: crate::value_map! {
-            "interval": interval,
+            // OneBot 12 心跳间隔单位为毫秒
+            "interval": interval.saturating_mul(1000),
             "status": status
         },
     }
@@ -103,21 +316,150 @@ fn start_hb<AH, EH, E, A, R>(
     ob: &Arc<OneBot<AH, EH>>,
     interval: u32,
     hb_tx: broadcast::Sender<Event>,
+    mut signal: broadcast::Receiver<()>,
 ) -> JoinHandle<()>
 where
     AH: ActionHandler<E, A, R> + Send + Sync + 'static,
     EH: Send + Sync + 'static,
 {
     let hb_tx = Arc::new(hb_tx);
-    let mut signal = ob.get_signal_rx().unwrap();
     let ob = ob.clone();
     tokio::spawn(async move {
         loop {
-            if signal.try_recv().is_ok() {
-                break;
-            }
             hb_tx.send(build_hb(&ob, interval).await).ok();
-            tokio::time::sleep(std::time::Duration::from_secs(interval as u64)).await;
+            tokio::select! {
+                _ = signal.recv() => break,
+                _ = tokio::time::sleep(std::time::Duration::from_secs(interval as u64)) => {}
+            }
         }
     })
 }
+
+/// 连接建立时依次发送的 `meta.connect` 与 `meta.status_update` 事件
+pub(crate) fn connect_events<AH, EH, E, A, R>(ob: &OneBot<AH, EH>) -> [Event; 2]
+where
+    AH: ActionHandler<E, A, R> + Send + Sync,
+{
+    let meta = |detail_type: &str, extra| Event {
+        id: "".to_owned(),
+        time: crate::util::timestamp_nano_f64(),
+        ty: "meta".to_owned(),
+        detail_type: detail_type.to_owned(),
+        sub_type: "".to_owned(),
+        extra,
+    };
+    [
+        meta(
+            "connect",
+            crate::value_map! { "version": ob.version.clone() },
+        ),
+        meta(
+            "status_update",
+            crate::value_map! { "status": ob.action_handler.gen_status() },
+        ),
+    ]
+}
+
+/// 无法解析为 Action 时的错误响应
+pub(crate) fn action_error(action: Echo<ValueMap>, msg: String) -> Echo<Resp> {
+    let (_, echo_s) = action.unpack();
+    warn!(target: crate::WALLE_CORE, "action warn: {}", msg);
+    if msg.starts_with("missing field") {
+        echo_s.pack(Resp::from(resp_error::bad_segment_data(msg)))
+    } else {
+        echo_s.pack(resp_error::unsupported_action(msg).into())
+    }
+}
+
+/// 处理 Action，响应发送至 `resp_tx`
+pub(crate) fn spawn_action<E, A, R, AH, EH>(
+    ob: &Arc<OneBot<AH, EH>>,
+    action: Echo<A>,
+    resp_tx: QueueSender<Echo<R>>,
+) where
+    A: ProtocolItem,
+    R: ProtocolItem,
+    AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+    EH: EventHandler<E, A, R> + Send + Sync + 'static,
+{
+    let (action, echos) = action.unpack();
+    let ob = ob.clone();
+    tokio::spawn(async move {
+        tokio::time::timeout(Duration::from_secs(10), async move {
+            match ob.handle_action(action).await {
+                Ok(r) => {
+                    resp_tx.send(echos.pack(r)).await.ok();
+                }
+                Err(e) => warn!(target: OBC, "handle action error: {}", e),
+            }
+        })
+        .await
+    });
+}
+
+#[tokio::test]
+async fn event_capacity_test() {
+    use crate::action::Action;
+    use crate::config::{Heartbeat, ImplConfig};
+    use crate::obc::memory::connect;
+    use crate::structs::{Selft, Version};
+    use crate::testing::{MockActionHandler, MockEvents};
+
+    let config = |event_capacity| ImplConfig {
+        memory: vec![MemoryServer {
+            name: "capacity_test".to_owned(),
+        }],
+        websocket_rev: vec![],
+        heartbeat: Heartbeat {
+            enabled: false,
+            interval: 4,
+        },
+        event_capacity,
+        ..Default::default()
+    };
+    let ob = Arc::new(OneBot::new(
+        MockActionHandler::new(),
+        ImplOBC::<Event>::with_capacity("test".to_owned(), 16),
+        Version {
+            implt: "test".to_owned(),
+            version: crate::VERSION.to_owned(),
+            onebot_version: 12.to_string(),
+        },
+    ));
+    let capacity = || ob.event_handler.broadcast.read().unwrap().capacity;
+    ob.start::<Event, Action, Resp>((), config(None), true)
+        .await
+        .unwrap();
+    assert_eq!(capacity(), 16);
+    let mut old = connect("capacity_test").unwrap();
+
+    // 容量变化时重启组件，新连接订阅新的广播
+    ob.reconfigure::<Event, Action, Resp>(None, Some(config(Some(2))))
+        .await
+        .unwrap();
+    assert_eq!(capacity(), 2);
+    tokio::time::timeout(Duration::from_secs(1), async {
+        while old.recv().await.is_some() {}
+    })
+    .await
+    .unwrap();
+    let mut client = connect("capacity_test").unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let selft = Selft {
+        platform: "test".to_owned(),
+        user_id: "bot".to_owned(),
+    };
+    let event = MockEvents::new(selft).private_message("alice", "hi");
+    let id = event.id.clone();
+    ob.handle_event::<Event, Action, Resp>(event).await.unwrap();
+    loop {
+        let text = tokio::time::timeout(Duration::from_secs(1), client.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        if text.contains(&id) {
+            break;
+        }
+    }
+    ob.shutdown::<Event, Action, Resp>(true).await.unwrap();
+}
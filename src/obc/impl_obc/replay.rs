@@ -0,0 +1,115 @@
+//! 反向 WebSocket 断线期间的事件重放缓冲
+
+use std::collections::{HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+use tracing::warn;
+
+use crate::config::ReplayConfig;
+use crate::event::EventKind;
+
+/// 按数量与时长限制的事件缓冲，按 `Event.id` 去重
+pub(crate) struct ReplayBuffer<E> {
+    events: VecDeque<(Instant, E)>,
+    capacity: usize,
+    max_age: Duration,
+    /// 上一次重放的事件 id，用于过滤重连后重复收到的事件
+    replayed: HashSet<String>,
+}
+
+impl<E: EventKind> ReplayBuffer<E> {
+    pub(crate) fn new(config: &ReplayConfig) -> Self {
+        Self {
+            events: VecDeque::new(),
+            capacity: config.capacity,
+            max_age: Duration::from_secs(config.max_age as u64),
+            replayed: HashSet::default(),
+        }
+    }
+    /// 缓存一个未能发送的事件，已缓存的同 id 事件将被忽略
+    pub(crate) fn push(&mut self, event: E) {
+        if self.capacity == 0 {
+            return;
+        }
+        if !event.id().is_empty() && self.events.iter().any(|(_, e)| e.id() == event.id()) {
+            return;
+        }
+        self.events.push_back((Instant::now(), event));
+        if self.events.len() > self.capacity {
+            self.events.pop_front();
+            warn!(target: super::OBC, "replay buffer is full, drop the oldest event");
+        }
+    }
+    /// 取出所有未过期的缓存事件
+    pub(crate) fn drain(&mut self) -> Vec<E> {
+        let max_age = self.max_age;
+        self.events.retain(|(t, _)| t.elapsed() <= max_age);
+        self.replayed = self
+            .events
+            .iter()
+            .map(|(_, e)| e.id().to_owned())
+            .filter(|id| !id.is_empty())
+            .collect();
+        self.events.drain(..).map(|(_, e)| e).collect()
+    }
+    /// 事件是否已在重连时重放过
+    pub(crate) fn is_replayed(&self, event: &E) -> bool {
+        self.replayed.contains(event.id())
+    }
+    /// 持续从广播接收事件并缓存，用于连接断开期间
+    ///
+    /// 该 future 永远不会返回，可以安全的在 `select!` 中被取消
+    pub(crate) async fn record(&mut self, event_rx: &mut Receiver<E>)
+    where
+        E: Clone,
+    {
+        loop {
+            match event_rx.recv().await {
+                Ok(event) => self.push(event),
+                Err(RecvError::Lagged(n)) => {
+                    warn!(target: super::OBC, "replay receiver lagged, {} events skipped", n)
+                }
+                Err(RecvError::Closed) => std::future::pending().await,
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn replay_test() {
+    use crate::event::Event;
+
+    let event = |id: &str| Event {
+        id: id.to_owned(),
+        time: 0.0,
+        ty: "message".to_owned(),
+        detail_type: "private".to_owned(),
+        sub_type: "".to_owned(),
+        extra: Default::default(),
+    };
+    let mut buffer = ReplayBuffer::new(&ReplayConfig {
+        capacity: 2,
+        max_age: 60,
+    });
+    let (tx, mut rx) = tokio::sync::broadcast::channel(8);
+    for id in ["1", "2", "2", "3"] {
+        tx.send(event(id)).unwrap();
+    }
+    tokio::time::timeout(Duration::from_millis(10), buffer.record(&mut rx))
+        .await
+        .ok();
+    let ids = buffer.drain().into_iter().map(|e| e.id).collect::<Vec<_>>();
+    assert_eq!(ids, vec!["2", "3"]);
+    assert!(buffer.is_replayed(&event("3")));
+    assert!(!buffer.is_replayed(&event("1")));
+    assert!(buffer.drain().is_empty());
+
+    let mut buffer = ReplayBuffer::new(&ReplayConfig {
+        capacity: 2,
+        max_age: 0,
+    });
+    buffer.push(event("1"));
+    std::thread::sleep(Duration::from_millis(5));
+    assert!(buffer.drain().is_empty());
+}
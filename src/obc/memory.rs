@@ -0,0 +1,180 @@
+//! 进程内内存传输
+//!
+//! 实现端以名称注册监听，应用端以名称连接，连接双方通过 tokio channel 交换与 WebSocket 文本帧相同的 json 文本，
+//! 监听在 drop 时注销
+
+use std::collections::HashMap;
+use std::io::{Error as IoError, ErrorKind};
+use std::sync::{Mutex, OnceLock};
+
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+use crate::WalleResult;
+
+type Listeners = Mutex<HashMap<String, UnboundedSender<MemoryStream>>>;
+
+fn listeners() -> &'static Listeners {
+    static LISTENERS: OnceLock<Listeners> = OnceLock::new();
+    LISTENERS.get_or_init(Listeners::default)
+}
+
+/// 内存连接的一端
+#[derive(Debug)]
+pub(crate) struct MemoryStream {
+    tx: UnboundedSender<String>,
+    rx: UnboundedReceiver<String>,
+}
+
+impl MemoryStream {
+    #[cfg_attr(not(feature = "app-obc"), allow(dead_code))]
+    fn pair() -> (Self, Self) {
+        let (tx0, rx0) = unbounded_channel();
+        let (tx1, rx1) = unbounded_channel();
+        (Self { tx: tx0, rx: rx1 }, Self { tx: tx1, rx: rx0 })
+    }
+    /// 发送文本，对端已关闭时返回 false
+    pub(crate) fn send(&self, text: String) -> bool {
+        self.tx.send(text).is_ok()
+    }
+    /// 接收文本，对端已关闭时返回 None
+    pub(crate) async fn recv(&mut self) -> Option<String> {
+        self.rx.recv().await
+    }
+}
+
+/// 内存监听
+#[cfg_attr(not(feature = "impl-obc"), allow(dead_code))]
+pub(crate) struct MemoryListener {
+    name: String,
+    tx: UnboundedSender<MemoryStream>,
+    rx: UnboundedReceiver<MemoryStream>,
+}
+
+#[cfg_attr(not(feature = "impl-obc"), allow(dead_code))]
+impl MemoryListener {
+    /// 以名称注册监听，同一名称同时只能存在一个监听
+    pub(crate) fn bind(name: &str) -> WalleResult<Self> {
+        let mut listeners = listeners().lock().unwrap();
+        if listeners.get(name).is_some_and(|tx| !tx.is_closed()) {
+            return Err(IoError::new(
+                ErrorKind::AddrInUse,
+                format!("memory listener {} already exists", name),
+            )
+            .into());
+        }
+        let (tx, rx) = unbounded_channel();
+        listeners.insert(name.to_owned(), tx.clone());
+        Ok(Self {
+            name: name.to_owned(),
+            tx,
+            rx,
+        })
+    }
+    pub(crate) async fn accept(&mut self) -> Option<MemoryStream> {
+        self.rx.recv().await
+    }
+}
+
+impl Drop for MemoryListener {
+    fn drop(&mut self) {
+        let mut listeners = listeners().lock().unwrap();
+        if listeners
+            .get(&self.name)
+            .is_some_and(|tx| tx.same_channel(&self.tx))
+        {
+            listeners.remove(&self.name);
+        }
+    }
+}
+
+/// 以名称连接监听
+#[cfg_attr(not(feature = "app-obc"), allow(dead_code))]
+pub(crate) fn connect(name: &str) -> WalleResult<MemoryStream> {
+    let (local, remote) = MemoryStream::pair();
+    listeners()
+        .lock()
+        .unwrap()
+        .get(name)
+        .and_then(|tx| tx.send(remote).ok())
+        .ok_or_else(|| {
+            IoError::new(
+                ErrorKind::ConnectionRefused,
+                format!("memory listener {} not found", name),
+            )
+        })?;
+    Ok(local)
+}
+
+#[cfg(all(feature = "impl-obc", feature = "app-obc"))]
+#[tokio::test]
+async fn memory_test() {
+    use crate::action::Action;
+    use crate::config::{MemoryClient, MemoryServer};
+    use crate::event::Event;
+    use crate::resp::Resp;
+    use crate::structs::Selft;
+    use crate::testing::{Loopback, MockActionHandler, MockEventHandler, MockEvents};
+    use crate::value_map;
+    use std::time::Duration;
+
+    {
+        let mut listener = MemoryListener::bind("memory_test_raw").unwrap();
+        assert!(MemoryListener::bind("memory_test_raw").is_err());
+        let mut client = connect("memory_test_raw").unwrap();
+        let mut server = listener.accept().await.unwrap();
+        assert!(client.send("ping".to_owned()));
+        assert_eq!(server.recv().await.unwrap(), "ping");
+        drop(server);
+        assert!(client.recv().await.is_none());
+    }
+    assert!(connect("memory_test_raw").is_err());
+
+    let selft = Selft {
+        platform: "test".to_owned(),
+        user_id: "bot".to_owned(),
+    };
+    let ah = MockActionHandler::new()
+        .with_bot(selft.clone())
+        .with_resp("get_self_info", value_map! { "user_id": "bot" });
+    let eh = MockEventHandler::new();
+    let loopback = Loopback::start_memory(
+        ah.clone(),
+        (),
+        eh.clone(),
+        (),
+        MemoryServer {
+            name: "memory_test".to_owned(),
+        },
+        MemoryClient {
+            name: "memory_test".to_owned(),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    MockEvents::new(selft.clone())
+        .inject(&loopback.impl_ob, |events| {
+            events.private_message("alice", "hi")
+        })
+        .await
+        .unwrap();
+    let event = eh
+        .wait_for(|e| e.ty == "message", Duration::from_secs(1))
+        .await
+        .unwrap();
+    assert_eq!(event.detail_type, "private");
+
+    let resp: Resp = loopback
+        .app_ob
+        .handle_action::<Event, _, _>(Action {
+            action: "get_self_info".to_owned(),
+            selft: Some(selft),
+            params: value_map!(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(resp.retcode, 0);
+    assert_eq!(ah.called("get_self_info").len(), 1);
+    loopback.shutdown().await;
+}
@@ -4,8 +4,13 @@ pub const OBC: &str = "Walle-OBC";
 
 #[cfg(feature = "app-obc")]
 mod app_obc;
+mod components;
 #[cfg(feature = "impl-obc")]
 mod impl_obc;
+mod memory;
+#[cfg(any(feature = "http", feature = "websocket"))]
+mod net;
+mod queue;
 #[cfg(feature = "websocket")]
 mod ws_util;
 
@@ -0,0 +1,879 @@
+//! TCP / Unix domain socket 连接
+//!
+//! 服务端设置 `unix` 后监听 Unix socket 而不是 `host:port`，客户端 `url` 可以使用
+//! `unix:{socket 路径}` 或 `unix:{socket 路径}:{请求路径}` 地址
+//!
+//! 同一进程内监听相同地址的服务端共享监听，按请求路径分发连接
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::net::{TcpListener, TcpSocket, TcpStream};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::mpsc;
+use tracing::{trace, warn};
+
+use crate::config::UnixSocket;
+use crate::WalleResult;
+
+/// 解析 `unix:` 地址，返回 socket 路径与请求路径，非 `unix:` 地址返回 None
+pub(crate) fn parse_unix(url: &str) -> Option<(&str, &str)> {
+    let addr = url.strip_prefix("unix:")?;
+    Some(match addr.rfind(":/") {
+        Some(idx) => (&addr[..idx], &addr[idx + 1..]),
+        None => (addr, "/"),
+    })
+}
+
+/// TCP 或 Unix socket 连接
+#[derive(Debug)]
+pub(crate) enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+    /// 共享监听分发时已读取的请求头部分，读取时先返回该部分
+    Rewind(Vec<u8>, Box<Stream>),
+}
+
+impl Stream {
+    /// 连接 Unix socket
+    pub(crate) async fn connect_unix(path: &str) -> io::Result<Self> {
+        #[cfg(unix)]
+        {
+            UnixStream::connect(path).await.map(Self::Unix)
+        }
+        #[cfg(not(unix))]
+        {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("unix socket {} is not supported on this platform", path),
+            ))
+        }
+    }
+    /// 是否为共享监听按请求路径分发的连接
+    ///
+    /// 分发仅依据第一个请求，HTTP 服务端不应在该连接上保持 keep-alive
+    #[cfg_attr(not(feature = "http"), allow(dead_code))]
+    pub(crate) fn is_routed(&self) -> bool {
+        matches!(self, Self::Rewind(..))
+    }
+}
+
+macro_rules! delegate {
+    ($stream: expr, $s: ident => $e: expr) => {
+        match $stream {
+            Stream::Tcp($s) => $e,
+            #[cfg(unix)]
+            Stream::Unix($s) => $e,
+            Stream::Rewind(_, $s) => $e,
+        }
+    };
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if let Stream::Rewind(prefix, _) = this {
+            if !prefix.is_empty() {
+                let n = prefix.len().min(buf.remaining());
+                buf.put_slice(&prefix[..n]);
+                prefix.drain(..n);
+                return Poll::Ready(Ok(()));
+            }
+        }
+        delegate!(this, s => Pin::new(s).poll_read(cx, buf))
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        delegate!(self.get_mut(), s => Pin::new(s).poll_write(cx, buf))
+    }
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        delegate!(self.get_mut(), s => Pin::new(s).poll_write_vectored(cx, bufs))
+    }
+    fn is_write_vectored(&self) -> bool {
+        delegate!(self, s => s.is_write_vectored())
+    }
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        delegate!(self.get_mut(), s => Pin::new(s).poll_flush(cx))
+    }
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        delegate!(self.get_mut(), s => Pin::new(s).poll_shutdown(cx))
+    }
+}
+
+/// TCP 或 Unix socket 监听，Unix socket 文件在 drop 时移除
+enum Socket {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener, String),
+}
+
+impl Socket {
+    fn bind(host: IpAddr, port: u16, unix: &Option<UnixSocket>) -> io::Result<Self> {
+        match unix {
+            None => {
+                // 与 TcpListener::bind 相同，非 windows 平台设置 SO_REUSEADDR
+                let addr = SocketAddr::new(host, port);
+                let socket = match addr {
+                    SocketAddr::V4(_) => TcpSocket::new_v4()?,
+                    SocketAddr::V6(_) => TcpSocket::new_v6()?,
+                };
+                #[cfg(not(windows))]
+                socket.set_reuseaddr(true)?;
+                socket.bind(addr)?;
+                Ok(Self::Tcp(socket.listen(1024)?))
+            }
+            #[cfg(unix)]
+            Some(unix) => Ok(Self::Unix(bind_unix(unix)?, unix.path.clone())),
+            #[cfg(not(unix))]
+            Some(unix) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "unix socket {} is not supported on this platform",
+                    unix.path
+                ),
+            )),
+        }
+    }
+    async fn accept(&self) -> io::Result<(Stream, String)> {
+        match self {
+            Self::Tcp(l) => {
+                let (s, addr) = l.accept().await?;
+                Ok((Stream::Tcp(s), addr.to_string()))
+            }
+            #[cfg(unix)]
+            Self::Unix(l, path) => {
+                let (s, _) = l.accept().await?;
+                Ok((Stream::Unix(s), format!("unix:{}", path)))
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Socket {
+    fn drop(&mut self) {
+        if let Self::Unix(_, path) = self {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// 端点在共享监听上占用的请求路径
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum RoutePath {
+    Exact(String),
+    /// 以该路径开头的所有请求路径
+    #[cfg_attr(not(all(feature = "impl-obc", feature = "http")), allow(dead_code))]
+    Prefix(String),
+}
+
+impl RoutePath {
+    /// 服务端 `path` 设置对应的路径，None 时为 `/`
+    pub(crate) fn endpoint(path: &Option<String>) -> Self {
+        Self::Exact(path.clone().unwrap_or_else(|| "/".to_owned()))
+    }
+    fn matches(&self, path: &str) -> bool {
+        match self {
+            Self::Exact(p) => p == path,
+            Self::Prefix(p) => path.starts_with(p.as_str()),
+        }
+    }
+    fn conflicts(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Exact(a), Self::Exact(b)) => a == b,
+            (Self::Prefix(p), Self::Exact(e)) | (Self::Exact(e), Self::Prefix(p)) => {
+                e.starts_with(p.as_str())
+            }
+            (Self::Prefix(a), Self::Prefix(b)) => {
+                a.starts_with(b.as_str()) || b.starts_with(a.as_str())
+            }
+        }
+    }
+}
+
+type Conn = (Stream, String);
+
+struct Route {
+    id: u64,
+    /// 为空时占用所有路径
+    paths: Vec<RoutePath>,
+    tx: mpsc::UnboundedSender<Conn>,
+}
+
+type Routes = Arc<Mutex<Vec<Route>>>;
+
+/// 共享监听，所有端点 drop 后停止监听
+struct Shared {
+    key: Option<String>,
+    socket: Socket,
+    routes: Routes,
+}
+
+impl Drop for Shared {
+    fn drop(&mut self) {
+        if let Some(key) = &self.key {
+            let mut registry = registry().lock().unwrap();
+            if registry.get(key).is_some_and(|s| s.strong_count() == 0) {
+                registry.remove(key);
+            }
+        }
+    }
+}
+
+fn registry() -> &'static Mutex<HashMap<String, Weak<Shared>>> {
+    static REGISTRY: OnceLock<Mutex<HashMap<String, Weak<Shared>>>> = OnceLock::new();
+    REGISTRY.get_or_init(Mutex::default)
+}
+
+/// 监听端点
+///
+/// 同一进程内监听相同 `host:port` 或 Unix socket 的端点共享同一监听，
+/// 连接按第一个请求的路径分发至对应端点，未匹配的请求返回 404；仅有一个端点时连接直接交由该端点处理
+pub(crate) struct Listener {
+    id: u64,
+    rx: mpsc::UnboundedReceiver<Conn>,
+    shared: Arc<Shared>,
+}
+
+impl Listener {
+    /// 设置 `unix` 时监听 Unix socket，否则监听 `host:port`，`paths` 为该端点占用的请求路径
+    ///
+    /// 地址已被其他端点监听时加入该监听，路径冲突时返回 `AddrInUse` 错误
+    pub(crate) async fn bind(
+        host: IpAddr,
+        port: u16,
+        unix: &Option<UnixSocket>,
+        paths: Vec<RoutePath>,
+    ) -> WalleResult<Self> {
+        static ROUTE_ID: AtomicU64 = AtomicU64::new(0);
+        let id = ROUTE_ID.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = mpsc::unbounded_channel();
+        let route = Route { id, paths, tx };
+        // 随机端口不共享
+        let key = match unix {
+            Some(unix) => Some(format!("unix:{}", unix.path)),
+            None if port != 0 => Some(SocketAddr::new(host, port).to_string()),
+            None => None,
+        };
+        let mut registry = registry().lock().unwrap();
+        if let Some(shared) = key.as_ref().and_then(|k| registry.get(k)?.upgrade()) {
+            // 释放 registry 锁，避免 shared 在此处被最后 drop 时死锁
+            drop(registry);
+            let mut routes = shared.routes.lock().unwrap();
+            if routes.iter().any(|r| conflicts(&r.paths, &route.paths)) {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("path already in use on {}", key.unwrap_or_default()),
+                )
+                .into());
+            }
+            routes.push(route);
+            drop(routes);
+            return Ok(Self { id, rx, shared });
+        }
+        let shared = Arc::new(Shared {
+            key: key.clone(),
+            socket: Socket::bind(host, port, unix)?,
+            routes: Routes::new(Mutex::new(vec![route])),
+        });
+        if let Some(key) = key {
+            registry.insert(key, Arc::downgrade(&shared));
+        }
+        Ok(Self { id, rx, shared })
+    }
+    /// 接受连接，返回连接与用于日志的对端地址
+    ///
+    /// 共享监听的所有端点同时接受连接并分发，该 future 可以安全的在 `select!` 中被取消
+    pub(crate) async fn accept(&mut self) -> Option<Conn> {
+        loop {
+            tokio::select! {
+                conn = self.rx.recv() => return conn,
+                accepted = self.shared.socket.accept() => match accepted {
+                    Ok((stream, peer)) => {
+                        tokio::spawn(dispatch(stream, peer, self.shared.routes.clone()));
+                    }
+                    Err(e) => warn!(target: super::OBC, "accept connection failed: {}", e),
+                },
+            }
+        }
+    }
+    /// 用于日志的监听地址
+    pub(crate) fn display(&self, scheme: &str) -> String {
+        match &self.shared.socket {
+            Socket::Tcp(l) => match l.local_addr() {
+                Ok(addr) => format!("{}://{}", scheme, addr),
+                Err(_) => format!("{}://<unknown>", scheme),
+            },
+            #[cfg(unix)]
+            Socket::Unix(_, path) => format!("unix:{}", path),
+        }
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        self.shared
+            .routes
+            .lock()
+            .unwrap()
+            .retain(|r| r.id != self.id);
+    }
+}
+
+fn conflicts(a: &[RoutePath], b: &[RoutePath]) -> bool {
+    a.is_empty() || b.is_empty() || a.iter().any(|a| b.iter().any(|b| a.conflicts(b)))
+}
+
+/// 请求头读取超时
+const DISPATCH_TIMEOUT: Duration = Duration::from_secs(10);
+/// 请求行最大长度
+const MAX_REQUEST_LINE: usize = 8192;
+
+async fn dispatch(mut stream: Stream, peer: String, routes: Routes) {
+    let single = {
+        let routes = routes.lock().unwrap();
+        (routes.len() == 1).then(|| routes[0].tx.clone())
+    };
+    if let Some(tx) = single {
+        tx.send((stream, peer)).ok();
+        return;
+    }
+    let mut head = vec![];
+    let path =
+        match tokio::time::timeout(DISPATCH_TIMEOUT, read_request_path(&mut stream, &mut head))
+            .await
+        {
+            Ok(Some(path)) => path,
+            _ => return,
+        };
+    let tx = routes
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|r| r.paths.is_empty() || r.paths.iter().any(|p| p.matches(&path)))
+        .map(|r| r.tx.clone());
+    match tx {
+        Some(tx) => {
+            tx.send((Stream::Rewind(head, Box::new(stream)), peer)).ok();
+        }
+        None => {
+            trace!(target: super::OBC, "no endpoint for path {} from {}", path, peer);
+            stream
+                .write_all(
+                    b"HTTP/1.1 404 Not Found\r\ncontent-length: 9\r\nconnection: close\r\n\r\nNot Found",
+                )
+                .await
+                .ok();
+            stream.shutdown().await.ok();
+        }
+    }
+}
+
+/// 读取请求行并返回不含 query 的请求路径，已读取的内容保存在 `head`
+async fn read_request_path(stream: &mut Stream, head: &mut Vec<u8>) -> Option<String> {
+    let mut buf = [0u8; 1024];
+    while !head.contains(&b'\n') {
+        if head.len() > MAX_REQUEST_LINE {
+            return None;
+        }
+        let n = stream.read(&mut buf).await.ok()?;
+        if n == 0 {
+            return None;
+        }
+        head.extend_from_slice(&buf[..n]);
+    }
+    let line = std::str::from_utf8(head.split(|b| *b == b'\n').next()?).ok()?;
+    let target = line.split_whitespace().nth(1)?;
+    Some(target.split('?').next().unwrap_or_default().to_owned())
+}
+
+#[cfg(unix)]
+fn bind_unix(config: &UnixSocket) -> io::Result<UnixListener> {
+    use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+
+    // 移除已无进程监听的残留 socket 文件，其他类型的文件保持原样
+    if let Ok(meta) = std::fs::symlink_metadata(&config.path) {
+        if meta.file_type().is_socket()
+            && std::os::unix::net::UnixStream::connect(&config.path).is_err()
+        {
+            std::fs::remove_file(&config.path)?;
+        }
+    }
+    let Some(mode) = config.mode else {
+        return UnixListener::bind(&config.path);
+    };
+    if std::fs::symlink_metadata(&config.path).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("{} already exists", config.path),
+        ));
+    }
+    // 在仅当前用户可访问的临时目录中创建 socket 并设置权限后再移动到目标路径，
+    // 避免 socket 以 umask 权限短暂暴露
+    static SEQ: AtomicU64 = AtomicU64::new(0);
+    let path = std::path::Path::new(&config.path);
+    let dir = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(std::path::Path::new("."))
+        .join(format!(
+            ".walle-{}-{}",
+            std::process::id(),
+            SEQ.fetch_add(1, Ordering::Relaxed)
+        ));
+    std::fs::DirBuilder::new().mode(0o700).create(&dir)?;
+    let tmp = dir.join("sock");
+    let bound = UnixListener::bind(&tmp).and_then(|listener| {
+        std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(mode))?;
+        std::fs::rename(&tmp, path)?;
+        Ok(listener)
+    });
+    if bound.is_err() {
+        std::fs::remove_file(&tmp).ok();
+    }
+    std::fs::remove_dir(&dir).ok();
+    bound
+}
+
+#[cfg(feature = "http")]
+pub(crate) use http_client::HyperClient;
+
+#[cfg(feature = "http")]
+mod http_client {
+    use hyper::body::Incoming;
+    use hyper::http::request::Builder;
+    use hyper::{Request, Response};
+    use hyper_util::client::legacy::{connect::HttpConnector, Client};
+    use hyper_util::rt::{TokioExecutor, TokioIo};
+
+    type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+    /// HTTP 客户端，`url` 为 `unix:` 地址时通过 Unix socket 发送请求
+    #[derive(Debug, Clone)]
+    pub(crate) struct HyperClient(Client<HttpConnector, String>);
+
+    impl HyperClient {
+        pub(crate) fn new() -> Self {
+            Self(Client::builder(TokioExecutor::new()).build_http())
+        }
+        pub(crate) async fn request(
+            &self,
+            url: &str,
+            req: Builder,
+            body: String,
+        ) -> Result<Response<Incoming>, BoxError> {
+            match super::parse_unix(url) {
+                Some((socket, path)) => {
+                    let req: Request<String> = req
+                        .uri(path)
+                        .header(hyper::header::HOST, "localhost")
+                        .body(body)?;
+                    let stream = super::Stream::connect_unix(socket).await?;
+                    let (mut sender, conn) =
+                        hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+                    tokio::spawn(conn);
+                    Ok(sender.send_request(req).await?)
+                }
+                None => Ok(self.0.request(req.uri(url).body(body)?).await?),
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn bind_unix_mode_test() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join(format!("walle-bind-unix-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("walle.sock");
+    let config = UnixSocket {
+        path: path.to_str().unwrap().to_owned(),
+        mode: Some(0o600),
+    };
+    let listener = bind_unix(&config).unwrap();
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+    // 临时目录已移除，只留下 socket
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    assert!(UnixStream::connect(&path).await.is_ok());
+    drop(listener);
+
+    // 不覆盖已存在的普通文件
+    std::fs::remove_file(&path).unwrap();
+    std::fs::write(&path, b"keep").unwrap();
+    assert!(bind_unix(&config).is_err());
+    assert_eq!(std::fs::read(&path).unwrap(), b"keep");
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[cfg(all(
+    unix,
+    feature = "impl-obc",
+    feature = "app-obc",
+    feature = "websocket",
+    feature = "http"
+))]
+#[tokio::test]
+async fn unix_socket_test() {
+    use std::collections::HashMap;
+    use std::os::unix::fs::PermissionsExt;
+
+    use crate::action::Action;
+    use crate::config::{
+        AppConfig, Heartbeat, HttpClient, HttpServer, ImplConfig, WebSocketClient, WebSocketServer,
+    };
+    use crate::event::Event;
+    use crate::resp::Resp;
+    use crate::structs::Selft;
+    use crate::testing::{Loopback, MockActionHandler, MockEventHandler};
+    use crate::value_map;
+
+    assert_eq!(parse_unix("unix:/tmp/a.sock"), Some(("/tmp/a.sock", "/")));
+    assert_eq!(
+        parse_unix("unix:/tmp/a.sock:/onebot?access_token=t"),
+        Some(("/tmp/a.sock", "/onebot?access_token=t"))
+    );
+    assert_eq!(parse_unix("ws://127.0.0.1:8844"), None);
+
+    let dir = std::env::temp_dir().join(format!("walle-unix-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = |name: &str| dir.join(name).to_str().unwrap().to_owned();
+
+    // 残留的 socket 文件被移除，监听文件权限生效，drop 后移除 socket 文件
+    let stale = path("stale.sock");
+    drop(std::os::unix::net::UnixListener::bind(&stale).unwrap());
+    let unix = Some(UnixSocket {
+        path: stale.clone(),
+        mode: Some(0o600),
+    });
+    let listener = Listener::bind([127, 0, 0, 1].into(), 0, &unix, vec![])
+        .await
+        .unwrap();
+    assert!(Listener::bind([127, 0, 0, 1].into(), 0, &unix, vec![])
+        .await
+        .is_err());
+    let mode = std::fs::metadata(&stale).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+    drop(listener);
+    assert!(!std::path::Path::new(&stale).exists());
+
+    let selft = Selft {
+        platform: "test".to_owned(),
+        user_id: "bot".to_owned(),
+    };
+    let action = || Action {
+        action: "get_self_info".to_owned(),
+        selft: Some(selft.clone()),
+        params: value_map!(),
+    };
+    let impl_config = || ImplConfig {
+        websocket_rev: vec![],
+        heartbeat: Heartbeat {
+            enabled: false,
+            interval: 0,
+        },
+        ..Default::default()
+    };
+    let unix = |name: &str| {
+        Some(UnixSocket {
+            path: path(name),
+            mode: None,
+        })
+    };
+
+    let ah = MockActionHandler::new()
+        .with_bot(selft.clone())
+        .with_resp("get_self_info", value_map! { "user_id": "bot" });
+    let loopback = Loopback::start_with(
+        ah.clone(),
+        (),
+        ImplConfig {
+            websocket: vec![WebSocketServer {
+                path: Some("/onebot".to_owned()),
+                unix: unix("ws.sock"),
+                ..Default::default()
+            }],
+            ..impl_config()
+        },
+        MockEventHandler::new(),
+        (),
+        AppConfig {
+            websocket: vec![WebSocketClient {
+                url: format!("unix:{}:/onebot", path("ws.sock")),
+                reconnect_interval: 1,
+                ..Default::default()
+            }],
+            ..AppConfig::empty()
+        },
+    )
+    .await
+    .unwrap();
+    let resp: Resp = loopback
+        .app_ob
+        .handle_action::<Event, _, _>(action())
+        .await
+        .unwrap();
+    assert_eq!(resp.retcode, 0);
+    loopback.shutdown().await;
+
+    let loopback = Loopback::start_with(
+        ah.clone(),
+        (),
+        ImplConfig {
+            http: vec![HttpServer {
+                unix: unix("http.sock"),
+                ..Default::default()
+            }],
+            ..impl_config()
+        },
+        MockEventHandler::new(),
+        (),
+        AppConfig {
+            http: HashMap::from([(
+                "bot".to_owned(),
+                HttpClient {
+                    platform: Some("test".to_owned()),
+                    url: format!("unix:{}", path("http.sock")),
+                    ..Default::default()
+                },
+            )]),
+            ..AppConfig::empty()
+        },
+    )
+    .await
+    .unwrap();
+    let resp: Resp = loopback
+        .app_ob
+        .handle_action::<Event, _, _>(action())
+        .await
+        .unwrap();
+    assert_eq!(resp.retcode, 0);
+    loopback.shutdown().await;
+
+    assert_eq!(ah.called("get_self_info").len(), 2);
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[cfg(all(feature = "impl-obc", feature = "app-obc", feature = "websocket"))]
+#[tokio::test]
+async fn shared_listener_test() {
+    use crate::action::Action;
+    use crate::config::{AppConfig, Heartbeat, ImplConfig, WebSocketClient, WebSocketServer};
+    use crate::event::Event;
+    use crate::resp::Resp;
+    use crate::structs::Selft;
+    use crate::testing::{Loopback, MockActionHandler, MockEventHandler};
+    use crate::value_map;
+
+    let port = std::net::TcpListener::bind(("127.0.0.1", 0))
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let host: IpAddr = [127, 0, 0, 1].into();
+    let request = |req: &'static str| async move {
+        let mut stream = TcpStream::connect((host, port)).await.unwrap();
+        stream.write_all(req.as_bytes()).await.unwrap();
+        let mut resp = String::new();
+        stream.read_to_string(&mut resp).await.ok();
+        resp
+    };
+
+    let mut a = Listener::bind(host, port, &None, vec![RoutePath::Exact("/a".to_owned())])
+        .await
+        .unwrap();
+    let mut b = Listener::bind(host, port, &None, vec![RoutePath::Prefix("/b/".to_owned())])
+        .await
+        .unwrap();
+    assert!(
+        Listener::bind(host, port, &None, vec![RoutePath::Exact("/b/c".to_owned())])
+            .await
+            .is_err()
+    );
+
+    // 分发后的连接可以读取完整的请求
+    const REQ: &str = "GET /b/c?d=e HTTP/1.1\r\n\r\n";
+    tokio::spawn(request(REQ));
+    let (mut stream, _) = tokio::time::timeout(Duration::from_secs(1), b.accept())
+        .await
+        .unwrap()
+        .unwrap();
+    let mut buf = vec![0u8; REQ.len()];
+    stream.read_exact(&mut buf).await.unwrap();
+    assert_eq!(buf, REQ.as_bytes());
+    drop(stream);
+
+    tokio::select! {
+        _ = a.accept() => panic!("unexpected connection"),
+        resp = request("GET /c HTTP/1.1\r\n\r\n") => assert!(resp.starts_with("HTTP/1.1 404")),
+    }
+    drop((a, b));
+    drop(Listener::bind(host, port, &None, vec![]).await.unwrap());
+
+    // 两个 WebSocket 服务端共享端口，按路径与各自的 access_token 连接
+    let selft = Selft {
+        platform: "test".to_owned(),
+        user_id: "bot".to_owned(),
+    };
+    let ah = MockActionHandler::new()
+        .with_bot(selft.clone())
+        .with_resp("get_self_info", value_map! { "user_id": "bot" });
+    let server = |path: &str| WebSocketServer {
+        port,
+        path: Some(path.to_owned()),
+        access_token: Some(path.to_owned()),
+        ..Default::default()
+    };
+    let loopback = Loopback::start_with(
+        ah,
+        (),
+        ImplConfig {
+            websocket: vec![server("/a"), server("/b")],
+            websocket_rev: vec![],
+            heartbeat: Heartbeat {
+                enabled: false,
+                interval: 0,
+            },
+            ..Default::default()
+        },
+        MockEventHandler::new(),
+        (),
+        AppConfig {
+            websocket: vec![WebSocketClient {
+                url: format!("ws://127.0.0.1:{}/b", port),
+                access_token: Some("/b".to_owned()),
+                reconnect_interval: 1,
+                ..Default::default()
+            }],
+            ..AppConfig::empty()
+        },
+    )
+    .await
+    .unwrap();
+    let resp: Resp = loopback
+        .app_ob
+        .handle_action::<Event, _, _>(Action {
+            action: "get_self_info".to_owned(),
+            selft: Some(selft),
+            params: value_map!(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(resp.retcode, 0);
+    loopback.shutdown().await;
+}
+
+#[cfg(all(feature = "impl-obc", feature = "http"))]
+#[tokio::test]
+async fn shared_listener_keep_alive_test() {
+    use crate::action::Action;
+    use crate::config::{Heartbeat, HttpServer, ImplConfig};
+    use crate::event::Event;
+    use crate::obc::ImplOBC;
+    use crate::resp::Resp;
+    use crate::structs::Selft;
+    use crate::testing::MockActionHandler;
+    use crate::{value_map, OneBot};
+
+    let port = std::net::TcpListener::bind(("127.0.0.1", 0))
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let ah = MockActionHandler::new()
+        .with_bot(Selft {
+            platform: "test".to_owned(),
+            user_id: "bot".to_owned(),
+        })
+        .with_resp("get_self_info", value_map! { "user_id": "bot" });
+    let server = |path: &str| HttpServer {
+        port,
+        path: Some(path.to_owned()),
+        ..Default::default()
+    };
+    let ob = Arc::new(OneBot::new(
+        ah,
+        ImplOBC::<Event>::new("test".to_owned()),
+        crate::structs::Version {
+            implt: "test".to_owned(),
+            version: crate::VERSION.to_owned(),
+            onebot_version: 12.to_string(),
+        },
+    ));
+    ob.start::<Event, Action, Resp>(
+        (),
+        ImplConfig {
+            http: vec![server("/a"), server("/b")],
+            websocket_rev: vec![],
+            heartbeat: Heartbeat {
+                enabled: false,
+                interval: 0,
+            },
+            ..Default::default()
+        },
+        true,
+    )
+    .await
+    .unwrap();
+
+    // 同一连接上发送不同路径的两个请求，第一个响应后连接关闭，第二个请求不会被错误分发
+    let body = r#"{"action":"get_self_info","params":{}}"#;
+    let request = |path: &str| {
+        format!(
+            "POST {} HTTP/1.1\r\nhost: 127.0.0.1\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+            path,
+            body.len(),
+            body
+        )
+    };
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    stream
+        .write_all((request("/a") + &request("/b")).as_bytes())
+        .await
+        .unwrap();
+    let mut resp = String::new();
+    tokio::time::timeout(Duration::from_secs(3), stream.read_to_string(&mut resp))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(resp.matches("HTTP/1.1 ").count(), 1, "{}", resp);
+    assert!(resp.starts_with("HTTP/1.1 200"), "{}", resp);
+    assert!(resp.contains("connection: close"), "{}", resp);
+
+    // 第二个路径需要新的连接
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    stream.write_all(request("/b").as_bytes()).await.unwrap();
+    let mut resp = String::new();
+    tokio::time::timeout(Duration::from_secs(3), stream.read_to_string(&mut resp))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(resp.starts_with("HTTP/1.1 200"), "{}", resp);
+    ob.shutdown::<Event, Action, Resp>(true).await.unwrap();
+}
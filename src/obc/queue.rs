@@ -0,0 +1,185 @@
+//! 带溢出策略的有界队列
+//!
+//! 用于 OBC 内部每个连接的 action / resp 队列，溢出策略见 [`OverflowPolicy`]
+
+use std::collections::VecDeque;
+use std::pin::pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::Notify;
+use tracing::warn;
+
+use crate::config::{OverflowPolicy, QueueConfig};
+use crate::{WalleError, WalleResult};
+
+struct Shared<T> {
+    queue: Mutex<VecDeque<T>>,
+    capacity: usize,
+    overflow: OverflowPolicy,
+    /// 队列中有新元素或队列关闭
+    recv_notify: Notify,
+    /// 队列中有空位或队列关闭
+    send_notify: Notify,
+    senders: AtomicUsize,
+    closed: AtomicBool,
+}
+
+impl<T> Shared<T> {
+    fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        self.recv_notify.notify_one();
+        self.send_notify.notify_waiters();
+    }
+}
+
+/// 创建一个有界队列，容量至少为 1
+pub(crate) fn channel<T>(config: &QueueConfig) -> (QueueSender<T>, QueueReceiver<T>) {
+    let shared = Arc::new(Shared {
+        queue: Mutex::new(VecDeque::new()),
+        capacity: config.capacity.max(1),
+        overflow: config.overflow,
+        recv_notify: Notify::new(),
+        send_notify: Notify::new(),
+        senders: AtomicUsize::new(1),
+        closed: AtomicBool::new(false),
+    });
+    (QueueSender(shared.clone()), QueueReceiver(shared))
+}
+
+pub(crate) struct QueueSender<T>(Arc<Shared<T>>);
+
+impl<T> QueueSender<T> {
+    /// 向队列发送一个元素，队列满时按照溢出策略处理
+    ///
+    /// - `DropOldest`: 丢弃队列中最旧的元素
+    /// - `Block`: 等待队列出现空位
+    /// - `Disconnect`: 关闭队列并返回错误
+    pub(crate) async fn send(&self, item: T) -> WalleResult<()> {
+        let mut item = Some(item);
+        loop {
+            let mut notified = pin!(self.0.send_notify.notified());
+            notified.as_mut().enable();
+            {
+                if self.0.closed.load(Ordering::Acquire) {
+                    return Err(WalleError::QueueClosed);
+                }
+                let mut queue = self.0.queue.lock().unwrap();
+                if queue.len() < self.0.capacity {
+                    queue.push_back(item.take().unwrap());
+                    drop(queue);
+                    self.0.recv_notify.notify_one();
+                    return Ok(());
+                }
+                match self.0.overflow {
+                    OverflowPolicy::DropOldest => {
+                        queue.pop_front();
+                        queue.push_back(item.take().unwrap());
+                        drop(queue);
+                        warn!(target: super::OBC, "queue is full, drop the oldest item");
+                        self.0.recv_notify.notify_one();
+                        return Ok(());
+                    }
+                    OverflowPolicy::Disconnect => {
+                        drop(queue);
+                        warn!(target: super::OBC, "queue is full, disconnect");
+                        self.0.close();
+                        return Err(WalleError::QueueFull);
+                    }
+                    OverflowPolicy::Block => {}
+                }
+            }
+            notified.await;
+        }
+    }
+    #[cfg(feature = "app-obc")]
+    pub(crate) fn same_channel(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<T> Clone for QueueSender<T> {
+    fn clone(&self) -> Self {
+        self.0.senders.fetch_add(1, Ordering::AcqRel);
+        Self(self.0.clone())
+    }
+}
+
+impl<T> Drop for QueueSender<T> {
+    fn drop(&mut self) {
+        if self.0.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.0.recv_notify.notify_one();
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for QueueSender<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("QueueSender")
+            .field("capacity", &self.0.capacity)
+            .field("overflow", &self.0.overflow)
+            .finish()
+    }
+}
+
+pub(crate) struct QueueReceiver<T>(Arc<Shared<T>>);
+
+impl<T> QueueReceiver<T> {
+    /// 接收一个元素，当队列被关闭或所有 sender 均被 drop 且队列为空时返回 None
+    ///
+    /// 该 future 可以安全的在 `select!` 中被取消
+    pub(crate) async fn recv(&mut self) -> Option<T> {
+        loop {
+            let mut notified = pin!(self.0.recv_notify.notified());
+            notified.as_mut().enable();
+            if self.0.closed.load(Ordering::Acquire) {
+                return None;
+            }
+            let item = self.0.queue.lock().unwrap().pop_front();
+            if let Some(item) = item {
+                self.0.send_notify.notify_one();
+                return Some(item);
+            }
+            if self.0.senders.load(Ordering::Acquire) == 0 {
+                return None;
+            }
+            notified.await;
+        }
+    }
+}
+
+impl<T> Drop for QueueReceiver<T> {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+#[tokio::test]
+async fn queue_test() {
+    let config = |capacity, overflow| QueueConfig { capacity, overflow };
+
+    let (tx, mut rx) = channel(&config(2, OverflowPolicy::DropOldest));
+    for i in 0..3 {
+        tx.send(i).await.unwrap();
+    }
+    assert_eq!(rx.recv().await, Some(1));
+    assert_eq!(rx.recv().await, Some(2));
+    drop(tx);
+    assert_eq!(rx.recv().await, None);
+
+    let (tx, mut rx) = channel(&config(1, OverflowPolicy::Disconnect));
+    tx.send(0).await.unwrap();
+    assert!(matches!(tx.send(1).await, Err(WalleError::QueueFull)));
+    assert_eq!(rx.recv().await, None);
+    assert!(matches!(tx.send(2).await, Err(WalleError::QueueClosed)));
+
+    let (tx, mut rx) = channel(&config(1, OverflowPolicy::Block));
+    tx.send(0).await.unwrap();
+    let tx2 = tx.clone();
+    let blocked = tokio::spawn(async move { tx2.send(1).await });
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    assert!(!blocked.is_finished());
+    assert_eq!(rx.recv().await, Some(0));
+    blocked.await.unwrap().unwrap();
+    assert_eq!(rx.recv().await, Some(1));
+}
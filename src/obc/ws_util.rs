@@ -1,6 +1,5 @@
 use super::OBC;
 use colored::*;
-use tokio::net::TcpStream;
 use tokio_tungstenite::tungstenite::handshake::client::{generate_key, Request};
 use tokio_tungstenite::tungstenite::http::{
     request::Builder as HttpReqBuilder, response::Builder as HttpRespBuilder, Response as HttpResp,
@@ -9,16 +8,70 @@ use tokio_tungstenite::tungstenite::http::{
 use tokio_tungstenite::{accept_hdr_async, client_async, WebSocketStream};
 use tracing::{info, warn};
 
-use crate::config::WebSocketClient;
+use super::net::{parse_unix, Stream};
+use crate::config::{KeepAlive, WebSocketClient};
+use tokio::net::TcpStream;
+use tokio::time::{Duration, Instant};
+
+/// WebSocket ping/pong 保活计时器
+pub(crate) struct KeepAliveTimer {
+    config: Option<KeepAlive>,
+    next_ping: Instant,
+    ping_sent: Option<Instant>,
+}
+
+pub(crate) enum KeepAliveTick {
+    /// 需要发送 ping
+    Ping,
+    /// 等待 pong 超时，需要关闭连接
+    Timeout,
+}
+
+impl KeepAliveTimer {
+    pub(crate) fn new(config: Option<KeepAlive>) -> Self {
+        let next_ping = Instant::now()
+            + Duration::from_secs(config.as_ref().map(|c| c.interval).unwrap_or_default() as u64);
+        Self {
+            config,
+            next_ping,
+            ping_sent: None,
+        }
+    }
+    /// 等待下一次 ping 或超时，未设置保活时永远不会返回
+    ///
+    /// 该 future 可以安全的在 `select!` 中被取消
+    pub(crate) async fn tick(&mut self) -> KeepAliveTick {
+        let Some(config) = &self.config else {
+            return std::future::pending().await;
+        };
+        match self.ping_sent {
+            Some(sent) => {
+                tokio::time::sleep_until(sent + Duration::from_secs(config.timeout as u64)).await;
+                KeepAliveTick::Timeout
+            }
+            None => {
+                tokio::time::sleep_until(self.next_ping).await;
+                let now = Instant::now();
+                self.ping_sent = Some(now);
+                self.next_ping = now + Duration::from_secs(config.interval as u64);
+                KeepAliveTick::Ping
+            }
+        }
+    }
+    /// 收到 pong
+    pub(crate) fn pong(&mut self) {
+        self.ping_sent = None;
+    }
+}
 
 pub(crate) async fn try_connect(
     config: &WebSocketClient,
     req: HttpReqBuilder,
-) -> Option<WebSocketStream<TcpStream>> {
+) -> Option<WebSocketStream<Stream>> {
     fn err<E: std::fmt::Display>(
         config: &WebSocketClient,
         e: E,
-    ) -> Option<WebSocketStream<TcpStream>> {
+    ) -> Option<WebSocketStream<Stream>> {
         warn!(target: OBC, "connect to {} failed: {}", config.url, e);
         info!(
             target: OBC,
@@ -26,18 +79,34 @@ pub(crate) async fn try_connect(
         );
         None
     }
-    let uri: Uri = config.url.parse().unwrap();
-    let addr = format!("{}:{}", uri.host().unwrap(), uri.port().unwrap());
-    let authority = match uri.authority() {
-        Some(authority) => authority.as_str(),
-        None => return err(config, "authority is empty"),
+    let (stream, uri, host) = match parse_unix(&config.url) {
+        Some((socket, path)) => {
+            let uri: Uri = match format!("ws://localhost{}", path).parse() {
+                Ok(uri) => uri,
+                Err(e) => return err(config, e),
+            };
+            (
+                Stream::connect_unix(socket).await,
+                uri,
+                "localhost".to_owned(),
+            )
+        }
+        None => {
+            let uri: Uri = config.url.parse().unwrap();
+            let addr = format!("{}:{}", uri.host().unwrap(), uri.port().unwrap());
+            let authority = match uri.authority() {
+                Some(authority) => authority.as_str(),
+                None => return err(config, "authority is empty"),
+            };
+            let host = authority
+                .find('@')
+                .map(|idx| authority.split_at(idx + 1).1)
+                .unwrap_or_else(|| authority)
+                .to_owned();
+            (TcpStream::connect(&addr).await.map(Stream::Tcp), uri, host)
+        }
     };
-    let host = authority
-        .find('@')
-        .map(|idx| authority.split_at(idx + 1).1)
-        .unwrap_or_else(|| authority);
-
-    let stream = match TcpStream::connect(&addr).await {
+    let stream = match stream {
         Ok(stream) => stream,
         Err(e) => return err(config, e),
     };
@@ -67,18 +136,13 @@ pub(crate) async fn try_connect(
 pub(crate) async fn upgrade_websocket(
     access_token: &Option<String>,
     path: &Option<String>,
-    stream: TcpStream,
-) -> Option<(WebSocketStream<TcpStream>, String)> {
-    let addr = match stream.peer_addr() {
-        Ok(addr) => addr,
-        Err(e) => {
-            warn!(target: OBC, "Upgrade websocket failed: {}", e);
-            return None;
-        }
-    };
+    stream: Stream,
+    addr: &str,
+) -> Option<(WebSocketStream<Stream>, String)> {
     let mut implt = String::default();
     let ref_implt = &mut implt;
 
+    #[allow(clippy::result_large_err)]
     let callback =
         |req: &Request, resp: HttpResp<()>| -> Result<HttpResp<()>, HttpResp<Option<String>>> {
             use crate::obc::check_query;
@@ -129,7 +193,7 @@ pub(crate) async fn upgrade_websocket(
             info!(
                 target: OBC,
                 "Websocket connectted with {}",
-                addr.to_string().blue()
+                addr.blue()
             );
             Ok(resp)
         };
@@ -145,3 +209,21 @@ pub(crate) async fn upgrade_websocket(
         }
     }
 }
+
+#[tokio::test]
+async fn keepalive_test() {
+    let mut timer = KeepAliveTimer::new(Some(KeepAlive {
+        interval: 0,
+        timeout: 0,
+    }));
+    assert!(matches!(timer.tick().await, KeepAliveTick::Ping));
+    timer.pong();
+    assert!(matches!(timer.tick().await, KeepAliveTick::Ping));
+    assert!(matches!(timer.tick().await, KeepAliveTick::Timeout));
+    let mut timer = KeepAliveTimer::new(None);
+    assert!(
+        tokio::time::timeout(Duration::from_millis(10), timer.tick())
+            .await
+            .is_err()
+    );
+}
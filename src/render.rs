@@ -0,0 +1,428 @@
+//! 消息渲染
+//!
+//! 将 `Segments` 渲染为纯文本、Markdown 或 HTML，用于与其他聊天系统桥接，
+//! 同时提供从有限的 Markdown 子集解析回 `Segments` 的 [`parse_markdown`]。
+//!
+//! ```rust
+//! use walle_core::prelude::*;
+//! use walle_core::render::{PlainText, Renderer};
+//! use walle_core::segment::Mention;
+//!
+//! let message: Segments = vec![
+//!     Mention { user_id: "10001".to_string() }.into(),
+//!     " hello".into(),
+//! ];
+//! let renderer = PlainText::new().with_resolver(|user_id: &str| {
+//!     (user_id == "10001").then(|| "Alice".to_string())
+//! });
+//! assert_eq!(renderer.render(&message), "@Alice hello");
+//! ```
+
+use std::sync::Arc;
+
+use crate::segment::{Image, Mention, MentionAll, MsgSegment, Segments};
+use crate::util::{Value, ValueMapExt};
+
+/// 根据 `user_id` 查询展示名称，返回 None 时使用 `user_id`
+pub type MentionResolver = Arc<dyn Fn(&str) -> Option<String> + Send + Sync>;
+
+/// 消息渲染器
+pub trait Renderer {
+    /// 渲染单个消息段
+    fn render_segment(&self, segment: &MsgSegment) -> String;
+    /// 渲染消息
+    fn render(&self, segments: &Segments) -> String {
+        segments
+            .iter()
+            .map(|seg| self.render_segment(seg))
+            .collect()
+    }
+}
+
+fn get_str(segment: &MsgSegment, key: &str) -> String {
+    match segment.data.get(key) {
+        Some(Value::Str(s)) => s.clone(),
+        Some(Value::Int(i)) => i.to_string(),
+        Some(Value::F64(f)) => f.to_string(),
+        _ => String::default(),
+    }
+}
+
+/// 媒体消息段的链接，优先使用扩展字段 `url`，否则使用 `file_id`
+///
+/// 仅允许 `http`、`https` 与相对链接，其他协议（如 `javascript:`、`data:`）返回 None
+fn media_src(segment: &MsgSegment) -> Option<String> {
+    let src = segment
+        .data
+        .get_downcast::<String>("url")
+        .unwrap_or_else(|_| get_str(segment, "file_id"));
+    safe_url(&src).then_some(src)
+}
+
+fn safe_url(url: &str) -> bool {
+    match url.find([':', '/', '?', '#']) {
+        Some(i) if url[i..].starts_with(':') => {
+            let scheme = &url[..i];
+            scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https")
+        }
+        _ => !url.is_empty(),
+    }
+}
+
+/// 非文本消息段的占位文本
+fn placeholder(ty: &str) -> String {
+    match ty {
+        "mention_all" => "@全体成员".to_owned(),
+        "image" => "[图片]".to_owned(),
+        "voice" => "[语音]".to_owned(),
+        "audio" => "[音频]".to_owned(),
+        "video" => "[视频]".to_owned(),
+        "file" => "[文件]".to_owned(),
+        "location" => "[位置]".to_owned(),
+        "reply" => "[回复]".to_owned(),
+        ty => format!("[{}]", ty),
+    }
+}
+
+macro_rules! renderer {
+    ($(#[$meta: meta])* $name: ident) => {
+        $(#[$meta])*
+        #[derive(Clone, Default)]
+        pub struct $name {
+            resolver: Option<MentionResolver>,
+        }
+
+        impl $name {
+            pub fn new() -> Self {
+                Self::default()
+            }
+            /// 设置 `mention` 消息段的名称查询
+            pub fn with_resolver<F>(mut self, resolver: F) -> Self
+            where
+                F: Fn(&str) -> Option<String> + Send + Sync + 'static,
+            {
+                self.resolver = Some(Arc::new(resolver));
+                self
+            }
+            fn mention_name(&self, user_id: &str) -> String {
+                self.resolver
+                    .as_ref()
+                    .and_then(|resolver| resolver(user_id))
+                    .unwrap_or_else(|| user_id.to_owned())
+            }
+        }
+    };
+}
+
+renderer!(
+    /// 纯文本渲染，非文本消息段渲染为 `[图片]` 等占位文本
+    PlainText
+);
+renderer!(
+    /// Markdown 渲染，文本中的 Markdown 符号将被转义
+    Markdown
+);
+renderer!(
+    /// HTML 渲染，文本将被转义，换行渲染为 `<br>`
+    Html
+);
+
+impl Renderer for PlainText {
+    fn render_segment(&self, segment: &MsgSegment) -> String {
+        match segment.ty.as_str() {
+            "text" => get_str(segment, "text"),
+            "mention" => format!("@{}", self.mention_name(&get_str(segment, "user_id"))),
+            "location" => format!("[位置]{}", get_str(segment, "title")),
+            ty => placeholder(ty),
+        }
+    }
+}
+
+fn escape_markdown(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(
+            c,
+            '\\' | '`' | '*' | '_' | '~' | '[' | ']' | '!' | '<' | '>'
+        ) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// 对链接目标中会破坏 Markdown 链接语法的字符进行百分号编码
+fn escape_destination(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c.is_whitespace() || c.is_control() || matches!(c, '(' | ')' | '<' | '>' | '\\') {
+            let mut buf = [0; 4];
+            for b in c.encode_utf8(&mut buf).bytes() {
+                out.push_str(&format!("%{:02X}", b));
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+impl Renderer for Markdown {
+    fn render_segment(&self, segment: &MsgSegment) -> String {
+        match (segment.ty.as_str(), media_src(segment)) {
+            ("text", _) => escape_markdown(&get_str(segment, "text")),
+            ("mention", _) => escape_markdown(&format!(
+                "@{}",
+                self.mention_name(&get_str(segment, "user_id"))
+            )),
+            ("image", Some(src)) => format!("![图片]({})", escape_destination(&src)),
+            (ty @ ("voice" | "audio" | "video" | "file"), Some(src)) => {
+                let text = placeholder(ty);
+                let text = &text[1..text.len() - 1];
+                format!("[{}]({})", text, escape_destination(&src))
+            }
+            ("location", _) => format!(
+                "[位置 {}](geo:{},{})",
+                escape_markdown(&get_str(segment, "title")),
+                escape_destination(&get_str(segment, "latitude")),
+                escape_destination(&get_str(segment, "longitude"))
+            ),
+            (ty, _) => escape_markdown(&placeholder(ty)),
+        }
+    }
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl Renderer for Html {
+    fn render_segment(&self, segment: &MsgSegment) -> String {
+        let attr = |key: &str| escape_html(&get_str(segment, key));
+        let src = media_src(segment).map(|src| escape_html(&src));
+        match (segment.ty.as_str(), src) {
+            ("text", _) => escape_html(&get_str(segment, "text")).replace('\n', "<br>"),
+            ("mention", _) => format!(
+                "<span class=\"mention\" data-user-id=\"{}\">@{}</span>",
+                attr("user_id"),
+                escape_html(&self.mention_name(&get_str(segment, "user_id")))
+            ),
+            ("mention_all", _) => "<span class=\"mention-all\">@全体成员</span>".to_owned(),
+            ("image", Some(src)) => format!("<img src=\"{}\" alt=\"[图片]\">", src),
+            ("voice" | "audio", Some(src)) => format!("<audio controls src=\"{}\"></audio>", src),
+            ("video", Some(src)) => format!("<video controls src=\"{}\"></video>", src),
+            ("file", Some(src)) => format!("<a href=\"{}\">[文件]</a>", src),
+            ("location", _) => format!(
+                "<a href=\"geo:{},{}\">[位置]{}</a>",
+                attr("latitude"),
+                attr("longitude"),
+                attr("title")
+            ),
+            ("reply", _) => format!(
+                "<span class=\"reply\" data-message-id=\"{}\">[回复]</span>",
+                attr("message_id")
+            ),
+            (ty, _) => format!(
+                "<span class=\"{}\">{}</span>",
+                escape_html(ty),
+                escape_html(&placeholder(ty))
+            ),
+        }
+    }
+}
+
+/// 从 Markdown 子集解析消息
+///
+/// 支持的语法：
+///
+/// - `\` 转义
+/// - 图片 `![alt](src)`，解析为 `file_id` 为 `src` 的 `image` 消息段
+/// - 链接 `[text](url)`，解析为文本 `text (url)`
+/// - 行内代码 `` `code` ``，内容不做解析
+/// - 提及 `<@user_id>`，`<@all>` 解析为 `mention_all`
+/// - 强调符号 `*`、`**`、`__`、`~~` 将被移除
+pub fn parse_markdown(s: &str) -> Segments {
+    let mut segments = vec![];
+    let mut text = String::new();
+    let push = |segments: &mut Segments, text: &mut String, segment: Option<MsgSegment>| {
+        if !text.is_empty() {
+            segments.push(std::mem::take(text).as_str().into());
+        }
+        segments.extend(segment);
+    };
+    // `[text](url)` 形式，返回 text、url 与消耗的字节数
+    let link = |s: &str| -> Option<(String, String, usize)> {
+        let close = s.find("](")?;
+        let end = close + 2 + s[close + 2..].find(')')?;
+        Some((
+            s[1..close].to_owned(),
+            s[close + 2..end].to_owned(),
+            end + 1,
+        ))
+    };
+    let mut i = 0;
+    while i < s.len() {
+        let rest = &s[i..];
+        let c = rest.chars().next().unwrap_or_default();
+        if c == '\\' {
+            if let Some(next) = rest[1..].chars().next() {
+                text.push(next);
+                i += 1 + next.len_utf8();
+                continue;
+            }
+        } else if rest.starts_with("![") {
+            if let Some((_, src, len)) = link(&rest[1..]) {
+                let image = Image { file_id: src }.into();
+                push(&mut segments, &mut text, Some(image));
+                i += 1 + len;
+                continue;
+            }
+        } else if c == '[' {
+            if let Some((label, url, len)) = link(rest) {
+                let label = parse_markdown(&label)
+                    .iter()
+                    .map(|seg| get_str(seg, "text"))
+                    .collect::<String>();
+                if label == url {
+                    text.push_str(&url);
+                } else {
+                    text.push_str(&format!("{} ({})", label, url));
+                }
+                i += len;
+                continue;
+            }
+        } else if c == '`' {
+            if let Some(end) = rest[1..].find('`') {
+                text.push_str(&rest[1..end + 1]);
+                i += end + 2;
+                continue;
+            }
+        } else if rest.starts_with("<@") {
+            if let Some(end) = rest.find('>') {
+                let segment = match &rest[2..end] {
+                    "all" => MentionAll {}.into(),
+                    user_id => Mention {
+                        user_id: user_id.to_owned(),
+                    }
+                    .into(),
+                };
+                push(&mut segments, &mut text, Some(segment));
+                i += end + 1;
+                continue;
+            }
+        } else if rest.starts_with("**") || rest.starts_with("__") || rest.starts_with("~~") {
+            i += 2;
+            continue;
+        } else if c == '*' {
+            i += 1;
+            continue;
+        }
+        text.push(c);
+        i += c.len_utf8();
+    }
+    push(&mut segments, &mut text, None);
+    segments
+}
+
+#[test]
+fn render_test() {
+    use crate::segment::{Location, Reply};
+
+    let message: Segments = vec![
+        Reply {
+            message_id: "1".to_owned(),
+            user_id: None,
+        }
+        .into(),
+        Mention {
+            user_id: "10001".to_owned(),
+        }
+        .into(),
+        " a*b <c>\n".into(),
+        Image {
+            file_id: "f".to_owned(),
+        }
+        .into(),
+        MentionAll {}.into(),
+        Location {
+            latitude: 1.5,
+            longitude: 2.0,
+            title: "t".to_owned(),
+            content: "".to_owned(),
+        }
+        .into(),
+    ];
+    let resolver = |user_id: &str| (user_id == "10001").then(|| "Alice".to_owned());
+
+    assert_eq!(
+        PlainText::new().render(&message),
+        "[回复]@10001 a*b <c>\n[图片]@全体成员[位置]t"
+    );
+    assert_eq!(
+        Markdown::new().with_resolver(resolver).render(&message),
+        "\\[回复\\]@Alice a\\*b \\<c\\>\n![图片](f)@全体成员[位置 t](geo:1.5,2)"
+    );
+    assert_eq!(
+        Html::new().with_resolver(resolver).render(&message),
+        "<span class=\"reply\" data-message-id=\"1\">[回复]</span>\
+        <span class=\"mention\" data-user-id=\"10001\">@Alice</span> a*b &lt;c&gt;<br>\
+        <img src=\"f\" alt=\"[图片]\"><span class=\"mention-all\">@全体成员</span>\
+        <a href=\"geo:1.5,2\">[位置]t</a>"
+    );
+
+    let parsed =
+        parse_markdown("hi <@10001> **bold** `a*b` \\*x\\* [doc](http://a) ![img](f)<@all>");
+    assert_eq!(
+        parsed,
+        vec![
+            "hi ".into(),
+            Mention {
+                user_id: "10001".to_owned()
+            }
+            .into(),
+            " bold a*b *x* doc (http://a) ".into(),
+            Image {
+                file_id: "f".to_owned()
+            }
+            .into(),
+            MentionAll {}.into(),
+        ]
+    );
+    let text: Segments = vec!["a_b *c* [d] \\ `e` <f> !g".into()];
+    assert_eq!(parse_markdown(&Markdown::new().render(&text)), text);
+    // 不安全的链接被丢弃，链接目标不能破坏 Markdown 语法
+    let media = |file_id: &str| -> Segments {
+        vec![Image {
+            file_id: file_id.to_owned(),
+        }
+        .into()]
+    };
+    for src in [
+        "javascript:alert(1)",
+        "JavaScript:x",
+        " data:text/html,x",
+        "\x01javascript:x",
+    ] {
+        assert_eq!(
+            Html::new().render(&media(src)),
+            "<span class=\"image\">[图片]</span>"
+        );
+        assert_eq!(Markdown::new().render(&media(src)), "\\[图片\\]");
+    }
+    assert_eq!(
+        Html::new().render(&media("https://a/b?c=\"d\"")),
+        "<img src=\"https://a/b?c=&quot;d&quot;\" alt=\"[图片]\">"
+    );
+    assert_eq!(
+        Markdown::new().render(&media("http://a/b c)![x](y")),
+        "![图片](http://a/b%20c%29![x]%28y)"
+    );
+    assert_eq!(
+        parse_markdown(&Markdown::new().render(&media("/a) b"))),
+        media("/a%29%20b")
+    );
+}
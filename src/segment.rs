@@ -6,6 +6,9 @@ use crate::{
     value, value_map,
 };
 
+pub(crate) mod cq;
+pub use cq::{from_cq, to_cq};
+
 pub type Segments = Vec<MsgSegment>;
 
 /// 标准 MsgSegment 模型
@@ -0,0 +1,257 @@
+//! CQ 码解析与序列化
+//!
+//! CQ 码中的参数值均为字符串，`&`、`[`、`]` 与参数中的 `,` 需要转义
+
+use super::{MsgSegment, Segments};
+use crate::util::{Value, ValueMap};
+use crate::value_map;
+
+fn string_of(value: Option<Value>) -> String {
+    match value {
+        Some(Value::Str(s)) => s,
+        Some(Value::Int(i)) => i.to_string(),
+        Some(Value::F64(f)) => f.to_string(),
+        Some(Value::Bool(b)) => b.to_string(),
+        _ => String::default(),
+    }
+}
+
+fn f64_of(value: Option<Value>) -> f64 {
+    match value {
+        Some(Value::F64(f)) => f,
+        Some(Value::Int(i)) => i as f64,
+        Some(Value::Str(s)) => s.parse().unwrap_or_default(),
+        _ => 0.0,
+    }
+}
+
+fn escape(s: &str, param: bool) -> String {
+    let s = s
+        .replace('&', "&amp;")
+        .replace('[', "&#91;")
+        .replace(']', "&#93;");
+    if param {
+        s.replace(',', "&#44;")
+    } else {
+        s
+    }
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("&#44;", ",")
+        .replace("&#91;", "[")
+        .replace("&#93;", "]")
+        .replace("&amp;", "&")
+}
+
+/// 解析 CQ 码字符串为 CQ 消息段，不做类型转换
+pub(crate) fn parse(s: &str) -> Segments {
+    let mut segments = vec![];
+    let push_text = |segments: &mut Segments, text: &str| {
+        if !text.is_empty() {
+            segments.push(MsgSegment {
+                ty: "text".to_owned(),
+                data: value_map! { "text": unescape(text) },
+            });
+        }
+    };
+    let mut rest = s;
+    while let Some(start) = rest.find("[CQ:") {
+        let Some(len) = rest[start..].find(']') else {
+            break;
+        };
+        push_text(&mut segments, &rest[..start]);
+        let mut parts = rest[start + 4..start + len].split(',');
+        let ty = parts.next().unwrap_or_default().to_owned();
+        let data: ValueMap = parts
+            .filter_map(|kv| kv.split_once('='))
+            .map(|(k, v)| (k.to_owned(), Value::Str(unescape(v))))
+            .collect();
+        segments.push(MsgSegment { ty, data });
+        rest = &rest[start + len + 1..];
+    }
+    push_text(&mut segments, rest);
+    segments
+}
+
+/// 将 CQ 消息段序列化为 CQ 码字符串，参数按名称排序
+pub(crate) fn serialize(segments: &[MsgSegment]) -> String {
+    segments
+        .iter()
+        .map(|seg| {
+            if seg.ty == "text" {
+                return escape(&string_of(seg.data.get("text").cloned()), false);
+            }
+            let mut params = seg
+                .data
+                .iter()
+                .map(|(k, v)| format!(",{}={}", k, escape(&string_of(Some(v.clone())), true)))
+                .collect::<Vec<_>>();
+            params.sort();
+            format!("[CQ:{}{}]", seg.ty, params.concat())
+        })
+        .collect()
+}
+
+/// 将 CQ 消息段转换为 OneBot 12 消息段
+///
+/// 无对应标准类型的消息段，设置 `platform` 时以 `{platform}.{type}` 作为扩展类型，否则保持原类型
+pub(crate) fn from_cq_segment(segment: MsgSegment, platform: Option<&str>) -> MsgSegment {
+    let MsgSegment { ty, mut data } = segment;
+    let (ty, data) = match ty.as_str() {
+        "text" => (ty, data),
+        "at" => match string_of(data.remove("qq")).as_str() {
+            "all" => ("mention_all".to_owned(), data),
+            user_id => {
+                data.insert("user_id".to_owned(), user_id.into());
+                ("mention".to_owned(), data)
+            }
+        },
+        "image" | "video" | "record" => {
+            let value = string_of(data.remove("file"));
+            data.insert("file_id".to_owned(), value.into());
+            let ty = if ty == "record" {
+                "voice".to_owned()
+            } else {
+                ty
+            };
+            (ty, data)
+        }
+        "reply" => {
+            let value = string_of(data.remove("id"));
+            data.insert("message_id".to_owned(), value.into());
+            (ty, data)
+        }
+        "location" => {
+            let mut location = value_map! {
+                "latitude": f64_of(data.remove("lat")),
+                "longitude": f64_of(data.remove("lon")),
+                "title": string_of(data.remove("title")),
+                "content": string_of(data.remove("content"))
+            };
+            location.extend(data);
+            (ty, location)
+        }
+        _ => match platform {
+            Some(platform) => (format!("{}.{}", platform, ty), data),
+            None => (ty, data),
+        },
+    };
+    MsgSegment { ty, data }
+}
+
+/// 将 OneBot 12 消息段转换为 CQ 消息段
+///
+/// 带有平台前缀的扩展消息段类型将去除前缀
+pub(crate) fn to_cq_segment(segment: MsgSegment) -> MsgSegment {
+    let MsgSegment { ty, mut data } = segment;
+    let (ty, data) = match ty.as_str() {
+        "mention" => {
+            let value = string_of(data.remove("user_id"));
+            data.insert("qq".to_owned(), value.into());
+            ("at".to_owned(), data)
+        }
+        "mention_all" => ("at".to_owned(), value_map! { "qq": "all" }),
+        "image" | "video" | "voice" | "audio" => {
+            let value = string_of(data.remove("file_id"));
+            data.insert("file".to_owned(), value.into());
+            let ty = match ty.as_str() {
+                "voice" | "audio" => "record".to_owned(),
+                _ => ty,
+            };
+            (ty, data)
+        }
+        "reply" => {
+            data.remove("user_id");
+            let value = string_of(data.remove("message_id"));
+            data.insert("id".to_owned(), value.into());
+            (ty, data)
+        }
+        "location" => {
+            let mut location = value_map! {
+                "lat": string_of(data.remove("latitude")),
+                "lon": string_of(data.remove("longitude"))
+            };
+            location.extend(data);
+            (ty, location)
+        }
+        _ => match ty.split_once('.') {
+            Some((_, ty)) => (ty.to_owned(), data),
+            None => (ty, data),
+        },
+    };
+    MsgSegment { ty, data }
+}
+
+/// 解析 CQ 码字符串为 OneBot 12 消息
+///
+/// `at`、`image`、`record`、`reply` 等转换为对应的标准消息段，其他 CQ 码保持原类型
+pub fn from_cq(s: &str) -> Segments {
+    parse(s)
+        .into_iter()
+        .map(|seg| from_cq_segment(seg, None))
+        .collect()
+}
+
+/// 将 OneBot 12 消息序列化为 CQ 码字符串
+pub fn to_cq(segments: &Segments) -> String {
+    serialize(
+        &segments
+            .iter()
+            .cloned()
+            .map(to_cq_segment)
+            .collect::<Vec<_>>(),
+    )
+}
+
+#[test]
+fn cq_test() {
+    use super::{IntoMessage, Location, Mention, MentionAll, Reply};
+
+    let cq = "[CQ:reply,id=1][CQ:at,qq=10001] &#91;hi&#93; &amp; [CQ:face,id=1][CQ:at,qq=all]\
+        [CQ:image,file=a.jpg,url=http://a/?b=1&#44;2][CQ:record,file=b.amr][CQ:location,content=,lat=1.5,lon=2,title=t]";
+    let segments = from_cq(cq);
+    assert_eq!(segments.len(), 8);
+    assert_eq!(to_cq(&segments), cq);
+
+    let message = vec![
+        Reply {
+            message_id: "1".to_owned(),
+            user_id: None,
+        }
+        .into(),
+        Mention {
+            user_id: "10001".to_owned(),
+        }
+        .into(),
+        MentionAll {}.into(),
+        Location {
+            latitude: 1.5,
+            longitude: 2.0,
+            title: "t".to_owned(),
+            content: "".to_owned(),
+        }
+        .into(),
+    ]
+    .into_iter()
+    .chain(" [a,b] & &amp; [CQ:x".into_message())
+    .collect::<Segments>();
+    let round = from_cq(&to_cq(&message));
+    assert_eq!(round.len(), message.len());
+    assert_eq!(round[1], message[1]);
+    assert_eq!(round[2], message[2]);
+    assert_eq!(round[3], message[3]);
+    assert_eq!(round[4], message[4]);
+    assert_eq!(
+        round[0].data.get("message_id"),
+        message[0].data.get("message_id")
+    );
+
+    // 未闭合的 CQ 码视为文本
+    assert_eq!(from_cq("a[CQ:at,qq=1"), "a[CQ:at,qq=1".into_message());
+    let face = MsgSegment {
+        ty: "qq.face".to_owned(),
+        data: value_map! { "id": "1" },
+    };
+    assert_eq!(to_cq(&vec![face]), "[CQ:face,id=1]");
+}
@@ -0,0 +1,339 @@
+//! 会话
+//!
+//! 交互式流程（问卷、确认等）需要等待同一用户在同一群组中的下一条消息。
+//! 通过 [`Sessions::begin`] 开启会话后，匹配 [`SessionKey`] 的 `message` 事件将转发至
+//! [`Session`]，不再分发给被 [`Sessions::handler`] 包装的 EventHandler，会话在 drop 时结束。
+//!
+//! ```rust,no_run
+//! use std::time::Duration;
+//! use walle_core::prelude::*;
+//! use walle_core::session::{SessionKey, Sessions};
+//!
+//! async fn confirm(sessions: &Sessions, event: &Event) -> WalleResult<bool> {
+//!     let key = SessionKey::from_event(event).unwrap();
+//!     let mut session = sessions.begin(key)?;
+//!     // 回复 "确认吗？" 后等待用户的下一条消息
+//!     Ok(match session.next(Duration::from_secs(30)).await {
+//!         Some(reply) => reply.extra.get_downcast::<String>("alt_message")? == "是",
+//!         None => false,
+//!     })
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
+
+use crate::event::Event;
+use crate::lifecycle::{RestartPolicy, TaskStatus};
+use crate::structs::Selft;
+use crate::util::ValueMapExt;
+use crate::{ActionHandler, EventHandler, OneBot, WalleError, WalleResult};
+
+/// 会话键，同一 bot 在同一会话场景下的同一用户
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SessionKey {
+    pub selft: Selft,
+    pub detail_type: String,
+    pub group_id: Option<String>,
+    pub guild_id: Option<String>,
+    pub channel_id: Option<String>,
+    pub user_id: String,
+}
+
+impl SessionKey {
+    /// 从 `message` 事件生成会话键，非 `message` 事件返回 None
+    pub fn from_event(event: &Event) -> Option<Self> {
+        if event.ty != "message" {
+            return None;
+        }
+        let get = |key: &str| event.extra.try_get_downcast::<String>(key).ok().flatten();
+        Some(Self {
+            selft: event.selft()?,
+            detail_type: event.detail_type.clone(),
+            group_id: get("group_id"),
+            guild_id: get("guild_id"),
+            channel_id: get("channel_id"),
+            user_id: get("user_id")?,
+        })
+    }
+}
+
+type Waiters = HashMap<SessionKey, (u64, UnboundedSender<Event>)>;
+
+/// 会话管理
+#[derive(Clone, Default)]
+pub struct Sessions {
+    waiters: Arc<Mutex<Waiters>>,
+    seq: Arc<AtomicU64>,
+}
+
+impl Sessions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// 开启会话，同一会话键同时只能存在一个会话
+    pub fn begin(&self, key: SessionKey) -> WalleResult<Session> {
+        let mut waiters = self.waiters.lock().unwrap();
+        if waiters.contains_key(&key) {
+            return Err(WalleError::SessionExists);
+        }
+        let id = self.seq.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = unbounded_channel();
+        waiters.insert(key.clone(), (id, tx));
+        Ok(Session {
+            key,
+            id,
+            rx,
+            waiters: self.waiters.clone(),
+        })
+    }
+    /// 开启会话并等待下一条消息，超时返回 None
+    pub async fn wait_next(
+        &self,
+        key: SessionKey,
+        timeout: Duration,
+    ) -> WalleResult<Option<Event>> {
+        Ok(self.begin(key)?.next(timeout).await)
+    }
+    pub fn is_active(&self, key: &SessionKey) -> bool {
+        self.waiters.lock().unwrap().contains_key(key)
+    }
+    /// 将事件转发至匹配的会话，返回事件是否被会话接收
+    pub fn feed(&self, event: &Event) -> bool {
+        let Some(key) = SessionKey::from_event(event) else {
+            return false;
+        };
+        match self.waiters.lock().unwrap().get(&key) {
+            Some((_, tx)) => tx.send(event.clone()).is_ok(),
+            None => false,
+        }
+    }
+    /// 包装 EventHandler，被会话接收的事件将不再分发给该 EventHandler
+    pub fn handler<EH>(&self, event_handler: EH) -> SessionHandler<EH> {
+        SessionHandler {
+            sessions: self.clone(),
+            inner: event_handler,
+        }
+    }
+}
+
+/// 进行中的会话，drop 时结束
+pub struct Session {
+    key: SessionKey,
+    id: u64,
+    rx: UnboundedReceiver<Event>,
+    waiters: Arc<Mutex<Waiters>>,
+}
+
+impl Session {
+    pub fn key(&self) -> &SessionKey {
+        &self.key
+    }
+    /// 等待会话中的下一条消息，超时返回 None
+    pub async fn next(&mut self, timeout: Duration) -> Option<Event> {
+        tokio::time::timeout(timeout, self.rx.recv())
+            .await
+            .ok()
+            .flatten()
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        let mut waiters = self.waiters.lock().unwrap();
+        if waiters.get(&self.key).map(|(id, _)| *id) == Some(self.id) {
+            waiters.remove(&self.key);
+        }
+    }
+}
+
+/// 由 [`Sessions::handler`] 创建的 EventHandler
+pub struct SessionHandler<EH> {
+    sessions: Sessions,
+    inner: EH,
+}
+
+impl<EH0, E, A, R> EventHandler<E, A, R> for SessionHandler<EH0>
+where
+    EH0: EventHandler<E, A, R> + Send + Sync + 'static,
+    EH0::Config: Send,
+    E: Clone + Into<Event> + Send + 'static,
+{
+    type Config = EH0::Config;
+    async fn start<AH, EH>(
+        &self,
+        ob: &Arc<OneBot<AH, EH>>,
+        config: Self::Config,
+    ) -> WalleResult<Vec<JoinHandle<()>>>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        self.inner.start(ob, config).await
+    }
+    async fn reconfigure<AH, EH>(
+        &self,
+        ob: &Arc<OneBot<AH, EH>>,
+        config: Self::Config,
+    ) -> WalleResult<()>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        self.inner.reconfigure(ob, config).await
+    }
+    fn validate_config(&self, config: &Self::Config) -> WalleResult<()> {
+        self.inner.validate_config(config)
+    }
+    fn task_status(&self) -> Vec<TaskStatus> {
+        self.inner.task_status()
+    }
+    async fn restart_failed<AH, EH>(&self, ob: &Arc<OneBot<AH, EH>>, policy: &RestartPolicy)
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        self.inner.restart_failed(ob, policy).await
+    }
+    async fn call<AH, EH>(&self, event: E, ob: &Arc<OneBot<AH, EH>>) -> WalleResult<()>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        if self.sessions.feed(&event.clone().into()) {
+            return Ok(());
+        }
+        self.inner.call(event, ob).await
+    }
+    async fn before_call_action<AH, EH>(
+        &self,
+        action: A,
+        ob: &Arc<OneBot<AH, EH>>,
+    ) -> WalleResult<A>
+    where
+        A: Send + 'static,
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        self.inner.before_call_action(action, ob).await
+    }
+    async fn after_call_action<AH, EH>(&self, resp: R, ob: &Arc<OneBot<AH, EH>>) -> WalleResult<R>
+    where
+        R: Send + 'static,
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        self.inner.after_call_action(resp, ob).await
+    }
+    async fn shutdown(&self) {
+        self.inner.shutdown().await
+    }
+    async fn on_onebot_connect<AH, EH>(&self, ob: &Arc<OneBot<AH, EH>>) -> WalleResult<()>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        self.inner.on_onebot_connect(ob).await
+    }
+    async fn on_onebot_disconnect<AH, EH>(&self, ob: &Arc<OneBot<AH, EH>>) -> WalleResult<()>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        self.inner.on_onebot_disconnect(ob).await
+    }
+}
+
+#[tokio::test]
+async fn session_test() {
+    use crate::value_map;
+
+    let message = |user_id: &str, text: &str| Event {
+        id: text.to_owned(),
+        time: 0.0,
+        ty: "message".to_owned(),
+        detail_type: "group".to_owned(),
+        sub_type: "".to_owned(),
+        extra: value_map! {
+            "self": Selft { platform: "test".to_owned(), user_id: "bot".to_owned() },
+            "group_id": "group",
+            "user_id": user_id,
+            "alt_message": text
+        },
+    };
+    let sessions = Sessions::new();
+    let key = SessionKey::from_event(&message("user", "start")).unwrap();
+    let mut session = sessions.begin(key.clone()).unwrap();
+    assert!(matches!(
+        sessions.begin(key.clone()),
+        Err(WalleError::SessionExists)
+    ));
+
+    assert!(!sessions.feed(&message("other", "hi")));
+    assert!(sessions.feed(&message("user", "yes")));
+    let reply = session.next(Duration::from_millis(10)).await.unwrap();
+    assert_eq!(reply.id, "yes");
+    assert!(session.next(Duration::from_millis(10)).await.is_none());
+
+    drop(session);
+    assert!(!sessions.is_active(&key));
+    assert!(!sessions.feed(&message("user", "late")));
+}
+
+#[cfg(feature = "impl-obc")]
+#[tokio::test]
+async fn session_handler_forward_test() {
+    use crate::action::Action;
+    use crate::config::{ImplConfig, MemoryServer};
+    use crate::obc::ImplOBC;
+    use crate::resp::Resp;
+    use crate::structs::Version;
+    use crate::testing::MockActionHandler;
+
+    let config = |name: &str| ImplConfig {
+        memory: vec![MemoryServer {
+            name: name.to_owned(),
+        }],
+        websocket_rev: vec![],
+        ..Default::default()
+    };
+    let ob = Arc::new(OneBot::new(
+        MockActionHandler::new(),
+        Sessions::new().handler(ImplOBC::<Event>::new("test".to_owned())),
+        Version {
+            implt: "test".to_owned(),
+            version: crate::VERSION.to_owned(),
+            onebot_version: 12.to_string(),
+        },
+    ));
+    ob.start::<Event, Action, Resp>((), config("session_old"), true)
+        .await
+        .unwrap();
+    assert!(ob
+        .task_status::<Event, Action, Resp>()
+        .iter()
+        .any(|s| s.name == "memory session_old"));
+
+    // 重新配置与校验均转发至被包装的 handler
+    assert!(matches!(
+        ob.reconfigure::<Event, Action, Resp>(None, Some(config("")))
+            .await,
+        Err(WalleError::Config(_))
+    ));
+    ob.reconfigure::<Event, Action, Resp>(None, Some(config("session_new")))
+        .await
+        .unwrap();
+    let names: Vec<String> = ob
+        .task_status::<Event, Action, Resp>()
+        .into_iter()
+        .map(|s| s.name)
+        .collect();
+    assert!(names.contains(&"memory session_new".to_owned()));
+    assert!(!names.contains(&"memory session_old".to_owned()));
+    ob.shutdown::<Event, Action, Resp>(true).await.unwrap();
+}
@@ -0,0 +1,393 @@
+//! 持久化事件存储
+//!
+//! [`EventStore`] 作为 EventHandler 将收到的每个 Event 以 json 行的形式追加写入日志文件，
+//! 并在内存中维护索引，可以按时间范围与条件查询。
+//! 相同 `id` 的事件只保存一次，`id` 为空的事件不去重。
+//!
+//! ```rust,no_run
+//! use walle_core::store::{EventQuery, EventStore};
+//!
+//! # async fn query(store: &EventStore) -> walle_core::WalleResult<()> {
+//! let now = walle_core::util::timestamp_nano_f64();
+//! let events = store
+//!     .query(&EventQuery {
+//!         start: Some(now - 24.0 * 3600.0),
+//!         group_id: Some("group".to_string()),
+//!         ..Default::default()
+//!     })
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashSet;
+use std::io::SeekFrom;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader};
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::config::EventStoreConfig;
+use crate::event::Event;
+use crate::structs::Selft;
+use crate::util::{timestamp_nano_f64, ProtocolItem, ValueMapExt};
+use crate::{ActionHandler, EventHandler, OneBot, WalleError, WalleResult};
+
+const STORE: &str = "EventStore";
+
+/// 事件索引
+#[derive(Debug, Clone)]
+struct Record {
+    id: String,
+    time: f64,
+    selft: Option<Selft>,
+    ty: String,
+    detail_type: String,
+    group_id: Option<String>,
+    user_id: Option<String>,
+    offset: u64,
+    len: u64,
+}
+
+impl Record {
+    fn new(event: &Event, offset: u64, len: u64) -> Self {
+        let get = |key: &str| event.extra.try_get_downcast::<String>(key).ok().flatten();
+        Self {
+            id: event.id.clone(),
+            time: event.time,
+            selft: event.selft(),
+            ty: event.ty.clone(),
+            detail_type: event.detail_type.clone(),
+            group_id: get("group_id"),
+            user_id: get("user_id"),
+            offset,
+            len,
+        }
+    }
+    fn matches(&self, query: &EventQuery) -> bool {
+        fn eq<T: PartialEq>(filter: &Option<T>, value: Option<&T>) -> bool {
+            filter.iter().all(|f| value == Some(f))
+        }
+        query.start.iter().all(|&start| self.time >= start)
+            && query.end.iter().all(|&end| self.time < end)
+            && eq(&query.selft, self.selft.as_ref())
+            && eq(&query.ty, Some(&self.ty))
+            && eq(&query.detail_type, Some(&self.detail_type))
+            && eq(&query.group_id, self.group_id.as_ref())
+            && eq(&query.user_id, self.user_id.as_ref())
+    }
+}
+
+/// 事件查询条件，为 None 的条件不做过滤
+///
+/// 时间范围为 `[start, end)`，单位为秒
+#[derive(Debug, Clone, Default)]
+pub struct EventQuery {
+    pub start: Option<f64>,
+    pub end: Option<f64>,
+    pub selft: Option<Selft>,
+    pub ty: Option<String>,
+    pub detail_type: Option<String>,
+    pub group_id: Option<String>,
+    pub user_id: Option<String>,
+    /// 最多返回的事件数量，按时间顺序返回最早的事件
+    pub limit: Option<usize>,
+}
+
+struct Log {
+    path: PathBuf,
+    file: File,
+    end: u64,
+    records: Vec<Record>,
+    ids: HashSet<String>,
+}
+
+impl Log {
+    async fn open(path: PathBuf) -> WalleResult<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)
+            .await?;
+        let mut log = Self {
+            path,
+            file,
+            end: 0,
+            records: vec![],
+            ids: HashSet::default(),
+        };
+        let mut reader = BufReader::new(log.file.try_clone().await?);
+        reader.seek(SeekFrom::Start(0)).await?;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let len = reader.read_line(&mut line).await? as u64;
+            if len == 0 {
+                break;
+            }
+            // 未写完的最后一行，截断后新事件从新的一行开始写入
+            if !line.ends_with('\n') {
+                warn!(
+                    target: STORE,
+                    "truncate partial event at {} ({} bytes)", log.end, len
+                );
+                log.file.set_len(log.end).await?;
+                break;
+            }
+            match Event::json_decode(line.trim_end()) {
+                Ok(event) => log.index(&event, log.end, len),
+                Err(e) => warn!(target: STORE, "skip broken event at {}: {}", log.end, e),
+            }
+            log.end += len;
+        }
+        Ok(log)
+    }
+    /// 是否已保存过相同 `id` 的事件，空 `id` 无法去重
+    fn is_duplicate(&self, event: &Event) -> bool {
+        !event.id.is_empty() && self.ids.contains(&event.id)
+    }
+    fn index(&mut self, event: &Event, offset: u64, len: u64) {
+        if event.id.is_empty() || self.ids.insert(event.id.clone()) {
+            self.records.push(Record::new(event, offset, len));
+        }
+    }
+    async fn append(&mut self, event: &Event) -> WalleResult<()> {
+        if self.is_duplicate(event) {
+            return Ok(());
+        }
+        let mut line = event.json_encode();
+        line.push('\n');
+        self.file.write_all(line.as_bytes()).await?;
+        self.file.flush().await?;
+        let len = line.len() as u64;
+        self.index(event, self.end, len);
+        self.end += len;
+        Ok(())
+    }
+    async fn read(&mut self, record: &Record) -> WalleResult<Event> {
+        let mut buf = vec![0; record.len as usize];
+        self.file.seek(SeekFrom::Start(record.offset)).await?;
+        self.file.read_exact(&mut buf).await?;
+        let line = std::str::from_utf8(&buf).map_err(|e| WalleError::Other(e.to_string()))?;
+        Event::json_decode(line.trim_end()).map_err(WalleError::Other)
+    }
+    /// 重写日志文件，仅保留 `before` 之后的事件
+    async fn compact(&mut self, before: f64) -> WalleResult<usize> {
+        let expired = self.records.iter().filter(|r| r.time < before).count();
+        if expired == 0 {
+            return Ok(0);
+        }
+        let tmp = self.path.with_extension("compact");
+        let mut out = File::create(&tmp).await?;
+        for record in self.records.clone() {
+            if record.time >= before {
+                let mut line = self.read(&record).await?.json_encode();
+                line.push('\n');
+                out.write_all(line.as_bytes()).await?;
+            }
+        }
+        out.sync_all().await?;
+        drop(out);
+        tokio::fs::rename(&tmp, &self.path).await?;
+        *self = Self::open(self.path.clone()).await?;
+        Ok(expired)
+    }
+}
+
+/// 持久化事件存储，启动后将收到的所有事件写入日志文件
+///
+/// 可以通过 `join` 与其他 EventHandler 组合使用
+#[derive(Default, Clone)]
+pub struct EventStore {
+    log: Arc<Mutex<Option<Log>>>,
+}
+
+impl EventStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// 打开日志文件并建立索引，通常由 `start` 调用
+    pub async fn open(&self, path: impl Into<PathBuf>) -> WalleResult<()> {
+        let log = Log::open(path.into()).await?;
+        info!(
+            target: STORE,
+            "EventStore opened {:?} with {} events",
+            log.path,
+            log.records.len()
+        );
+        *self.log.lock().await = Some(log);
+        Ok(())
+    }
+    /// 写入一个事件，已存在相同 id 的事件将被忽略
+    pub async fn insert(&self, event: &Event) -> WalleResult<()> {
+        match self.log.lock().await.as_mut() {
+            Some(log) => log.append(event).await,
+            None => Err(WalleError::NotStarted),
+        }
+    }
+    /// 按 id 获取事件
+    pub async fn get(&self, id: &str) -> WalleResult<Option<Event>> {
+        if id.is_empty() {
+            return Ok(None);
+        }
+        let mut log = self.log.lock().await;
+        let log = log.as_mut().ok_or(WalleError::NotStarted)?;
+        match log.records.iter().find(|r| r.id == id).cloned() {
+            Some(record) => log.read(&record).await.map(Some),
+            None => Ok(None),
+        }
+    }
+    /// 查询事件，按时间顺序返回
+    pub async fn query(&self, query: &EventQuery) -> WalleResult<Vec<Event>> {
+        let mut log = self.log.lock().await;
+        let log = log.as_mut().ok_or(WalleError::NotStarted)?;
+        let mut records = log
+            .records
+            .iter()
+            .filter(|r| r.matches(query))
+            .cloned()
+            .collect::<Vec<_>>();
+        records.sort_by(|a, b| a.time.total_cmp(&b.time));
+        records.truncate(query.limit.unwrap_or(usize::MAX));
+        let mut events = Vec::with_capacity(records.len());
+        for record in records {
+            events.push(log.read(&record).await?);
+        }
+        Ok(events)
+    }
+    /// 删除 `before` 之前的事件，返回删除的数量
+    pub async fn compact(&self, before: f64) -> WalleResult<usize> {
+        match self.log.lock().await.as_mut() {
+            Some(log) => log.compact(before).await,
+            None => Err(WalleError::NotStarted),
+        }
+    }
+}
+
+impl<E, A, R> EventHandler<E, A, R> for EventStore
+where
+    E: ProtocolItem + Clone + Into<Event>,
+    A: ProtocolItem,
+    R: ProtocolItem,
+{
+    type Config = EventStoreConfig;
+    async fn start<AH, EH>(
+        &self,
+        ob: &Arc<OneBot<AH, EH>>,
+        config: EventStoreConfig,
+    ) -> WalleResult<Vec<JoinHandle<()>>>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        self.open(&config.path).await?;
+        let mut tasks = vec![];
+        if let Some(retention) = config.retention {
+            let store = self.clone();
+            let mut signal = ob.get_signal_rx()?;
+            tasks.push(tokio::spawn(async move {
+                loop {
+                    let before = timestamp_nano_f64() - retention as f64;
+                    match store.compact(before).await {
+                        Ok(0) => {}
+                        Ok(n) => info!(target: STORE, "{} expired events removed", n),
+                        Err(e) => warn!(target: STORE, "compact failed: {}", e),
+                    }
+                    tokio::select! {
+                        _ = signal.recv() => break,
+                        _ = tokio::time::sleep(Duration::from_secs(60)) => {}
+                    }
+                }
+            }));
+        }
+        Ok(tasks)
+    }
+    async fn call<AH, EH>(&self, event: E, _ob: &Arc<OneBot<AH, EH>>) -> WalleResult<()>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        self.insert(&event.into()).await
+    }
+}
+
+#[tokio::test]
+async fn event_store_test() {
+    use crate::value_map;
+
+    let path = std::env::temp_dir().join(format!("walle-store-{}.log", std::process::id()));
+    tokio::fs::remove_file(&path).await.ok();
+    let event = |id: &str, time: f64, group_id: &str| Event {
+        id: id.to_string(),
+        time,
+        ty: "message".to_string(),
+        detail_type: "group".to_string(),
+        sub_type: "".to_string(),
+        extra: value_map! {
+            "group_id": group_id,
+            "user_id": "user"
+        },
+    };
+
+    let store = EventStore::new();
+    store.open(&path).await.unwrap();
+    store.insert(&event("1", 1.0, "a")).await.unwrap();
+    store.insert(&event("2", 2.0, "b")).await.unwrap();
+    store.insert(&event("3", 3.0, "a")).await.unwrap();
+    store.insert(&event("3", 3.0, "a")).await.unwrap();
+    // 空 id 的事件不去重
+    store.insert(&event("", 4.0, "c")).await.unwrap();
+    store.insert(&event("", 5.0, "c")).await.unwrap();
+
+    // reopen and rebuild index from log file
+    let store = EventStore::new();
+    store.open(&path).await.unwrap();
+    let ids = |events: Vec<Event>| events.into_iter().map(|e| e.id).collect::<Vec<_>>();
+    let query = EventQuery {
+        group_id: Some("a".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(ids(store.query(&query).await.unwrap()), vec!["1", "3"]);
+    let query = EventQuery {
+        start: Some(2.0),
+        user_id: Some("user".to_string()),
+        limit: Some(1),
+        ..Default::default()
+    };
+    assert_eq!(ids(store.query(&query).await.unwrap()), vec!["2"]);
+    assert!(store.get("2").await.unwrap().is_some());
+    assert!(store.get("").await.unwrap().is_none());
+    let query = EventQuery {
+        group_id: Some("c".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(store.query(&query).await.unwrap().len(), 2);
+
+    assert_eq!(store.compact(2.5).await.unwrap(), 2);
+    assert_eq!(
+        ids(store.query(&EventQuery::default()).await.unwrap()),
+        vec!["3", "", ""]
+    );
+
+    // 未写完的最后一行在打开时被截断，不影响之后写入的事件
+    drop(store);
+    let mut file = OpenOptions::new().append(true).open(&path).await.unwrap();
+    file.write_all(br#"{"id":"partial","#).await.unwrap();
+    drop(file);
+    let store = EventStore::new();
+    store.open(&path).await.unwrap();
+    store.insert(&event("6", 6.0, "a")).await.unwrap();
+    let store = EventStore::new();
+    store.open(&path).await.unwrap();
+    assert_eq!(
+        ids(store.query(&EventQuery::default()).await.unwrap()),
+        vec!["3", "", "", "6"]
+    );
+    tokio::fs::remove_file(&path).await.ok();
+}
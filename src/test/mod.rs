@@ -10,7 +10,9 @@ use crate::{
 use walle_macro::{
     _PushToValueMap as PushToValueMap, _ToEvent as ToEvent, _TryFromEvent as TryFromEvent,
 };
+#[allow(dead_code)]
 mod event;
+#[allow(dead_code)]
 mod value;
 
 #[test]
@@ -164,6 +166,88 @@ fn event() {
     ));
 }
 
+#[test]
+fn event_kind() {
+    use walle_macro::_EventKind as EventKind;
+    #[derive(Debug, Clone, EventKind)]
+    struct MyEvent {
+        id: String,
+        ty: String,
+        detail_type: String,
+    }
+    fn check<E: crate::event::EventKind>(e: &E, ty: &str, detail_type: &str) {
+        assert_eq!(e.id(), "id");
+        assert_eq!(e.ty(), ty);
+        assert_eq!(e.detail_type(), detail_type);
+    }
+
+    check(
+        &MyEvent {
+            id: "id".to_string(),
+            ty: "meta".to_string(),
+            detail_type: "heartbeat".to_string(),
+        },
+        "meta",
+        "heartbeat",
+    );
+    let event = Event {
+        id: "id".to_string(),
+        time: 0.0,
+        ty: "meta".to_string(),
+        detail_type: "heartbeat".to_string(),
+        sub_type: "".to_string(),
+        extra: value_map! { "interval": 4 },
+    };
+    check(&event, "meta", "heartbeat");
+    let event: HeartbeatEvent = event.try_into().unwrap();
+    check(&event, "meta", "heartbeat");
+
+    // manual impls without an id field keep compiling
+    struct NoId;
+    impl crate::event::EventKind for NoId {
+        fn ty(&self) -> &str {
+            "meta"
+        }
+        fn detail_type(&self) -> &str {
+            "heartbeat"
+        }
+    }
+    assert_eq!(crate::event::EventKind::id(&NoId), "");
+
+    // derive without an id field falls back to the default id
+    #[derive(EventKind)]
+    struct DerivedNoId {
+        ty: String,
+        detail_type: String,
+    }
+    let event = DerivedNoId {
+        ty: "meta".to_string(),
+        detail_type: "heartbeat".to_string(),
+    };
+    assert_eq!(crate::event::EventKind::id(&event), "");
+    assert_eq!(crate::event::EventKind::ty(&event), "meta");
+
+    // derive on generic structs
+    #[derive(EventKind)]
+    struct Generic<T: Clone> {
+        id: String,
+        ty: String,
+        detail_type: String,
+        #[allow(dead_code)]
+        extra: T,
+    }
+    check(
+        &Generic {
+            id: "id".to_string(),
+            ty: "message".to_string(),
+            detail_type: "private".to_string(),
+            extra: 0u8,
+        },
+        "message",
+        "private",
+    );
+}
+
 #[test]
 fn action() {
     fn test<T>(action: (&str, Action, T))
@@ -358,3 +442,54 @@ fn option_action() {
         )
     )
 }
+
+#[test]
+fn reply_action() {
+    let event = |detail_type: MessageDetailTypes| MessageDetailEvent {
+        id: "id".to_string(),
+        time: 0.0,
+        implt: (),
+        platform: (),
+        ty: Message {
+            selft: Selft {
+                platform: "qq".to_string(),
+                user_id: "bot".to_string(),
+            },
+            message_id: "41".to_string(),
+            message: vec![],
+            alt_message: "".to_string(),
+            user_id: "10001".to_string(),
+        },
+        detail_type,
+        sub_type: (),
+        extra: value_map!(),
+    };
+    let group = event(MessageDetailTypes::Group(Group {
+        group_id: "12467".to_string(),
+    }));
+    let reply = group.reply_action("hi");
+    assert_eq!(reply.detail_type, "group");
+    assert_eq!(reply.group_id.as_deref(), Some("12467"));
+    assert_eq!(reply.user_id, None);
+    assert_eq!(
+        group.reply_quote_action("hi", true).message,
+        vec![
+            Reply {
+                message_id: "41".to_string(),
+                user_id: Some("10001".to_string()),
+            }
+            .into(),
+            Mention {
+                user_id: "10001".to_string(),
+            }
+            .into(),
+            "hi".into(),
+        ]
+    );
+
+    let private = event(MessageDetailTypes::Private(Private));
+    let reply = private.reply_quote_action("hi", true);
+    assert_eq!(reply.detail_type, "private");
+    assert_eq!(reply.user_id.as_deref(), Some("10001"));
+    assert_eq!(reply.message.len(), 2);
+}
@@ -0,0 +1,566 @@
+//! 集成测试工具，需要 `testing` feature
+//!
+//! - [`MockActionHandler`]：可编排的 ActionHandler，记录收到的 Action 并按 action 名称返回预设的 Resp
+//! - [`MockEventHandler`]：记录收到的 Event，可等待满足条件的 Event
+//! - [`MockEvents`]：构造事件并通过 `OneBot::handle_event` 注入
+//! - [`Loopback`]：通过本地回环 WebSocket 或进程内内存传输连接的 ImplOBC / AppOBC，用于端到端测试
+//!   （需要 `impl-obc` 与 `app-obc` feature，WebSocket 另需 `websocket` feature）
+//!
+//! ```rust
+//! use std::sync::Arc;
+//! use walle_core::prelude::*;
+//! use walle_core::testing::{MockActionHandler, MockEventHandler, MockEvents};
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> WalleResult<()> {
+//! let selft = Selft { platform: "test".to_string(), user_id: "bot".to_string() };
+//! let ah = MockActionHandler::new()
+//!     .with_bot(selft.clone())
+//!     .with_resp("get_self_info", value_map! { "user_id": "bot" });
+//! let eh = MockEventHandler::new();
+//! let version = Version {
+//!     implt: walle_core::WALLE_CORE.to_owned(),
+//!     version: walle_core::VERSION.to_owned(),
+//!     onebot_version: 12.to_string(),
+//! };
+//! let ob = Arc::new(OneBot::new(ah.clone(), eh.clone(), version));
+//!
+//! MockEvents::new(selft).inject(&ob, |events| events.private_message("alice", "hi")).await?;
+//! assert_eq!(eh.events().len(), 1);
+//!
+//! let resp: Resp = ob.handle_action::<Event, _, _>(Action {
+//!     action: "get_self_info".to_string(),
+//!     selft: None,
+//!     params: value_map!(),
+//! }).await?;
+//! assert_eq!(resp.retcode, 0);
+//! assert_eq!(ah.called("get_self_info").len(), 1);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+use crate::action::Action;
+use crate::ah::GenStatus;
+use crate::event::Event;
+use crate::resp::{resp_error, Resp};
+use crate::segment::{alt, IntoMessage, Segments};
+use crate::structs::{Bot, Selft, Status};
+use crate::util::{timestamp_nano_f64, ValueMap};
+use crate::{value_map, ActionHandler, EventHandler, OneBot, WalleResult};
+
+type Responder = Arc<dyn Fn(&Action) -> Resp + Send + Sync>;
+
+#[derive(Default)]
+struct MockActionInner {
+    bots: Mutex<Vec<Selft>>,
+    responders: Mutex<HashMap<String, Responder>>,
+    actions: Mutex<Vec<Action>>,
+}
+
+/// 可编排的 ActionHandler
+///
+/// 未设置 Resp 的 action 返回 `unsupported_action`，clone 后共享状态
+#[derive(Clone, Default)]
+pub struct MockActionHandler {
+    inner: Arc<MockActionInner>,
+}
+
+impl MockActionHandler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// 添加在线 bot，将出现在 `gen_status` 中
+    pub fn with_bot(self, selft: Selft) -> Self {
+        self.inner.bots.lock().unwrap().push(selft);
+        self
+    }
+    /// 设置 action 返回固定的 Resp
+    pub fn with_resp(self, action: &str, resp: impl Into<Resp>) -> Self {
+        let resp: Resp = resp.into();
+        self.with_handler(action, move |_| resp.clone())
+    }
+    /// 设置 action 根据 Action 生成 Resp
+    pub fn with_handler<F>(self, action: &str, handler: F) -> Self
+    where
+        F: Fn(&Action) -> Resp + Send + Sync + 'static,
+    {
+        self.inner
+            .responders
+            .lock()
+            .unwrap()
+            .insert(action.to_owned(), Arc::new(handler));
+        self
+    }
+    /// 按顺序返回所有收到的 Action
+    pub fn actions(&self) -> Vec<Action> {
+        self.inner.actions.lock().unwrap().clone()
+    }
+    /// 返回并清空所有收到的 Action
+    pub fn take_actions(&self) -> Vec<Action> {
+        std::mem::take(&mut *self.inner.actions.lock().unwrap())
+    }
+    /// 返回收到的指定名称的 Action
+    pub fn called(&self, action: &str) -> Vec<Action> {
+        self.inner
+            .actions
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|a| a.action == action)
+            .cloned()
+            .collect()
+    }
+    fn respond(&self, action: Action) -> Resp {
+        let responder = self
+            .inner
+            .responders
+            .lock()
+            .unwrap()
+            .get(&action.action)
+            .cloned();
+        let resp = match &responder {
+            Some(responder) => responder(&action),
+            None => resp_error::unsupported_action(&action.action).into(),
+        };
+        self.inner.actions.lock().unwrap().push(action);
+        resp
+    }
+}
+
+impl GenStatus for MockActionHandler {
+    fn contains_bot(&self, bot: &Selft) -> bool {
+        self.inner.bots.lock().unwrap().contains(bot)
+    }
+    fn gen_status(&self) -> Status {
+        Status {
+            good: true,
+            bots: self
+                .inner
+                .bots
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|selft| Bot {
+                    selft: selft.clone(),
+                    online: true,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl<E, A, R> ActionHandler<E, A, R> for MockActionHandler
+where
+    A: Into<Action> + Send + 'static,
+    R: From<Resp>,
+{
+    type Config = ();
+    async fn start<AH, EH>(
+        &self,
+        _ob: &Arc<OneBot<AH, EH>>,
+        _config: (),
+    ) -> WalleResult<Vec<JoinHandle<()>>>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        Ok(vec![])
+    }
+    async fn call<AH, EH>(&self, action: A, _ob: &Arc<OneBot<AH, EH>>) -> WalleResult<R>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        Ok(self.respond(action.into()).into())
+    }
+}
+
+#[derive(Default)]
+struct MockEventInner {
+    events: Mutex<Vec<Event>>,
+    notify: Notify,
+}
+
+/// 记录收到的 Event 的 EventHandler，clone 后共享状态
+#[derive(Clone, Default)]
+pub struct MockEventHandler {
+    inner: Arc<MockEventInner>,
+}
+
+impl MockEventHandler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// 按顺序返回所有收到的 Event
+    pub fn events(&self) -> Vec<Event> {
+        self.inner.events.lock().unwrap().clone()
+    }
+    /// 返回并清空所有收到的 Event
+    pub fn take_events(&self) -> Vec<Event> {
+        std::mem::take(&mut *self.inner.events.lock().unwrap())
+    }
+    /// 等待满足条件的 Event（包括已收到的），超时返回 None
+    pub async fn wait_for<F>(&self, f: F, timeout: Duration) -> Option<Event>
+    where
+        F: Fn(&Event) -> bool,
+    {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let notified = self.inner.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if let Some(event) = self.inner.events.lock().unwrap().iter().find(|e| f(e)) {
+                return Some(event.clone());
+            }
+            tokio::time::timeout_at(deadline, notified).await.ok()?;
+        }
+    }
+}
+
+impl<E, A, R> EventHandler<E, A, R> for MockEventHandler
+where
+    E: Into<Event> + Send + 'static,
+{
+    type Config = ();
+    async fn start<AH, EH>(
+        &self,
+        _ob: &Arc<OneBot<AH, EH>>,
+        _config: (),
+    ) -> WalleResult<Vec<JoinHandle<()>>>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        Ok(vec![])
+    }
+    async fn call<AH, EH>(&self, event: E, _ob: &Arc<OneBot<AH, EH>>) -> WalleResult<()>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        self.inner.events.lock().unwrap().push(event.into());
+        self.inner.notify.notify_waiters();
+        Ok(())
+    }
+}
+
+/// 模拟事件源，以指定 bot 的身份构造事件
+///
+/// 事件 `id` 与 `message_id` 为递增序号
+pub struct MockEvents {
+    selft: Selft,
+    seq: AtomicU64,
+}
+
+impl MockEvents {
+    pub fn new(selft: Selft) -> Self {
+        Self {
+            selft,
+            seq: AtomicU64::default(),
+        }
+    }
+    fn next_id(&self) -> String {
+        self.seq.fetch_add(1, Ordering::Relaxed).to_string()
+    }
+    /// 构造事件，非 `meta` 事件将添加 `self` 字段
+    pub fn event(&self, ty: &str, detail_type: &str, mut extra: ValueMap) -> Event {
+        if ty != "meta" {
+            extra.insert("self".to_owned(), self.selft.clone().into());
+        }
+        Event {
+            id: self.next_id(),
+            time: timestamp_nano_f64(),
+            ty: ty.to_owned(),
+            detail_type: detail_type.to_owned(),
+            sub_type: String::default(),
+            extra,
+        }
+    }
+    fn message(&self, detail_type: &str, mut extra: ValueMap, message: Segments) -> Event {
+        extra.insert("message_id".to_owned(), self.next_id().into());
+        extra.insert("alt_message".to_owned(), alt(&message).into());
+        extra.insert("message".to_owned(), message.into());
+        self.event("message", detail_type, extra)
+    }
+    pub fn private_message(&self, user_id: &str, message: impl IntoMessage) -> Event {
+        self.message(
+            "private",
+            value_map! { "user_id": user_id },
+            message.into_message(),
+        )
+    }
+    pub fn group_message(&self, group_id: &str, user_id: &str, message: impl IntoMessage) -> Event {
+        self.message(
+            "group",
+            value_map! { "group_id": group_id, "user_id": user_id },
+            message.into_message(),
+        )
+    }
+    pub fn channel_message(
+        &self,
+        guild_id: &str,
+        channel_id: &str,
+        user_id: &str,
+        message: impl IntoMessage,
+    ) -> Event {
+        self.message(
+            "channel",
+            value_map! { "guild_id": guild_id, "channel_id": channel_id, "user_id": user_id },
+            message.into_message(),
+        )
+    }
+    /// 构造事件并通过 `OneBot::handle_event` 注入
+    pub async fn inject<F, AH, EH>(&self, ob: &Arc<OneBot<AH, EH>>, f: F) -> WalleResult<()>
+    where
+        F: FnOnce(&Self) -> Event,
+        AH: ActionHandler<Event, Action, Resp> + Send + Sync + 'static,
+        EH: EventHandler<Event, Action, Resp> + Send + Sync + 'static,
+    {
+        ob.handle_event(f(self)).await
+    }
+}
+
+#[cfg(all(feature = "impl-obc", feature = "app-obc"))]
+pub use loopback::Loopback;
+
+#[cfg(all(feature = "impl-obc", feature = "app-obc"))]
+mod loopback {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::action::Action;
+    use crate::ah::GenStatus;
+    use crate::config::{AppConfig, Heartbeat, ImplConfig, MemoryClient, MemoryServer};
+    use crate::event::Event;
+    use crate::obc::{AppOBC, ImplOBC};
+    use crate::resp::Resp;
+    use crate::structs::Version;
+    use crate::{ActionHandler, EventHandler, OneBot, WalleError, WalleResult};
+
+    /// 等待应用端获取实现端所有 bot 的超时时间
+    const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// 连接的实现端与应用端
+    ///
+    /// 实现端 ImplOBC 监听（不发送心跳），应用端 AppOBC 作为客户端连接，
+    /// 启动后等待应用端获取实现端 ActionHandler 的所有 bot
+    pub struct Loopback<AH, EH> {
+        pub impl_ob: Arc<OneBot<AH, ImplOBC<Event>>>,
+        pub app_ob: Arc<OneBot<AppOBC<Action, Resp>, EH>>,
+    }
+
+    fn version() -> Version {
+        Version {
+            implt: crate::WALLE_CORE.to_owned(),
+            version: crate::VERSION.to_owned(),
+            onebot_version: 12.to_string(),
+        }
+    }
+
+    /// 仅启用指定传输的实现端设置
+    fn impl_config() -> ImplConfig {
+        ImplConfig {
+            websocket_rev: vec![],
+            heartbeat: Heartbeat {
+                enabled: false,
+                interval: 0,
+            },
+            ..Default::default()
+        }
+    }
+
+    impl<AH, EH> Loopback<AH, EH>
+    where
+        AH: ActionHandler<Event, Action, Resp> + Send + Sync + 'static,
+        EH: EventHandler<Event, Action, Resp> + Send + Sync + 'static,
+        AH::Config: Clone + Send + Sync + 'static,
+        EH::Config: Clone + Send + Sync + 'static,
+    {
+        /// 通过本地回环 WebSocket 连接，实现端监听随机端口
+        #[cfg(feature = "websocket")]
+        pub async fn start(
+            action_handler: AH,
+            ah_config: AH::Config,
+            event_handler: EH,
+            eh_config: EH::Config,
+        ) -> WalleResult<Self> {
+            use crate::config::{WebSocketClient, WebSocketServer};
+
+            let port = std::net::TcpListener::bind(("127.0.0.1", 0))?
+                .local_addr()?
+                .port();
+            let impl_config = ImplConfig {
+                websocket: vec![WebSocketServer {
+                    port,
+                    ..Default::default()
+                }],
+                ..impl_config()
+            };
+            let app_config = AppConfig {
+                websocket: vec![WebSocketClient {
+                    url: format!("ws://127.0.0.1:{}", port),
+                    reconnect_interval: 1,
+                    ..Default::default()
+                }],
+                ..AppConfig::empty()
+            };
+            Self::start_with(
+                action_handler,
+                ah_config,
+                impl_config,
+                event_handler,
+                eh_config,
+                app_config,
+            )
+            .await
+        }
+        /// 通过进程内内存传输连接
+        pub async fn start_memory(
+            action_handler: AH,
+            ah_config: AH::Config,
+            event_handler: EH,
+            eh_config: EH::Config,
+            server: MemoryServer,
+            client: MemoryClient,
+        ) -> WalleResult<Self> {
+            let impl_config = ImplConfig {
+                memory: vec![server],
+                ..impl_config()
+            };
+            let app_config = AppConfig {
+                memory: vec![client],
+                ..AppConfig::empty()
+            };
+            Self::start_with(
+                action_handler,
+                ah_config,
+                impl_config,
+                event_handler,
+                eh_config,
+                app_config,
+            )
+            .await
+        }
+        /// 使用自定义的实现端与应用端设置连接
+        ///
+        /// 实现端的 `heartbeat` 与 `websocket_rev` 设置按原样使用，如不需要请自行关闭
+        pub async fn start_with(
+            action_handler: AH,
+            ah_config: AH::Config,
+            impl_config: ImplConfig,
+            event_handler: EH,
+            eh_config: EH::Config,
+            app_config: AppConfig,
+        ) -> WalleResult<Self> {
+            let impl_ob = Arc::new(OneBot::new(
+                action_handler,
+                ImplOBC::new("loopback".to_owned()),
+                version(),
+            ));
+            impl_ob.start(ah_config, impl_config, true).await?;
+            let app_ob = Arc::new(OneBot::new(AppOBC::new(), event_handler, version()));
+            if let Err(e) = app_ob.start(app_config, eh_config, false).await {
+                impl_ob.shutdown(true).await.ok();
+                return Err(e);
+            }
+            let loopback = Self { impl_ob, app_ob };
+            let connected = tokio::time::timeout(CONNECT_TIMEOUT, async {
+                while !loopback
+                    .impl_ob
+                    .gen_status()
+                    .bots
+                    .iter()
+                    .all(|bot| loopback.app_ob.contains_bot(&bot.selft))
+                {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            })
+            .await;
+            if connected.is_err() {
+                loopback.shutdown().await;
+                return Err(WalleError::Other("loopback connect timeout".to_owned()));
+            }
+            Ok(loopback)
+        }
+        /// 关闭应用端与实现端
+        pub async fn shutdown(&self) {
+            self.app_ob.shutdown(true).await.ok();
+            self.impl_ob.shutdown(true).await.ok();
+        }
+    }
+}
+
+#[cfg(all(feature = "impl-obc", feature = "app-obc", feature = "websocket"))]
+#[tokio::test]
+async fn loopback_test() {
+    use crate::action::SendMessage;
+    use crate::structs::SendMessageResp;
+
+    let selft = Selft {
+        platform: "test".to_owned(),
+        user_id: "bot".to_owned(),
+    };
+    let ah = MockActionHandler::new()
+        .with_bot(selft.clone())
+        .with_handler("send_message", |action| {
+            value_map! { "message_id": action.action.clone(), "time": 0.0 }.into()
+        });
+    let eh = MockEventHandler::new();
+    let loopback = Loopback::start(ah.clone(), (), eh.clone(), ())
+        .await
+        .unwrap();
+
+    let events = MockEvents::new(selft.clone());
+    events
+        .inject(&loopback.impl_ob, |events| {
+            events.group_message("g", "alice", "ping")
+        })
+        .await
+        .unwrap();
+    let event = eh
+        .wait_for(|e| e.ty == "message", Duration::from_secs(1))
+        .await
+        .unwrap();
+    assert_eq!(event.detail_type, "group");
+    assert_eq!(eh.events().iter().filter(|e| e.ty == "message").count(), 1);
+
+    let mut action: Action = SendMessage {
+        detail_type: "group".to_owned(),
+        group_id: Some("g".to_owned()),
+        user_id: None,
+        guild_id: None,
+        channel_id: None,
+        message: "pong".into_message(),
+    }
+    .into();
+    action.selft = Some(selft);
+    let resp: SendMessageResp = loopback
+        .app_ob
+        .handle_action::<Event, _, _>(action)
+        .await
+        .unwrap()
+        .as_result_downcast()
+        .unwrap();
+    assert_eq!(resp.message_id, "send_message");
+    assert_eq!(ah.called("send_message").len(), 1);
+
+    let resp: Resp = loopback
+        .app_ob
+        .handle_action::<Event, _, _>(Action {
+            action: "unknown".to_owned(),
+            selft: ah.actions()[0].selft.clone(),
+            params: value_map!(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(resp.retcode, 10002);
+    loopback.shutdown().await;
+}
@@ -0,0 +1,469 @@
+//! 应用端文件上传与下载
+//!
+//! 基于 `OneBot::handle_action` 封装 `upload_file`、`upload_file_fragmented` 与
+//! `get_file_fragmented` 动作，按文件大小选择单次或分片传输，计算 `sha256` 并报告进度。
+//!
+//! ```rust,no_run
+//! use std::sync::Arc;
+//! use walle_core::prelude::*;
+//! use walle_core::transfer::{upload_file_from_path, TransferConfig};
+//!
+//! async fn upload<E, AH, EH>(ob: &Arc<OneBot<AH, EH>>, selft: &Selft) -> WalleResult<FileId>
+//! where
+//!     AH: ActionHandler<E, Action, Resp> + Send + Sync + 'static,
+//!     EH: EventHandler<E, Action, Resp> + Send + Sync + 'static,
+//! {
+//!     upload_file_from_path(ob, selft, "image.png", &TransferConfig::default(), |p| {
+//!         println!("{}/{}", p.transferred, p.total)
+//!     })
+//!     .await
+//! }
+//! ```
+
+use std::io::SeekFrom;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use futures_util::{StreamExt, TryStreamExt};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+use crate::action::{Action, GetFile, GetFileFragmented, UploadFile, UploadFileFragmented};
+use crate::resp::{resp_error, Resp};
+use crate::structs::{FileFragmentedData, FileFragmentedHead, FileId, Selft};
+use crate::util::{OneBotBytes, ValueMapExt};
+use crate::{ActionHandler, EventHandler, OneBot, WalleError, WalleResult};
+
+/// 文件传输设置
+#[derive(Debug, Clone)]
+pub struct TransferConfig {
+    /// 文件大小超过该值时使用分片传输
+    pub threshold: u64,
+    /// 分片大小
+    pub fragment_size: u64,
+    /// 同时进行的分片传输数量
+    pub concurrency: usize,
+}
+
+impl Default for TransferConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 4 * 1024 * 1024,
+            fragment_size: 1024 * 1024,
+            concurrency: 4,
+        }
+    }
+}
+
+/// 传输进度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    pub transferred: u64,
+    pub total: u64,
+}
+
+fn fragments(total: u64, fragment_size: u64) -> impl Iterator<Item = (u64, u64)> {
+    let fragment_size = fragment_size.max(1);
+    (0..total)
+        .step_by(fragment_size as usize)
+        .map(move |offset| (offset, fragment_size.min(total - offset)))
+}
+
+async fn sha256_file(path: &Path) -> WalleResult<String> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+async fn call<E, AH, EH>(
+    ob: &Arc<OneBot<AH, EH>>,
+    selft: &Selft,
+    action: impl Into<Action>,
+) -> WalleResult<Resp>
+where
+    AH: ActionHandler<E, Action, Resp> + Send + Sync + 'static,
+    EH: EventHandler<E, Action, Resp> + Send + Sync + 'static,
+{
+    let mut action: Action = action.into();
+    action.selft = Some(selft.clone());
+    ob.handle_action(action).await
+}
+
+/// 上传本地文件，返回 `file_id`
+///
+/// 文件大小不超过 `threshold` 时使用 `upload_file` 单次上传，否则使用 `upload_file_fragmented`
+pub async fn upload_file_from_path<E, AH, EH>(
+    ob: &Arc<OneBot<AH, EH>>,
+    selft: &Selft,
+    path: impl AsRef<Path>,
+    config: &TransferConfig,
+    progress: impl Fn(Progress) + Send + Sync,
+) -> WalleResult<FileId>
+where
+    AH: ActionHandler<E, Action, Resp> + Send + Sync + 'static,
+    EH: EventHandler<E, Action, Resp> + Send + Sync + 'static,
+{
+    let path = path.as_ref();
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let total = tokio::fs::metadata(path).await?.len();
+    let sha256 = sha256_file(path).await?;
+
+    if total <= config.threshold {
+        let data = tokio::fs::read(path).await?;
+        let file_id = call(
+            ob,
+            selft,
+            UploadFile {
+                ty: "data".to_string(),
+                name,
+                url: None,
+                headers: None,
+                path: None,
+                data: Some(data.into()),
+                sha256: Some(sha256),
+            },
+        )
+        .await?
+        .as_result_downcast()?;
+        progress(Progress {
+            transferred: total,
+            total,
+        });
+        return Ok(file_id);
+    }
+
+    let FileId { file_id } = call(
+        ob,
+        selft,
+        UploadFileFragmented::Prepare {
+            name,
+            total_size: total as i64,
+        },
+    )
+    .await?
+    .as_result_downcast()?;
+    let transferred = AtomicU64::new(0);
+    futures_util::stream::iter(fragments(total, config.fragment_size))
+        .map(|(offset, size)| {
+            let (file_id, transferred, progress) = (&file_id, &transferred, &progress);
+            async move {
+                let mut file = tokio::fs::File::open(path).await?;
+                file.seek(SeekFrom::Start(offset)).await?;
+                let mut data = vec![0; size as usize];
+                file.read_exact(&mut data).await?;
+                call(
+                    ob,
+                    selft,
+                    UploadFileFragmented::Transfer {
+                        file_id: file_id.clone(),
+                        offset: offset as i64,
+                        data: data.into(),
+                    },
+                )
+                .await?
+                .as_result()
+                .map_err(WalleError::RespError)?;
+                progress(Progress {
+                    transferred: transferred.fetch_add(size, Ordering::AcqRel) + size,
+                    total,
+                });
+                WalleResult::Ok(())
+            }
+        })
+        .buffer_unordered(config.concurrency.max(1))
+        .try_collect::<()>()
+        .await?;
+    call(
+        ob,
+        selft,
+        UploadFileFragmented::Finish {
+            file_id,
+            sha256: Some(sha256),
+        },
+    )
+    .await?
+    .as_result_downcast()
+}
+
+/// 下载文件到本地路径，完成后校验 `sha256`
+///
+/// 通过 `get_file_fragmented` prepare 阶段获取文件大小，不超过 `threshold` 时使用 `get_file`
+/// 单次获取全部内容，否则分片获取
+pub async fn download_file_to_path<E, AH, EH>(
+    ob: &Arc<OneBot<AH, EH>>,
+    selft: &Selft,
+    file_id: &str,
+    path: impl AsRef<Path>,
+    config: &TransferConfig,
+    progress: impl Fn(Progress) + Send + Sync,
+) -> WalleResult<()>
+where
+    AH: ActionHandler<E, Action, Resp> + Send + Sync + 'static,
+    EH: EventHandler<E, Action, Resp> + Send + Sync + 'static,
+{
+    let path = path.as_ref();
+    let head: FileFragmentedHead = call(
+        ob,
+        selft,
+        GetFileFragmented::Prepare {
+            file_id: file_id.to_owned(),
+        },
+    )
+    .await?
+    .as_result_downcast()?;
+    let total = head.total_size as u64;
+    if total <= config.threshold {
+        let mut file = call(
+            ob,
+            selft,
+            GetFile {
+                file_id: file_id.to_owned(),
+                ty: "data".to_string(),
+            },
+        )
+        .await?
+        .as_result()
+        .map_err(WalleError::RespError)?
+        .downcast_map()?;
+        // `data` 可能为 bytes 或 base64 字符串
+        let data = file
+            .try_remove_downcast::<OneBotBytes>("data")?
+            .ok_or_else(|| {
+                WalleError::RespError(resp_error::bad_handler("get_file missing field data"))
+            })?
+            .0;
+        tokio::fs::write(path, &data).await?;
+        progress(Progress {
+            transferred: data.len() as u64,
+            total,
+        });
+    } else {
+        download_fragments(ob, selft, file_id, path, total, config, &progress).await?;
+    }
+    let sha256 = sha256_file(path).await?;
+    if !sha256.eq_ignore_ascii_case(&head.sha256) {
+        return Err(WalleError::RespError(resp_error::bad_handler(format!(
+            "sha256 mismatch, expect {} got {}",
+            head.sha256, sha256
+        ))));
+    }
+    Ok(())
+}
+
+async fn download_fragments<E, AH, EH>(
+    ob: &Arc<OneBot<AH, EH>>,
+    selft: &Selft,
+    file_id: &str,
+    path: &Path,
+    total: u64,
+    config: &TransferConfig,
+    progress: &(impl Fn(Progress) + Send + Sync),
+) -> WalleResult<()>
+where
+    AH: ActionHandler<E, Action, Resp> + Send + Sync + 'static,
+    EH: EventHandler<E, Action, Resp> + Send + Sync + 'static,
+{
+    tokio::fs::File::create(path).await?.set_len(total).await?;
+    let transferred = AtomicU64::new(0);
+    futures_util::stream::iter(fragments(total, config.fragment_size))
+        .map(|(offset, size)| {
+            let transferred = &transferred;
+            async move {
+                let FileFragmentedData { data } = call(
+                    ob,
+                    selft,
+                    GetFileFragmented::Transfer {
+                        file_id: file_id.to_owned(),
+                        offset: offset as i64,
+                        size: size as i64,
+                    },
+                )
+                .await?
+                .as_result_downcast()?;
+                if data.0.len() as u64 != size {
+                    return Err(WalleError::RespError(resp_error::bad_handler(format!(
+                        "expect {} bytes at offset {}, got {}",
+                        size,
+                        offset,
+                        data.0.len()
+                    ))));
+                }
+                let mut file = tokio::fs::OpenOptions::new().write(true).open(path).await?;
+                file.seek(SeekFrom::Start(offset)).await?;
+                file.write_all(&data.0).await?;
+                file.flush().await?;
+                progress(Progress {
+                    transferred: transferred.fetch_add(size, Ordering::AcqRel) + size,
+                    total,
+                });
+                Ok(())
+            }
+        })
+        .buffer_unordered(config.concurrency.max(1))
+        .try_collect::<()>()
+        .await
+}
+
+#[test]
+fn fragments_test() {
+    assert_eq!(
+        fragments(10, 4).collect::<Vec<_>>(),
+        vec![(0, 4), (4, 4), (8, 2)]
+    );
+    assert_eq!(fragments(0, 4).count(), 0);
+}
+
+#[tokio::test]
+async fn transfer_test() {
+    use crate::event::Event;
+    use crate::structs::{File, Version};
+    use crate::testing::{MockActionHandler, MockEventHandler};
+    use std::sync::Mutex;
+
+    let content: Vec<u8> = (0..10).collect();
+    let sha256 = hex::encode(Sha256::digest(&content));
+    let stored = Arc::new(Mutex::new(vec![0u8; content.len()]));
+    let (stored_, stored__) = (stored.clone(), stored.clone());
+    let (content_, sha256_) = (content.clone(), sha256.clone());
+    let ah = MockActionHandler::new()
+        .with_handler("upload_file", |action| {
+            let upload = UploadFile::try_from(&mut action.params.clone()).unwrap();
+            assert_eq!(upload.data.unwrap().0.len(), 10);
+            FileId {
+                file_id: "single".to_string(),
+            }
+            .into()
+        })
+        .with_handler("upload_file_fragmented", move |action| {
+            let file_id = "fragmented".to_string();
+            match UploadFileFragmented::try_from(&mut action.params.clone()).unwrap() {
+                UploadFileFragmented::Transfer { offset, data, .. } => {
+                    let offset = offset as usize;
+                    stored_.lock().unwrap()[offset..offset + data.0.len()].copy_from_slice(&data.0);
+                    Resp::from(crate::util::Value::Null)
+                }
+                UploadFileFragmented::Finish { sha256, .. } => {
+                    let hash = hex::encode(Sha256::digest(&*stored_.lock().unwrap()));
+                    match sha256 {
+                        Some(sha256) if sha256 == hash => FileId { file_id }.into(),
+                        _ => resp_error::bad_param("sha256 mismatch").into(),
+                    }
+                }
+                UploadFileFragmented::Prepare { .. } => FileId { file_id }.into(),
+            }
+        })
+        .with_handler("get_file", move |_| {
+            File {
+                name: "file".to_string(),
+                url: None,
+                headers: None,
+                path: None,
+                data: Some(content_.clone()),
+                sha256: None,
+            }
+            .into()
+        })
+        .with_handler("get_file_fragmented", move |action| {
+            match GetFileFragmented::try_from(&mut action.params.clone()).unwrap() {
+                GetFileFragmented::Prepare { file_id } => FileFragmentedHead {
+                    name: "file".to_string(),
+                    total_size: 10,
+                    // "broken" 的 sha256 与内容不符
+                    sha256: match file_id.as_str() {
+                        "broken" => "00".to_string(),
+                        _ => sha256_.clone(),
+                    },
+                }
+                .into(),
+                GetFileFragmented::Transfer { offset, size, .. } => {
+                    let data = &stored__.lock().unwrap()[offset as usize..][..size as usize];
+                    FileFragmentedData {
+                        data: data.to_vec().into(),
+                    }
+                    .into()
+                }
+            }
+        });
+    let ob = Arc::new(OneBot::new(
+        ah.clone(),
+        MockEventHandler::new(),
+        Version {
+            implt: "test".to_owned(),
+            version: crate::VERSION.to_owned(),
+            onebot_version: 12.to_string(),
+        },
+    ));
+    let selft = Selft {
+        platform: "test".to_owned(),
+        user_id: "bot".to_owned(),
+    };
+    let dir = std::env::temp_dir().join(format!("walle-transfer-{}", std::process::id()));
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    let src = dir.join("src.bin");
+    let dst = dir.join("dst.bin");
+    tokio::fs::write(&src, &content).await.unwrap();
+    let small = TransferConfig::default();
+    let fragmented = TransferConfig {
+        threshold: 4,
+        fragment_size: 3,
+        concurrency: 2,
+    };
+    let progress = Arc::new(Mutex::new(vec![]));
+    let record = |p: Progress| progress.lock().unwrap().push(p.transferred);
+
+    let id = upload_file_from_path::<Event, _, _>(&ob, &selft, &src, &small, record)
+        .await
+        .unwrap();
+    assert_eq!(id.file_id, "single");
+    assert_eq!(std::mem::take(&mut *progress.lock().unwrap()), vec![10]);
+
+    // 分片上传，进度按分片递增
+    let id = upload_file_from_path::<Event, _, _>(&ob, &selft, &src, &fragmented, record)
+        .await
+        .unwrap();
+    assert_eq!(id.file_id, "fragmented");
+    assert_eq!(*stored.lock().unwrap(), content);
+    // 分片并发完成，中间进度取决于完成顺序
+    let mut steps = std::mem::take(&mut *progress.lock().unwrap());
+    steps.sort_unstable();
+    assert_eq!(steps.len(), 4);
+    assert!(steps.windows(2).all(|w| w[0] < w[1]));
+    assert_eq!(steps.last(), Some(&10));
+
+    // 小文件通过 get_file 单次下载
+    download_file_to_path::<Event, _, _>(&ob, &selft, "fragmented", &dst, &small, record)
+        .await
+        .unwrap();
+    assert_eq!(tokio::fs::read(&dst).await.unwrap(), content);
+    assert_eq!(ah.called("get_file").len(), 1);
+    assert_eq!(std::mem::take(&mut *progress.lock().unwrap()), vec![10]);
+
+    tokio::fs::remove_file(&dst).await.unwrap();
+    download_file_to_path::<Event, _, _>(&ob, &selft, "fragmented", &dst, &fragmented, record)
+        .await
+        .unwrap();
+    assert_eq!(tokio::fs::read(&dst).await.unwrap(), content);
+    assert_eq!(ah.called("get_file").len(), 1);
+    assert_eq!(progress.lock().unwrap().iter().max(), Some(&10));
+
+    // sha256 不符时返回错误
+    for config in [&small, &fragmented] {
+        assert!(matches!(
+            download_file_to_path::<Event, _, _>(&ob, &selft, "broken", &dst, config, |_| {}).await,
+            Err(WalleError::RespError(e)) if e.message.contains("sha256 mismatch")
+        ));
+    }
+    tokio::fs::remove_dir_all(&dir).await.ok();
+}
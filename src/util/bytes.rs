@@ -89,7 +89,7 @@ fn sertest() {
 fn detest() {
     let bytes = OneBotBytes(vec![0, 1, 2, 3]);
     let json = "\"AAECAw==\"";
-    assert_eq!(bytes, serde_json::from_str::<OneBotBytes>(&json).unwrap());
+    assert_eq!(bytes, serde_json::from_str::<OneBotBytes>(json).unwrap());
     let msgpack = vec![196, 4, 0, 1, 2, 3];
     assert_eq!(
         bytes,
@@ -28,7 +28,7 @@ pub fn timestamp_nano_f64() -> f64 {
 }
 
 /// 从纳秒时间戳生成 uuid
-#[cfg(feature = "impl-obc")]
+#[cfg(any(feature = "impl-obc", feature = "app-obc", feature = "file-store"))]
 pub fn new_uuid() -> String {
     uuid::Uuid::from_u128(timestamp_nano()).to_string()
 }
@@ -113,6 +113,7 @@ impl std::fmt::Display for ContentType {
 }
 
 #[doc(hidden)]
+#[cfg(any(feature = "websocket", feature = "http"))]
 pub(crate) trait AuthReqHeaderExt {
     fn header_auth_token(self, token: &Option<String>) -> Self;
 }
@@ -0,0 +1,192 @@
+use serde::{Deserialize, Serialize};
+
+use super::message::{message_from_v12, message_to_v12};
+use super::{ids_from_v12, ids_to_v12};
+use crate::util::{timestamp_nano_f64, Value, ValueMap, ValueMapExt};
+
+/// OneBot 11 标准动作
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Action {
+    pub action: String,
+    #[serde(default)]
+    pub params: ValueMap,
+}
+
+/// OneBot 11 与 OneBot 12 动作名称对应关系
+const ACTIONS: &[(&str, &str)] = &[
+    ("send_msg", "send_message"),
+    ("delete_msg", "delete_message"),
+    ("get_login_info", "get_self_info"),
+    ("get_stranger_info", "get_user_info"),
+    ("get_version_info", "get_version"),
+    ("set_group_leave", "leave_group"),
+];
+
+/// OneBot 11 与 OneBot 12 同名的标准动作
+const SAME_ACTIONS: &[&str] = &[
+    "get_status",
+    "get_friend_list",
+    "get_group_info",
+    "get_group_list",
+    "get_group_member_info",
+    "get_group_member_list",
+    "set_group_name",
+];
+
+fn map_ids(params: ValueMap, f: fn(&mut Value)) -> ValueMap {
+    let mut params = Value::Map(params);
+    f(&mut params);
+    match params {
+        Value::Map(params) => params,
+        _ => unreachable!(),
+    }
+}
+
+impl Action {
+    /// 转换为 OneBot 12 动作
+    ///
+    /// 非标准动作将以 `{platform}.{action}` 作为扩展动作名称
+    pub fn to_v12(self, platform: &str) -> crate::action::Action {
+        let Action { action, params } = self;
+        let mut params = map_ids(params, ids_to_v12);
+        let action = match action.as_str() {
+            "send_msg" | "send_private_msg" | "send_group_msg" => {
+                let detail_type = match params.remove_downcast::<String>("message_type") {
+                    Ok(ty) => ty,
+                    Err(_) if action == "send_group_msg" || params.contains_key("group_id") => {
+                        "group".to_owned()
+                    }
+                    Err(_) => "private".to_owned(),
+                };
+                params.insert("detail_type".to_owned(), detail_type.into());
+                if let Some(message) = params.remove("message") {
+                    let message = message_to_v12(message, platform).unwrap_or_default();
+                    params.insert("message".to_owned(), message.into());
+                }
+                "send_message".to_owned()
+            }
+            action => match ACTIONS.iter().find(|(v11, _)| *v11 == action) {
+                Some((_, v12)) => v12.to_string(),
+                None if SAME_ACTIONS.contains(&action) => action.to_owned(),
+                None => format!("{}.{}", platform, action),
+            },
+        };
+        crate::action::Action {
+            action,
+            params,
+            selft: None,
+        }
+    }
+}
+
+impl From<crate::action::Action> for Action {
+    fn from(action: crate::action::Action) -> Self {
+        let crate::action::Action { action, params, .. } = action;
+        let mut params = map_ids(params, ids_from_v12);
+        if action == "send_message" {
+            if let Some(ty) = params.remove("detail_type") {
+                params.insert("message_type".to_owned(), ty);
+            }
+            if let Ok(message) = params.remove_downcast("message") {
+                params.insert("message".to_owned(), message_from_v12(message).into());
+            }
+        }
+        let action = match ACTIONS.iter().find(|(_, v12)| *v12 == action) {
+            Some((v11, _)) => v11.to_string(),
+            None => action
+                .split_once('.')
+                .map(|(_, action)| action.to_owned())
+                .unwrap_or(action),
+        };
+        Action { action, params }
+    }
+}
+
+/// OneBot 11 标准响应
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Resp {
+    pub status: String,
+    pub retcode: i64,
+    #[serde(default = "null")]
+    pub data: Value,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub wording: String,
+}
+
+fn null() -> Value {
+    Value::Null
+}
+
+/// OneBot 11 与 OneBot 12 返回码对应关系
+const RETCODES: &[(i64, u32)] = &[(1400, 10001), (1404, 10002), (100, 10003)];
+
+impl Resp {
+    /// 转换为 OneBot 12 响应
+    ///
+    /// 为 `message_id` 补充 `time` 字段，为 `nickname` 补充 `user_name` 字段
+    pub fn to_v12(self) -> crate::resp::Resp {
+        let Resp {
+            status,
+            retcode,
+            mut data,
+            msg,
+            wording,
+        } = self;
+        ids_to_v12(&mut data);
+        if let Value::Map(map) = &mut data {
+            if map.contains_key("message_id") && !map.contains_key("time") {
+                map.insert("time".to_owned(), timestamp_nano_f64().into());
+            }
+            if let Some(nickname) = map.get("nickname").cloned() {
+                map.entry("user_name".to_owned()).or_insert(nickname);
+                map.entry("user_displayname".to_owned())
+                    .or_insert_with(|| "".into());
+            }
+        }
+        let retcode = match retcode {
+            // 1 为异步执行，视为成功
+            0 | 1 => 0,
+            retcode => RETCODES
+                .iter()
+                .find(|(v11, _)| *v11 == retcode)
+                .map(|(_, v12)| *v12)
+                .unwrap_or(20002),
+        };
+        crate::resp::Resp {
+            status: if retcode == 0 { "ok" } else { status.as_str() }.to_owned(),
+            retcode,
+            data,
+            message: if wording.is_empty() { msg } else { wording },
+        }
+    }
+}
+
+impl From<crate::resp::Resp> for Resp {
+    fn from(resp: crate::resp::Resp) -> Self {
+        let crate::resp::Resp {
+            status,
+            retcode,
+            mut data,
+            message,
+        } = resp;
+        ids_from_v12(&mut data);
+        if let Value::Map(map) = &mut data {
+            if let Some(user_name) = map.get("user_name").cloned() {
+                map.entry("nickname".to_owned()).or_insert(user_name);
+            }
+        }
+        Resp {
+            status,
+            retcode: RETCODES
+                .iter()
+                .find(|(_, v12)| *v12 == retcode)
+                .map(|(v11, _)| *v11)
+                .unwrap_or(retcode as i64),
+            data,
+            msg: message.clone(),
+            wording: message,
+        }
+    }
+}
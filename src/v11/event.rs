@@ -0,0 +1,231 @@
+use serde::{Deserialize, Serialize};
+
+use super::message::{message_from_v12, message_to_v12, serialize_cq};
+use super::{ids_from_v12, ids_to_v12};
+use crate::structs::{Bot, Selft, Status};
+use crate::util::{timestamp_nano, Value, ValueMap, ValueMapExt};
+use crate::{value_map, WalleError, WalleResult};
+
+/// OneBot 11 标准事件
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    pub time: i64,
+    pub self_id: i64,
+    pub post_type: String,
+    #[serde(flatten)]
+    pub extra: ValueMap,
+}
+
+/// OneBot 11 与 OneBot 12 `notice` 事件类型对应关系
+const NOTICE_TYPES: &[(&str, &str)] = &[
+    ("friend_add", "friend_increase"),
+    ("group_increase", "group_member_increase"),
+    ("group_decrease", "group_member_decrease"),
+    ("group_recall", "group_message_delete"),
+    ("friend_recall", "private_message_delete"),
+];
+
+fn take_str(extra: &mut ValueMap, key: &str) -> String {
+    extra.remove_downcast::<String>(key).unwrap_or_default()
+}
+
+impl Event {
+    /// 转换为 OneBot 12 事件
+    pub fn to_v12(self, platform: &str) -> WalleResult<crate::event::Event> {
+        let Event {
+            time,
+            self_id,
+            post_type,
+            mut extra,
+        } = self;
+        let selft = Selft {
+            platform: platform.to_owned(),
+            user_id: self_id.to_string(),
+        };
+        let mut sub_type = take_str(&mut extra, "sub_type");
+        let (ty, detail_type) = match post_type.as_str() {
+            "message" | "message_sent" => {
+                let message =
+                    message_to_v12(extra.remove("message").unwrap_or(Value::Null), platform)?;
+                extra.remove("raw_message");
+                extra.remove("font");
+                extra.insert(
+                    "alt_message".to_owned(),
+                    crate::segment::alt(&message).into(),
+                );
+                extra.insert("message".to_owned(), message.into());
+                ("message", take_str(&mut extra, "message_type"))
+            }
+            "notice" => {
+                let notice_type = take_str(&mut extra, "notice_type");
+                let detail_type = match NOTICE_TYPES.iter().find(|(v11, _)| *v11 == notice_type) {
+                    Some((_, v12)) => v12.to_string(),
+                    None => format!("{}.{}", platform, notice_type),
+                };
+                if sub_type == "kick_me" {
+                    sub_type = "kick".to_owned();
+                }
+                ("notice", detail_type)
+            }
+            "request" => (
+                "request",
+                format!("{}.{}", platform, take_str(&mut extra, "request_type")),
+            ),
+            "meta_event" => {
+                let online = sub_type != "disable";
+                let bots = |online: bool| {
+                    vec![Bot {
+                        selft: selft.clone(),
+                        online,
+                    }]
+                };
+                let detail_type = match take_str(&mut extra, "meta_event_type").as_str() {
+                    "lifecycle" => {
+                        sub_type = String::default();
+                        extra.insert(
+                            "status".to_owned(),
+                            Status {
+                                good: online,
+                                bots: bots(online),
+                            }
+                            .into(),
+                        );
+                        "status_update".to_owned()
+                    }
+                    "heartbeat" => {
+                        let status = extra
+                            .remove("status")
+                            .and_then(|status| status.downcast_map().ok())
+                            .unwrap_or_default();
+                        let get = |key| status.get_downcast::<bool>(key).unwrap_or(true);
+                        let interval = extra.remove_downcast::<i64>("interval").unwrap_or_default();
+                        extra.insert("interval".to_owned(), interval.into());
+                        extra.insert(
+                            "status".to_owned(),
+                            Status {
+                                good: get("good"),
+                                bots: bots(get("online")),
+                            }
+                            .into(),
+                        );
+                        "heartbeat".to_owned()
+                    }
+                    other => format!("{}.{}", platform, other),
+                };
+                ("meta", detail_type)
+            }
+            _ => return Err(WalleError::DeclareNotMatch("post_type", post_type)),
+        };
+        let mut extra = Value::Map(extra);
+        ids_to_v12(&mut extra);
+        let mut extra = extra.downcast_map()?;
+        if ty != "meta" {
+            extra.insert("self".to_owned(), selft.into());
+        }
+        Ok(crate::event::Event {
+            id: format!("{}-{}", self_id, timestamp_nano()),
+            time: time as f64,
+            ty: ty.to_owned(),
+            detail_type,
+            sub_type,
+            extra,
+        })
+    }
+}
+
+impl TryFrom<crate::event::Event> for Event {
+    type Error = WalleError;
+    fn try_from(event: crate::event::Event) -> Result<Self, Self::Error> {
+        let crate::event::Event {
+            time,
+            ty,
+            detail_type,
+            mut sub_type,
+            mut extra,
+            ..
+        } = event;
+        // self 与 status 中的 user_id 在 OneBot 11 中没有对应，需要在转换 id 前取出
+        let selft = extra.remove_downcast::<Selft>("self");
+        let status = extra.remove_downcast::<Status>("status").ok();
+        let mut extra = Value::Map(extra);
+        ids_from_v12(&mut extra);
+        let mut extra = extra.downcast_map()?;
+        let self_id = match selft {
+            Ok(selft) => selft
+                .user_id
+                .parse()
+                .map_err(|_| WalleError::ValueTypeNotMatch("i64".to_owned(), selft.user_id))?,
+            Err(_) => 0,
+        };
+        let strip = |ty: &str| {
+            ty.split_once('.')
+                .map(|(_, ty)| ty)
+                .unwrap_or(ty)
+                .to_owned()
+        };
+        let post_type = match ty.as_str() {
+            "message" => {
+                let message = message_from_v12(extra.remove_downcast("message")?);
+                extra.remove("alt_message");
+                extra.insert("raw_message".to_owned(), serialize_cq(&message).into());
+                extra.insert("message".to_owned(), message.into());
+                extra.insert("message_type".to_owned(), detail_type.into());
+                "message"
+            }
+            "notice" => {
+                let notice_type = match NOTICE_TYPES.iter().find(|(_, v12)| *v12 == detail_type) {
+                    Some((v11, _)) => v11.to_string(),
+                    None => strip(&detail_type),
+                };
+                extra.insert("notice_type".to_owned(), notice_type.into());
+                "notice"
+            }
+            "request" => {
+                extra.insert("request_type".to_owned(), strip(&detail_type).into());
+                "request"
+            }
+            "meta" => {
+                let meta_event_type = match detail_type.as_str() {
+                    "connect" => {
+                        extra.remove("version");
+                        sub_type = "connect".to_owned();
+                        "lifecycle".to_owned()
+                    }
+                    "status_update" => {
+                        let good = status.map(|s| s.good).unwrap_or(true);
+                        sub_type = if good { "enable" } else { "disable" }.to_owned();
+                        "lifecycle".to_owned()
+                    }
+                    "heartbeat" => {
+                        let status = status.unwrap_or(Status {
+                            good: true,
+                            bots: vec![],
+                        });
+                        extra.insert(
+                            "status".to_owned(),
+                            value_map! {
+                                "good": status.good,
+                                "online": status.bots.iter().any(|bot| bot.online)
+                            }
+                            .into(),
+                        );
+                        "heartbeat".to_owned()
+                    }
+                    other => strip(other),
+                };
+                extra.insert("meta_event_type".to_owned(), meta_event_type.into());
+                "meta_event"
+            }
+            _ => return Err(WalleError::DeclareNotMatch("type", ty)),
+        };
+        if !sub_type.is_empty() {
+            extra.insert("sub_type".to_owned(), sub_type.into());
+        }
+        Ok(Event {
+            time: time as i64,
+            self_id,
+            post_type: post_type.to_owned(),
+            extra,
+        })
+    }
+}
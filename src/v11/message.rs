@@ -0,0 +1,48 @@
+//! OneBot 11 消息
+
+use crate::segment::{cq, MsgSegment, Segments};
+use crate::util::Value;
+use crate::WalleResult;
+
+/// 将 OneBot 11 消息段转换为 OneBot 12 消息段
+///
+/// 非标准消息段类型将以 `{platform}.{type}` 作为扩展类型
+pub fn segment_to_v12(segment: MsgSegment, platform: &str) -> MsgSegment {
+    cq::from_cq_segment(segment, Some(platform))
+}
+
+/// 将 OneBot 12 消息段转换为 OneBot 11 消息段
+///
+/// 带有平台前缀的扩展消息段类型将去除前缀
+pub fn segment_from_v12(segment: MsgSegment) -> MsgSegment {
+    cq::to_cq_segment(segment)
+}
+
+/// 将 OneBot 11 消息（CQ 码字符串或消息段数组）转换为 OneBot 12 消息
+pub fn message_to_v12(message: Value, platform: &str) -> WalleResult<Segments> {
+    let segments = match message {
+        Value::Str(s) => cq::parse(&s),
+        message => message.downcast()?,
+    };
+    Ok(segments
+        .into_iter()
+        .map(|seg| segment_to_v12(seg, platform))
+        .collect())
+}
+
+/// 将 OneBot 12 消息转换为 OneBot 11 消息段数组
+pub fn message_from_v12(message: Segments) -> Vec<MsgSegment> {
+    message.into_iter().map(segment_from_v12).collect()
+}
+
+/// 解析 CQ 码字符串为 OneBot 11 消息段，参数值均为字符串
+pub fn parse_cq(s: &str) -> Segments {
+    cq::parse(s)
+}
+
+/// 将 OneBot 11 消息段序列化为 CQ 码字符串，不做消息段类型转换
+///
+/// 转换 OneBot 12 消息使用 [`crate::segment::to_cq`]
+pub fn serialize_cq(segments: &[MsgSegment]) -> String {
+    cq::serialize(segments)
+}
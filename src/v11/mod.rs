@@ -0,0 +1,222 @@
+//! OneBot 11 兼容层
+//!
+//! 提供 OneBot 11 的 Event、Action 与 Resp 模型，以及与 OneBot 12 模型的双向转换：
+//!
+//! - CQ 码字符串或消息段数组与 OneBot 12 `Segments` 互转
+//! - 数字 id 与字符串 id 互转
+//! - 事件与动作名称按标准对应，非标准的事件与动作以 `{platform}.{name}` 作为扩展名称
+//!
+//! 应用端 WebSocket 连接（正向与反向）可以通过 `onebot_version` 设置项使用 OneBot 11 协议，
+//! 用于连接 go-cqhttp 等 OneBot 11 实现端。应用端 HTTP、Webhook 与实现端的所有传输
+//! 仅支持 OneBot 12。
+
+use crate::util::Value;
+
+mod action;
+mod event;
+mod message;
+
+pub use action::{Action, Resp};
+pub use event::Event;
+pub use message::*;
+
+/// OneBot 11 协议未携带平台信息，默认使用该平台名称
+pub const DEFAULT_PLATFORM: &str = "qq";
+
+fn is_id(key: &str) -> bool {
+    key == "id" || key.ends_with("_id")
+}
+
+/// OneBot 11 中为数字的 id 字段，其他 id 字段（如 `file_id`）保持字符串
+const NUMERIC_IDS: &[&str] = &[
+    "user_id",
+    "group_id",
+    "message_id",
+    "self_id",
+    "operator_id",
+    "target_id",
+];
+
+/// 将所有 `*_id` 字段的数字转换为字符串
+pub(crate) fn ids_to_v12(value: &mut Value) {
+    match value {
+        Value::Map(map) => map.iter_mut().for_each(|(k, v)| match v {
+            Value::Int(i) if is_id(k) => *v = Value::Str(i.to_string()),
+            v => ids_to_v12(v),
+        }),
+        Value::List(list) => list.iter_mut().for_each(ids_to_v12),
+        _ => {}
+    }
+}
+
+/// 将 [`NUMERIC_IDS`] 字段的数字字符串转换为数字
+pub(crate) fn ids_from_v12(value: &mut Value) {
+    match value {
+        Value::Map(map) => map.iter_mut().for_each(|(k, v)| match v {
+            Value::Str(s) if NUMERIC_IDS.contains(&k.as_str()) => {
+                if let Ok(i) = s.parse::<i64>() {
+                    *v = Value::Int(i)
+                }
+            }
+            v => ids_from_v12(v),
+        }),
+        Value::List(list) => list.iter_mut().for_each(ids_from_v12),
+        _ => {}
+    }
+}
+
+#[cfg(feature = "websocket")]
+pub(crate) mod ws {
+    use super::{Action, Event, Resp, DEFAULT_PLATFORM};
+    use crate::util::{Echo, ProtocolItem, Value};
+    use crate::{WalleError, WalleResult};
+
+    /// 将 OneBot 11 实现端推送的事件或响应转换为 OneBot 12 json
+    pub(crate) fn recv_to_v12(text: &str) -> WalleResult<String> {
+        let value: Value =
+            serde_json::from_str(text).map_err(|e| WalleError::Other(e.to_string()))?;
+        let is_event = matches!(&value, Value::Map(map) if map.contains_key("post_type"));
+        let text = serde_json::to_string(&value).map_err(|e| WalleError::Other(e.to_string()))?;
+        if is_event {
+            Event::json_decode(&text)
+                .map_err(WalleError::Other)?
+                .to_v12(DEFAULT_PLATFORM)
+                .map(|e| e.json_encode())
+        } else {
+            let (resp, echo) = Echo::<Resp>::json_decode(&text)
+                .map_err(WalleError::Other)?
+                .unpack();
+            Ok(echo.pack(resp.to_v12()).json_encode())
+        }
+    }
+
+    /// 将应用端发送的 OneBot 12 动作转换为 OneBot 11 json
+    pub(crate) fn action_from_v12<A: ProtocolItem>(action: &Echo<A>) -> WalleResult<String> {
+        let (action, echo) = Echo::<crate::action::Action>::json_decode(&action.json_encode())
+            .map_err(WalleError::Other)?
+            .unpack();
+        Ok(echo.pack(Action::from(action)).json_encode())
+    }
+}
+
+#[test]
+fn v11_test() {
+    use crate::segment::MsgSegment;
+    use crate::util::ValueMapExt;
+    use crate::value_map;
+
+    let cq = "hi&#91;[CQ:at,qq=10001][CQ:face,id=1]&amp;[CQ:image,file=a.jpg,url=http://a&#44;b]";
+    let segments = parse_cq(cq);
+    assert_eq!(segments.len(), 5);
+    assert_eq!(
+        segments[0].data.get_downcast::<String>("text").unwrap(),
+        "hi["
+    );
+    assert_eq!(serialize_cq(&segments), cq);
+
+    let message = message_to_v12(Value::Str(cq.to_owned()), DEFAULT_PLATFORM).unwrap();
+    assert_eq!(message[1].ty, "mention");
+    assert_eq!(message[2].ty, "qq.face");
+    assert_eq!(
+        message[4].data.get_downcast::<String>("file_id").unwrap(),
+        "a.jpg"
+    );
+    assert_eq!(serialize_cq(&message_from_v12(message)), cq);
+
+    let event: Event = serde_json::from_str(
+        r#"{"time":1,"self_id":10000,"post_type":"message","message_type":"group","sub_type":"normal",
+        "message_id":42,"group_id":123,"user_id":10001,"message":"[CQ:reply,id=41]hello",
+        "raw_message":"[CQ:reply,id=41]hello","font":0,"sender":{"user_id":10001,"nickname":"a"}}"#,
+    )
+    .unwrap();
+    let v12 = event.to_v12(DEFAULT_PLATFORM).unwrap();
+    assert_eq!(
+        (v12.ty.as_str(), v12.detail_type.as_str()),
+        ("message", "group")
+    );
+    assert_eq!(v12.selft().unwrap().user_id, "10000");
+    assert_eq!(v12.extra.get_downcast::<String>("group_id").unwrap(), "123");
+    assert_eq!(
+        v12.extra.get_downcast::<String>("alt_message").unwrap(),
+        "[reply,\"message_id\":\"41\"]hello"
+    );
+    let event = Event::try_from(v12).unwrap();
+    assert_eq!(event.self_id, 10000);
+    assert_eq!(event.extra.get_downcast::<i64>("message_id").unwrap(), 42);
+    assert_eq!(
+        event.extra.get_downcast::<String>("raw_message").unwrap(),
+        "[CQ:reply,id=41]hello"
+    );
+
+    let notice: Event = serde_json::from_str(
+        r#"{"time":1,"self_id":10000,"post_type":"notice","notice_type":"group_recall",
+        "group_id":123,"user_id":10001,"operator_id":10001,"message_id":42}"#,
+    )
+    .unwrap();
+    let v12 = notice.to_v12(DEFAULT_PLATFORM).unwrap();
+    assert_eq!(v12.detail_type, "group_message_delete");
+    assert_eq!(
+        Event::try_from(v12)
+            .unwrap()
+            .extra
+            .get_downcast::<String>("notice_type")
+            .unwrap(),
+        "group_recall"
+    );
+
+    let heartbeat: Event = serde_json::from_str(
+        r#"{"time":1,"self_id":10000,"post_type":"meta_event","meta_event_type":"heartbeat",
+        "interval":5000,"status":{"online":true,"good":true}}"#,
+    )
+    .unwrap();
+    let v12 = heartbeat.to_v12(DEFAULT_PLATFORM).unwrap();
+    assert_eq!(v12.detail_type, "heartbeat");
+    let status: crate::structs::Status = v12.extra.get_downcast("status").unwrap();
+    assert_eq!(status.bots[0].selft.user_id, "10000");
+
+    let action = crate::action::Action {
+        action: "send_message".to_owned(),
+        params: value_map! {
+            "detail_type": "private",
+            "user_id": "10001",
+            "message": [{"type": "mention_all", "data": {}}]
+        },
+        selft: None,
+    };
+    let v11 = Action::from(action.clone());
+    assert_eq!(v11.action, "send_msg");
+    assert_eq!(v11.params.get_downcast::<i64>("user_id").unwrap(), 10001);
+    assert_eq!(
+        v11.params
+            .get_downcast::<Vec<MsgSegment>>("message")
+            .unwrap()[0]
+            .data,
+        value_map! { "qq": "all" }
+    );
+    assert_eq!(v11.to_v12(DEFAULT_PLATFORM), action);
+
+    // 非数字 id 字段保持字符串
+    let v11 = Action::from(crate::action::Action {
+        action: "get_file".to_owned(),
+        params: value_map! { "file_id": "123", "group_id": "456" },
+        selft: None,
+    });
+    assert_eq!(v11.params.get_downcast::<String>("file_id").unwrap(), "123");
+    assert_eq!(v11.params.get_downcast::<i64>("group_id").unwrap(), 456);
+
+    let resp: Resp = serde_json::from_str(
+        r#"{"status":"ok","retcode":0,"data":{"user_id":10000,"nickname":"bot"}}"#,
+    )
+    .unwrap();
+    let v12 = resp.to_v12();
+    assert_eq!(v12.retcode, 0);
+    let data = v12.data.clone().downcast_map().unwrap();
+    assert_eq!(data.get_downcast::<String>("user_id").unwrap(), "10000");
+    assert_eq!(data.get_downcast::<String>("user_name").unwrap(), "bot");
+    let failed: Resp =
+        serde_json::from_str(r#"{"status":"failed","retcode":100,"data":null,"wording":"bad"}"#)
+            .unwrap();
+    let v12 = failed.to_v12();
+    assert_eq!((v12.retcode, v12.message.as_str()), (10003, "bad"));
+    assert_eq!(Resp::from(v12).retcode, 100);
+}
@@ -182,3 +182,44 @@ fn meta_parse(
     }
     Ok(())
 }
+
+pub(crate) fn event_kind_internal(input: DeriveInput, span: TokenStream2) -> Result<TokenStream2> {
+    let name = input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let fields = match input.data {
+        Data::Struct(data) => match data.fields {
+            Fields::Named(named) => named
+                .named
+                .into_iter()
+                .filter_map(|f| f.ident)
+                .map(|i| i.to_string())
+                .collect::<Vec<_>>(),
+            _ => return Err(error("only support named struct")),
+        },
+        _ => return Err(error("only support struct")),
+    };
+    for field in ["ty", "detail_type"] {
+        if !fields.iter().any(|f| f == field) {
+            return Err(error(format!("miss field {}", field)));
+        }
+    }
+    // 没有 id 字段时使用 trait 的默认实现
+    let id = fields.iter().any(|f| f == "id").then(|| {
+        quote!(
+            fn id(&self) -> &str {
+                &self.id
+            }
+        )
+    });
+    Ok(quote!(
+        impl #impl_generics #span::event::EventKind for #name #ty_generics #where_clause {
+            #id
+            fn ty(&self) -> &str {
+                &self.ty
+            }
+            fn detail_type(&self) -> &str {
+                &self.detail_type
+            }
+        }
+    ))
+}
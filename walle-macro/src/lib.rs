@@ -19,7 +19,7 @@ fn snake_case(s: String) -> String {
     let mut out = String::default();
     let mut chars = s.chars();
     out.push(chars.next().unwrap().to_ascii_lowercase());
-    while let Some(c) = chars.next() {
+    for c in chars {
         if c.is_ascii_uppercase() {
             out.push('_');
             out.push(c.to_ascii_lowercase());
@@ -74,6 +74,11 @@ use event::try_from_event_internal;
 ob!(TryFromEvent: event => try_from_event, try_from_event_internal, walle_core);
 ob!(_TryFromEvent: event => _try_from_event, try_from_event_internal, crate);
 
+use event::event_kind_internal;
+
+ob!(EventKind => event_kind, event_kind_internal, walle_core);
+ob!(_EventKind => _event_kind, event_kind_internal, crate);
+
 use action_segment::to_action_internal;
 
 ob!(ToAction: action => to_action, to_action_internal, walle_core);
@@ -95,13 +100,13 @@ ob!(TryFromMsgSegment: msg_segment => try_from_msg_segment, try_from_msg_segment
 ob!(_TryFromMsgSegment: msg_segment => _try_from_msg_segment, try_from_msg_segment_internal, crate);
 
 /// From
-/// ```rust
+/// ```text
 /// pub a: i32,         // Field
 /// pub b: Option<i32>, // Opetion Field
 /// (i32)               // Unnamed Field
 /// ```
 /// to
-/// ```rust
+/// ```text
 /// a: map.remove_downcast("a")
 /// b: map.try_remove_downcast("b")
 /// i32::try_from(map)